@@ -0,0 +1,197 @@
+//! Command line options
+
+use std::{path::PathBuf, str::FromStr};
+use structopt::StructOpt;
+
+#[derive(StructOpt, Debug)]
+#[structopt(name = "exifweb", about = "Render a blog from folders of photos")]
+pub struct Options {
+    /// Blog root directory
+    #[structopt(
+        short,
+        long,
+        default_value = ".",
+        global = true,
+        parse(from_os_str)
+    )]
+    pub dir: PathBuf,
+
+    #[structopt(subcommand)]
+    pub command: Command,
+}
+
+#[derive(StructOpt, Debug, PartialEq)]
+pub enum Command {
+    /// Render posts and pages that have changed
+    Render {
+        /// Keep running and render again whenever files change
+        #[structopt(long)]
+        auto: bool,
+
+        /// Report what would be rendered, downloaded and resized without
+        /// writing anything
+        #[structopt(long, conflicts_with = "auto")]
+        dry_run: bool,
+
+        #[structopt(flatten)]
+        force: Force,
+    },
+    /// Create post configuration file
+    Init,
+    /// Validate blog, series and post configuration without rendering
+    Check,
+    /// Remove render logs and photo caches so the next render is complete
+    Clean {
+        /// Also remove resized photos
+        #[structopt(long)]
+        photos: bool,
+    },
+    /// Serve rendered files, render changes and reload open pages
+    Serve {
+        /// Port to listen on
+        #[structopt(short, long, default_value = "3000")]
+        port: u16,
+
+        #[structopt(flatten)]
+        force: Force,
+    },
+    /// Summarize posts, photos, categories and tags
+    Stats,
+}
+
+#[derive(StructOpt, Debug, PartialEq)]
+pub struct Force {
+    /// Comma-delimited list of what to reprocess even if unchanged:
+    /// posts (all post HTML and basic root pages), maps (map images for post
+    /// and category pages), photos (all resized photos) or tags (all photo tag
+    /// pages)
+    #[structopt(
+        long = "force",
+        use_delimiter = true,
+        possible_values = &Reprocess::NAMES
+    )]
+    list: Vec<Reprocess>,
+}
+
+impl Force {
+    pub fn overrides(&self) -> Override {
+        let mut o = Override::default();
+
+        for r in self.list.iter() {
+            match r {
+                Reprocess::Posts => o.posts = true,
+                Reprocess::Maps => o.maps = true,
+                Reprocess::Photos => o.photos = true,
+                Reprocess::Tags => o.tags = true,
+            }
+        }
+        o
+    }
+}
+
+/// What can be forced to reprocess
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Reprocess {
+    Posts,
+    Maps,
+    Photos,
+    Tags,
+}
+
+impl Reprocess {
+    const NAMES: [&'static str; 4] = ["posts", "maps", "photos", "tags"];
+}
+
+impl FromStr for Reprocess {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "posts" => Ok(Reprocess::Posts),
+            "maps" => Ok(Reprocess::Maps),
+            "photos" => Ok(Reprocess::Photos),
+            "tags" => Ok(Reprocess::Tags),
+            _ => Err(format!("Cannot force {}", s)),
+        }
+    }
+}
+
+/// Override normal rendering behavior which is to only reprocess when changes
+/// are detected
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct Override {
+    pub posts: bool,
+    pub maps: bool,
+    pub photos: bool,
+    pub tags: bool,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Command, Options, Override};
+    use std::path::PathBuf;
+    use structopt::StructOpt;
+
+    fn parse(args: &[&str]) -> Options {
+        Options::from_iter_safe(args).unwrap()
+    }
+
+    #[test]
+    fn render_test() {
+        let options = parse(&[
+            "exifweb",
+            "-d=./some/path",
+            "render",
+            "--force=posts,tags",
+        ]);
+
+        assert_eq!(options.dir, PathBuf::from("./some/path"));
+
+        match options.command {
+            Command::Render {
+                auto,
+                dry_run,
+                force,
+            } => {
+                assert!(!auto);
+                assert!(!dry_run);
+                assert_eq!(
+                    force.overrides(),
+                    Override {
+                        posts: true,
+                        tags: true,
+                        ..Override::default()
+                    }
+                );
+            }
+            _ => panic!("Expected render command"),
+        }
+    }
+
+    #[test]
+    fn global_dir_test() {
+        let options =
+            parse(&["exifweb", "serve", "--dir", "./blog", "-p=8080"]);
+
+        assert_eq!(options.dir, PathBuf::from("./blog"));
+        assert!(matches!(options.command, Command::Serve { port: 8080, .. }));
+
+        assert_eq!(parse(&["exifweb", "stats"]).dir, PathBuf::from("."));
+    }
+
+    #[test]
+    fn invalid_test() {
+        let invalid: &[&[&str]] = &[
+            &["exifweb"],
+            &["exifweb", "publish"],
+            &["exifweb", "render", "--force=everything"],
+            &["exifweb", "render", "-auto"],
+            &["exifweb", "render", "--auto", "--dry-run"],
+            &["exifweb", "serve", "--port=http"],
+        ];
+
+        for args in invalid {
+            assert!(Options::from_iter_safe(*args).is_err(), "{:?}", args);
+        }
+    }
+}
@@ -5,7 +5,10 @@ use super::{
     vendors::{FacebookConfig, GoogleConfig, MapBoxConfig},
     ReadsEnv,
 };
-use crate::{deserialize::regex_string, models::Location, tools::Pairs};
+use crate::{
+    deserialize::regex_string, gps::Gazetteer, models::Location, tools::Pairs,
+};
+use colored::*;
 use regex::Regex;
 use serde::Deserialize;
 use std::path::Path;
@@ -35,6 +38,30 @@ pub struct SizeTypes {
     pub display: SizeConfig,
 }
 
+/// Tool used to read photo metadata
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum MetadataBackend {
+    /// Run `exiftool` for each post folder
+    ExifTool,
+    /// Run `magick convert` for each post folder
+    ImageMagick,
+    /// Read EXIF in-process (no external tool needed)
+    Native,
+}
+
+/// Tool used to create resized photos
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum ResizeBackend {
+    /// Run `cwebp` for each size
+    CWebP,
+    /// Run `magick convert` once for each photo
+    ImageMagick,
+    /// Decode, resize and encode in-process (no external tool needed)
+    Native,
+}
+
 #[derive(Deserialize, Debug)]
 pub struct PhotoConfig {
     /// Regex pattern to extract photo index and count from file name
@@ -50,11 +77,76 @@ pub struct PhotoConfig {
     /// Extension (*with* leading period) of source files from which published
     /// web files are rendered
     pub source_ext: String,
-    /// Extension (*with* leading period) applied to resized photos
+    /// Extension (*with* leading period) applied to resized photos. Since
+    /// this is the format shown by browsers and e-mail clients that support
+    /// nothing better, it should be widely supported, like `.jpg`.
     pub output_ext: String,
+    /// Extensions of additional formats, most preferred first, that resized
+    /// photos are also saved in and offered ahead of `output_ext`
+    ///
+    /// *Example* `[".avif", ".webp"]`
+    #[serde(default)]
+    pub output_formats: Vec<String>,
     /// Maximum edge size of source image. This may be used to determine if a
     /// resize is required for the largest photo.
     pub source_size: u16,
+    /// Tool used to read photo metadata. If not set then ExifTool is used when
+    /// installed, otherwise metadata are read in-process.
+    #[serde(default)]
+    pub metadata: Option<MetadataBackend>,
+    /// Tool used to create resized photos. If not set then `cwebp` is used
+    /// when installed, otherwise photos are resized in-process.
+    #[serde(default)]
+    pub resize: Option<ResizeBackend>,
+    /// Number of photo sizes created at once. If not set or `0` then one per
+    /// processor is used.
+    #[serde(default)]
+    pub resize_workers: usize,
+}
+
+impl PhotoConfig {
+    /// Output extension, if any, that's the same as the source extension so
+    /// resized photos would be mistaken for sources
+    pub fn source_conflict(&self) -> Option<&str> {
+        std::iter::once(&self.output_ext)
+            .chain(self.output_formats.iter())
+            .find(|ext| ext.eq_ignore_ascii_case(&self.source_ext))
+            .map(String::as_str)
+    }
+}
+
+#[cfg(test)]
+impl PhotoConfig {
+    /// Configuration matching photo file names in `src/fixtures`
+    pub fn fixture() -> Self {
+        toml::from_str(
+            r#"
+            capture_index = "(\\d{3})-of-(\\d{3})\\.jpg$"
+            remove_tags = []
+            source_ext = ".jpg"
+            output_ext = ".webp"
+            source_size = 2048
+
+            [size.render]
+            large = 2048
+            medium = 1024
+            small = 512
+            thumb = 256
+
+            [size.display]
+            large = 1024
+            medium = 512
+            small = 256
+            thumb = 128
+
+            [exif]
+            camera = []
+            software = []
+            lens = []
+            "#,
+        )
+        .unwrap()
+    }
 }
 
 #[derive(Deserialize, Debug)]
@@ -77,6 +169,7 @@ pub struct GpsPrivacy {
 }
 
 #[derive(Deserialize, Debug)]
+#[serde(default)]
 pub struct GpsTrackConfig {
     pub min_track_points: usize,
 
@@ -90,12 +183,89 @@ pub struct GpsTrackConfig {
 
     pub privacy: Option<GpsPrivacy>,
 
-    /// Whether track GPX files can be downloaded
+    /// Seconds to add to photo times so they match GPS time when geotagging,
+    /// such as `-120` for a camera clock two minutes fast
+    pub camera_offset_seconds: i64,
+
+    /// Photos taken further than this from the nearest track point aren't
+    /// geotagged
+    pub max_geotag_gap_seconds: u32,
+
+    /// Whether track GPX files can be downloaded. This is off unless enabled
+    /// since tracks show exactly where the author went.
     pub allow_download: bool,
     // Link patterns to external maps with `lat`, `lon`, `zoom` and `altitude`
     // tokens
 }
 
+impl Default for GpsTrackConfig {
+    fn default() -> Self {
+        GpsTrackConfig {
+            min_track_points: 5,
+            // about the accuracy of consumer GPS
+            max_point_deviation_feet: 15.0,
+            max_possible_speed_mph: 150.0,
+            privacy: None,
+            camera_offset_seconds: 0,
+            max_geotag_gap_seconds: 300,
+            allow_download: false,
+        }
+    }
+}
+
+/// Offline lookup of place names for photo locations
+#[derive(Deserialize, Debug)]
+#[serde(default)]
+pub struct PlaceConfig {
+    /// Path, relative to the root, of delimited place data such as a GeoNames
+    /// `cities500.txt` export with a header row added
+    pub file: Option<String>,
+
+    /// Places with fewer people are ignored
+    pub min_population: u64,
+
+    /// Photos further than this from any place aren't named
+    pub max_distance_miles: f32,
+
+    /// Whether to assign suggested "where" categories to posts that have none
+    /// rather than only reporting them
+    pub assign_where: bool,
+
+    /// Places loaded from `file`
+    #[serde(skip)]
+    pub gazetteer: Option<Gazetteer>,
+}
+
+impl Default for PlaceConfig {
+    fn default() -> Self {
+        PlaceConfig {
+            file: None,
+            min_population: 0,
+            max_distance_miles: 20.0,
+            assign_where: false,
+            gazetteer: None,
+        }
+    }
+}
+
+impl PlaceConfig {
+    /// Load place data if a file is configured, reporting rather than failing
+    /// if it can't be read
+    fn load(&mut self, root: &Path) {
+        if let Some(file) = &self.file {
+            match Gazetteer::load(&root.join(file), self.min_population) {
+                Ok(places) => self.gazetteer = Some(places),
+                Err(e) => println!(
+                    "   {} {} {}",
+                    "Failed to load places from".red(),
+                    file.red(),
+                    e
+                ),
+            }
+        }
+    }
+}
+
 /// Match category kind to material icon
 /// https://material.io/icons/
 #[derive(Deserialize, Debug)]
@@ -185,6 +355,10 @@ pub struct BlogConfig {
     #[serde(skip, default)]
     pub force: Overrides,
 
+    /// Whether to only report what would be rendered without writing files
+    #[serde(skip, default)]
+    pub dry_run: bool,
+
     /// Redirect source slug to target
     pub redirects: Option<Pairs>,
     pub site: SiteConfig,
@@ -192,15 +366,32 @@ pub struct BlogConfig {
     pub style: StyleConfig,
     pub category: CategoryConfig,
     pub photo: PhotoConfig,
+    /// How GPX tracks are cleaned and published
+    #[serde(default)]
+    pub track: GpsTrackConfig,
+    /// How photo locations are named
+    #[serde(default)]
+    pub places: PlaceConfig,
     pub facebook: FacebookConfig,
     pub mapbox: MapBoxConfig,
     pub google: GoogleConfig,
 }
 
 impl BlogConfig {
+    /// Load configuration from `path`, returning `None` if it's missing or
+    /// invalid
     pub fn load(path: &Path) -> Option<Self> {
         load_config::<Self>(path).and_then(|mut c| {
+            if let Some(ext) = c.photo.source_conflict() {
+                println!(
+                    "   {} {}",
+                    "Photo output format can't match source extension".red(),
+                    ext.red()
+                );
+                return None;
+            }
             c.from_env();
+            c.places.load(path);
             Some(c)
         })
     }
@@ -217,3 +408,22 @@ impl ReadsEnv for BlogConfig {
 fn default_post_alias() -> String {
     String::from("Post")
 }
+
+#[cfg(test)]
+mod tests {
+    use super::PhotoConfig;
+
+    #[test]
+    fn source_conflict_test() {
+        let mut config = PhotoConfig::fixture();
+
+        assert_eq!(config.source_conflict(), None);
+
+        config.output_formats = vec![".avif".to_owned(), ".JPG".to_owned()];
+        assert_eq!(config.source_conflict(), Some(".JPG"));
+
+        config.output_formats.clear();
+        config.output_ext = ".jpg".to_owned();
+        assert_eq!(config.source_conflict(), Some(".jpg"));
+    }
+}
@@ -0,0 +1,298 @@
+//! RON cache of photo metadata
+
+use super::{load_ron, GpsPrivacy, PhotoConfig};
+use crate::{
+    models::{Camera, Location, Photo, PhotoFile, SizeCollection},
+    tools::{write_result, FileStamp},
+};
+use chrono::{DateTime, FixedOffset};
+use ron::ser::{to_string_pretty, PrettyConfig};
+use serde::{Deserialize, Serialize};
+use sha1::{Digest, Sha1};
+use std::{collections::BTreeMap, path::Path};
+
+/// File that caches metadata read from each photo in a post folder
+pub static CACHE_FILE: &str = "photos.ron";
+
+/// Metadata read from each photo in a post folder, keyed by file name, so that
+/// only new or changed files need to be read again. Since the cache is written
+/// to the published post folder, locations within the privacy zone aren't
+/// cached.
+#[derive(Serialize, Deserialize, Debug, Default)]
+pub struct PhotoCache {
+    /// Digest of the privacy zone locations were hidden from, so a changed
+    /// zone invalidates the cache
+    #[serde(default)]
+    zone: Option<String>,
+    // use B-Tree so that keys are sorted
+    photos: BTreeMap<String, CachedPhoto>,
+}
+
+/// Photo fields that come from metadata, along with the size and digest of the
+/// file they were read from. Photo sizes aren't cached since they depend on
+/// configuration.
+#[derive(Serialize, Deserialize, Debug)]
+struct CachedPhoto {
+    stamp: FileStamp,
+    index: u8,
+    width: u16,
+    height: u16,
+    artist: Option<String>,
+    software: String,
+    title: Option<String>,
+    caption: Option<String>,
+    camera: Option<Camera>,
+    location: Option<Location>,
+    tags: Vec<String>,
+    city: Option<String>,
+    state: Option<String>,
+    usage_terms: Option<String>,
+    rating: Option<u8>,
+    date_taken: Option<DateTime<FixedOffset>>,
+    /// Whether the location was within the privacy zone and not cached
+    #[serde(default)]
+    hidden: bool,
+}
+
+impl PhotoCache {
+    /// Load cache file from path or an empty cache if there isn't one or it
+    /// was written for a different privacy zone
+    pub fn load(path: &Path, privacy: Option<&GpsPrivacy>) -> Self {
+        let zone = privacy.map(zone_digest);
+
+        load_ron::<Self>(path, CACHE_FILE, false)
+            .filter(|c| c.zone == zone)
+            .unwrap_or(PhotoCache {
+                zone,
+                photos: BTreeMap::new(),
+            })
+    }
+
+    /// Remove photos that are no longer present or have changed
+    pub fn retain(&mut self, files: &BTreeMap<String, FileStamp>) {
+        self.photos
+            .retain(|name, c| files.get(name) == Some(&c.stamp));
+    }
+
+    /// Whether the cached location of photo `name` was removed because it was
+    /// within the privacy zone
+    pub fn is_hidden(&self, name: &str) -> bool {
+        matches!(self.photos.get(name), Some(c) if c.hidden)
+    }
+
+    pub fn write(&self, path: &Path) {
+        let pretty = PrettyConfig::new().with_depth_limit(3);
+
+        write_result(
+            &path.join(CACHE_FILE),
+            || to_string_pretty(self, pretty),
+            false,
+        );
+    }
+
+    /// Cached photo for file `name` if its size and digest match `stamp`
+    pub fn get(
+        &self,
+        name: &str,
+        stamp: &FileStamp,
+        config: &PhotoConfig,
+    ) -> Option<Photo> {
+        let c = self.photos.get(name).filter(|c| c.stamp == *stamp)?;
+
+        Some(Photo {
+            file: PhotoFile {
+                name: name.to_string(),
+            },
+            index: c.index,
+            size: SizeCollection::from(c.width, c.height, c.index, config),
+            artist: c.artist.clone(),
+            software: c.software.clone(),
+            title: c.title.clone(),
+            caption: c.caption.clone(),
+            camera: c.camera.clone(),
+            location: c.location.clone(),
+            tags: c.tags.clone(),
+            city: c.city.clone(),
+            state: c.state.clone(),
+            usage_terms: c.usage_terms.clone(),
+            rating: c.rating,
+            date_taken: c.date_taken,
+            ..Photo::default()
+        })
+    }
+
+    /// Add or replace cached metadata for `photo` read from a file matching
+    /// `stamp`, leaving out its location if it's within the privacy zone
+    pub fn insert(
+        &mut self,
+        photo: &Photo,
+        stamp: FileStamp,
+        privacy: Option<&GpsPrivacy>,
+    ) {
+        let hidden = match (&photo.location, privacy) {
+            (Some(l), Some(zone)) => {
+                zone.contains((l.latitude as f64, l.longitude as f64))
+            }
+            _ => false,
+        };
+
+        self.photos.insert(
+            photo.file.name.clone(),
+            CachedPhoto {
+                stamp,
+                index: photo.index,
+                width: photo.size.original.width,
+                height: photo.size.original.height,
+                artist: photo.artist.clone(),
+                software: photo.software.clone(),
+                title: photo.title.clone(),
+                caption: photo.caption.clone(),
+                camera: photo.camera.clone(),
+                location: photo.location.clone().filter(|_| !hidden),
+                tags: photo.tags.clone(),
+                city: photo.city.clone(),
+                state: photo.state.clone(),
+                usage_terms: photo.usage_terms.clone(),
+                rating: photo.rating,
+                date_taken: photo.date_taken,
+                hidden,
+            },
+        );
+    }
+}
+
+/// Digest of the privacy zone so it can be compared without being written
+fn zone_digest(zone: &GpsPrivacy) -> String {
+    let text = format!(
+        "{},{},{}",
+        zone.center.latitude, zone.center.longitude, zone.miles
+    );
+
+    Sha1::digest(text.as_bytes())
+        .iter()
+        .map(|b| format!("{:02x}", b))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{PhotoCache, CACHE_FILE};
+    use crate::{
+        config::{GpsPrivacy, PhotoConfig},
+        models::{
+            Camera, ExposureMode, Location, Photo, PhotoFile, SizeCollection,
+        },
+        tools::FileStamp,
+    };
+    use std::{env, fs};
+
+    #[test]
+    fn round_trip_test() {
+        let config = PhotoConfig::fixture();
+        let stamp = FileStamp {
+            size: 1024,
+            digest: "abc".to_owned(),
+            modified: None,
+        };
+        let photo = Photo {
+            file: PhotoFile {
+                name: "img_006-of-021.jpg".to_owned(),
+            },
+            index: 6,
+            size: SizeCollection::from(100, 67, 6, &config),
+            title: Some("Time to move on".to_owned()),
+            tags: vec!["Fire".to_owned()],
+            camera: Some(Camera {
+                name: "NIKON D3".to_owned(),
+                mode: ExposureMode::AperturePriority,
+                ..Camera::default()
+            }),
+            ..Photo::default()
+        };
+
+        let mut cache = PhotoCache::default();
+        cache.insert(&photo, stamp.clone(), None);
+
+        let text = ron::ser::to_string(&cache).unwrap();
+        let cache: PhotoCache = ron::de::from_str(&text).unwrap();
+
+        let cached = cache.get("img_006-of-021.jpg", &stamp, &config).unwrap();
+
+        assert_eq!(cached, photo);
+        assert_eq!(cached.index, 6);
+        assert_eq!(cached.title, photo.title);
+        assert_eq!(cached.tags, photo.tags);
+        assert_eq!(cached.size.original.width, 100);
+        assert_eq!(cached.camera.unwrap().mode, ExposureMode::AperturePriority);
+
+        let changed = FileStamp {
+            digest: "def".to_owned(),
+            ..stamp
+        };
+        assert!(cache.get("img_006-of-021.jpg", &changed, &config).is_none());
+        assert!(cache.get("img_007-of-021.jpg", &stamp, &config).is_none());
+    }
+
+    #[test]
+    fn privacy_zone_test() {
+        let config = PhotoConfig::fixture();
+        let stamp = FileStamp {
+            size: 1024,
+            digest: "abc".to_owned(),
+            modified: None,
+        };
+        let zone: GpsPrivacy = toml::from_str(
+            r#"
+            miles = 1
+            verify = false
+            center = { latitude = 46.0, longitude = -116.0 }
+            "#,
+        )
+        .unwrap();
+        let photo = |name: &str, latitude: f32| Photo {
+            file: PhotoFile {
+                name: name.to_owned(),
+            },
+            location: Some(Location {
+                latitude,
+                longitude: -116.0,
+                ..Location::default()
+            }),
+            ..Photo::default()
+        };
+        let folder = env::temp_dir().join("exifweb-cache-privacy-test");
+        let _ = fs::remove_dir_all(&folder);
+        fs::create_dir_all(&folder).unwrap();
+
+        let mut cache = PhotoCache::load(&folder, Some(&zone));
+        cache.insert(&photo("home.jpg", 46.001), stamp.clone(), Some(&zone));
+        cache.insert(&photo("away.jpg", 46.5), stamp.clone(), Some(&zone));
+        cache.write(&folder);
+
+        let text = fs::read_to_string(folder.join(CACHE_FILE)).unwrap();
+
+        assert!(!text.contains("46.001"));
+        assert!(text.contains("46.5"));
+
+        let cache = PhotoCache::load(&folder, Some(&zone));
+
+        assert!(cache.is_hidden("home.jpg"));
+        assert!(!cache.is_hidden("away.jpg"));
+        assert_eq!(
+            cache.get("home.jpg", &stamp, &config).unwrap().location,
+            None
+        );
+        assert!(cache
+            .get("away.jpg", &stamp, &config)
+            .unwrap()
+            .location
+            .is_some());
+
+        // cache for another zone isn't used
+        assert!(PhotoCache::load(&folder, None)
+            .get("away.jpg", &stamp, &config)
+            .is_none());
+
+        let _ = fs::remove_dir_all(&folder);
+    }
+}
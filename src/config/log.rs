@@ -2,8 +2,8 @@
 
 use super::load_ron;
 use crate::{
-    models::{Blog, Photo, PhotoPath, Post, TagPhotos},
-    tools::write_result,
+    models::{Blog, Photo, PhotoPath, Post, TagPhotos, TrackStats},
+    tools::{write_result, FileStamp},
 };
 use chrono::{DateTime, FixedOffset, Local};
 use ron::ser::{to_string_pretty, PrettyConfig};
@@ -11,21 +11,20 @@ use serde::{Deserialize, Serialize};
 use std::{collections::BTreeMap, path::Path};
 
 /// File that stores photo tag information and last process time
-static LOG_FILE: &str = "log.ron";
+pub static LOG_FILE: &str = "log.ron";
 
 /// Log processed photo information per post folder to determine when
 /// re-processing is necessary.
 ///
 /// Re-rendering is triggered when
 ///
-/// - the configuration file or a photo has a modified date newer than
-///   the `as_of` time
-/// - the number of photos has changed
+/// - the configuration file or a photo has a different size or digest
+/// - a photo has been added or removed
 /// - adjacent post paths have changed
 ///
 /// Re-generating an image is triggered when
 ///
-/// - its modified date is newer than the `as_of` time
+/// - its size or digest has changed
 ///
 #[derive(Serialize, Deserialize, Debug)]
 pub struct PostLog {
@@ -47,6 +46,18 @@ pub struct PostLog {
 
     pub photo_locations: Vec<(f32, f32)>,
 
+    /// Index, longitude and latitude of located photos for the site map
+    #[serde(default)]
+    pub photo_points: Vec<(u8, f32, f32)>,
+
+    /// Whether the post had a GPX track
+    #[serde(default)]
+    pub has_track: bool,
+
+    /// Statistics computed from the post track
+    #[serde(default)]
+    pub track_stats: Option<TrackStats>,
+
     /// Even if post hasn't changed, its cover photo may be required to re-
     /// render category pages it's part of
     pub cover_photo: Option<Photo>,
@@ -56,25 +67,39 @@ pub struct PostLog {
     /// re-parsing every post photo.
     pub tags: BTreeMap<String, TagPhotos<u8>>,
 
+    /// Size and digest of each photo and the configuration file, keyed by
+    /// file name
+    #[serde(default)]
+    pub files: BTreeMap<String, FileStamp>,
+
     /// Whether post source files have changed since they were last read
     #[serde(skip)]
     pub files_changed: bool,
 }
 
 impl PostLog {
-    /// Save information about loaded photos to avoid unecessary re-processing
-    pub fn write(root: &Path, post: &Post) {
-        let log = PostLog {
+    /// Log of current post values
+    pub fn from_post(post: &Post) -> PostLog {
+        PostLog {
             prev_path: post.prev_path.clone(),
             next_path: post.next_path.clone(),
             happened_on: post.happened_on,
             photo_count: post.photo_count,
             photo_locations: post.photo_locations.clone(),
+            photo_points: post.photo_points.clone(),
+            has_track: post.has_track,
+            track_stats: post.track_stats.clone(),
             as_of: Local::now().timestamp(),
             tags: post.tags.clone(),
+            files: post.files.clone(),
             files_changed: false,
             cover_photo: post.cover_photo().cloned(),
-        };
+        }
+    }
+
+    /// Save information about loaded photos to avoid unecessary re-processing
+    pub fn write(root: &Path, post: &Post) {
+        let log = PostLog::from_post(post);
         let path = root.join(&post.path).join(LOG_FILE);
         let pretty = PrettyConfig::new().with_depth_limit(4);
 
@@ -94,12 +119,39 @@ impl PostLog {
             as_of: 0,
             photo_count: 0,
             photo_locations: Vec::new(),
+            photo_points: Vec::new(),
+            has_track: false,
+            track_stats: None,
             tags: BTreeMap::new(),
+            files: BTreeMap::new(),
             files_changed: true,
             cover_photo: None,
         }
     }
 
+    /// Whether the photo file has been added or changed. Photos in logs
+    /// written before file stamps were recorded are assumed unchanged so
+    /// upgrading doesn't resize every photo. Their stamps are recorded when
+    /// the log is next written.
+    pub fn photo_changed(&self, post: &Post, photo: &Photo) -> bool {
+        let name = &photo.file.name;
+
+        !self.unstamped() && self.files.get(name) != post.files.get(name)
+    }
+
+    /// Whether the log is for photos but has no file stamps because it was
+    /// written before they were recorded
+    fn unstamped(&self) -> bool {
+        self.files.is_empty() && self.photo_count > 0
+    }
+
+    /// Whether the log has photo locations but no map points because it was
+    /// written before they were recorded. Points can't be derived from the
+    /// sorted locations so the post must be re-read.
+    pub fn unmapped(&self) -> bool {
+        self.photo_points.is_empty() && !self.photo_locations.is_empty()
+    }
+
     /// Whether logged values differ from current post values
     pub fn sequence_changed(&self, post: &Post) -> bool {
         self.prev_path != post.prev_path || self.next_path != post.next_path
@@ -116,7 +168,7 @@ impl PostLog {
         } else {
             self.cover_photo
                 .as_ref()
-                .map_or(false, |p| p == post.cover_photo().unwrap())
+                .map_or(false, |p| p != post.cover_photo().unwrap())
         }
     }
 
@@ -130,7 +182,7 @@ impl PostLog {
             self.cover_photo.as_ref().map_or(false, |p| {
                 (p.aspect_ratio() - post.cover_photo().unwrap().aspect_ratio())
                     .abs()
-                    < f32::EPSILON
+                    > f32::EPSILON
             })
         }
     }
@@ -151,7 +203,11 @@ impl Clone for PostLog {
             as_of: self.as_of,
             photo_count: self.photo_count,
             photo_locations: self.photo_locations.clone(),
+            photo_points: self.photo_points.clone(),
+            has_track: self.has_track,
+            track_stats: self.track_stats.clone(),
             tags: self.tags.clone(),
+            files: self.files.clone(),
             files_changed: self.files_changed,
             cover_photo: if let Some(p) = &self.cover_photo {
                 Some(p.clone())
@@ -191,3 +247,121 @@ impl BlogLog {
         load_ron(path, LOG_FILE, false)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::PostLog;
+    use crate::{
+        config::PhotoConfig,
+        models::{Photo, PhotoFile, Post, SizeCollection},
+        tools::FileStamp,
+    };
+
+    #[test]
+    fn unstamped_log_test() {
+        // written before file stamps were logged
+        let mut log: PostLog = ron::from_str(
+            r#"(
+                happened_on: None,
+                as_of: 1600000000,
+                photo_count: 1,
+                photo_locations: [],
+                cover_photo: None,
+                tags: {},
+            )"#,
+        )
+        .unwrap();
+        let photo = Photo {
+            file: PhotoFile {
+                name: "001.jpg".to_owned(),
+            },
+            ..Photo::default()
+        };
+        let mut post = Post::default();
+
+        post.files.insert(
+            "001.jpg".to_owned(),
+            FileStamp {
+                size: 10,
+                digest: "abc".to_owned(),
+                modified: None,
+            },
+        );
+
+        assert!(log.files.is_empty());
+        assert!(!log.photo_changed(&post, &photo));
+
+        // once stamps are logged they're compared
+        log.files.insert(
+            "001.jpg".to_owned(),
+            FileStamp {
+                size: 11,
+                digest: "abd".to_owned(),
+                modified: None,
+            },
+        );
+        assert!(log.photo_changed(&post, &photo));
+        assert!(PostLog::empty().photo_changed(&post, &photo));
+    }
+
+    #[test]
+    fn unmapped_log_test() {
+        // written before photo points were logged
+        let mut log: PostLog = ron::from_str(
+            r#"(
+                happened_on: None,
+                as_of: 1600000000,
+                photo_count: 1,
+                photo_locations: [(-116.0, 46.0)],
+                cover_photo: None,
+                tags: {},
+            )"#,
+        )
+        .unwrap();
+
+        assert!(log.unmapped());
+
+        log.photo_points.push((0, -116.0, 46.0));
+        assert!(!log.unmapped());
+        assert!(!PostLog::empty().unmapped());
+    }
+
+    #[test]
+    fn cover_photo_changed_test() {
+        let photo = |name: &str, width: u16, height: u16| Photo {
+            file: PhotoFile {
+                name: name.to_owned(),
+            },
+            size: SizeCollection::from(
+                width,
+                height,
+                1,
+                &PhotoConfig::fixture(),
+            ),
+            ..Photo::default()
+        };
+        let post = |cover: Photo| {
+            let mut post = Post::default();
+            post.add_photos(vec![cover]);
+            post
+        };
+        let mut log = PostLog::empty();
+
+        log.cover_photo = Some(photo("001.jpg", 300, 200));
+
+        assert!(!log.cover_photo_changed(&post(photo("001.jpg", 300, 200))));
+        assert!(
+            !log.cover_aspect_ratio_changed(&post(photo("001.jpg", 300, 200)))
+        );
+
+        // different photo with the same shape
+        assert!(log.cover_photo_changed(&post(photo("002.jpg", 600, 400))));
+        assert!(
+            !log.cover_aspect_ratio_changed(&post(photo("002.jpg", 600, 400)))
+        );
+
+        assert!(
+            log.cover_aspect_ratio_changed(&post(photo("002.jpg", 200, 300)))
+        );
+    }
+}
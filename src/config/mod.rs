@@ -1,4 +1,5 @@
 mod blog;
+mod cache;
 mod log;
 pub mod post;
 mod series;
@@ -6,12 +7,14 @@ mod vendors;
 
 pub use blog::{
     BlogConfig, CategoryConfig, CategoryIcon, ExifConfig, FeaturedPost,
-    ImageConfig, OwnerConfig, PhotoConfig, SiteConfig, SizeConfig,
+    GpsPrivacy, GpsTrackConfig, ImageConfig, MetadataBackend, Overrides,
+    OwnerConfig, PhotoConfig, ResizeBackend, SiteConfig, SizeConfig,
 };
-pub use log::{BlogLog, PostLog};
+pub use cache::{PhotoCache, CACHE_FILE};
+pub use log::{BlogLog, PostLog, LOG_FILE};
 pub use post::PostConfig;
 pub use series::SeriesConfig;
-pub use vendors::FacebookConfig;
+pub use vendors::{FacebookConfig, MapBoxConfig};
 
 use colored::*;
 
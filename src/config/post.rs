@@ -39,7 +39,8 @@ pub struct PostConfig {
     #[serde(default, rename = "categories")]
     pub category_list: Option<PostCategories>,
 
-    /// One-based index of cover photo
+    /// One-based index of cover photo. If not set then the highest rated photo
+    /// is used or, if none are rated, the first.
     #[serde(default)]
     pub cover_photo_index: usize,
 
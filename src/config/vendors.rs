@@ -1,5 +1,9 @@
 use super::{env_or_empty, ReadsEnv};
 use serde::Deserialize;
+use std::{
+    env,
+    path::{Path, PathBuf},
+};
 
 // https://developers.facebook.com/docs/reference/plugins/like/
 // https://developers.facebook.com/apps/110860435668134/summary
@@ -25,6 +29,23 @@ pub struct MapBoxConfig {
     pub style: MapBoxStyles,
     /// Fully qualified path to image used to render pins on static map
     pub pin_image: String,
+    /// Scheme and host of the static image API
+    #[serde(default = "default_mapbox_url")]
+    pub base_url: String,
+    /// Folder, relative to the blog root, in which static map images are
+    /// cached. It should be outside the published folders. The system temporary
+    /// folder is used if none is configured.
+    pub cache_folder: Option<String>,
+}
+
+impl MapBoxConfig {
+    /// Folder in which static map images are cached
+    pub fn cache_path(&self, root: &Path) -> PathBuf {
+        match &self.cache_folder {
+            Some(folder) => root.join(folder),
+            _ => env::temp_dir().join("exifweb-map-cache"),
+        }
+    }
 }
 impl ReadsEnv for MapBoxConfig {
     fn from_env(&mut self) {
@@ -32,6 +53,32 @@ impl ReadsEnv for MapBoxConfig {
     }
 }
 
+#[cfg(test)]
+impl MapBoxConfig {
+    /// Configuration for requests to a service at `base_url`
+    pub fn fixture(base_url: &str) -> Self {
+        let mut config: MapBoxConfig = toml::from_str(&format!(
+            r#"
+            pin_image = "https://example.com/pin.png"
+            base_url = "{}"
+
+            [style]
+            dynamic = "owner/dynamic"
+            static = "owner/static"
+            "#,
+            base_url
+        ))
+        .unwrap();
+
+        config.access_token = "token".to_owned();
+        config
+    }
+}
+
+fn default_mapbox_url() -> String {
+    String::from("https://api.mapbox.com")
+}
+
 #[derive(Deserialize, Debug)]
 pub struct GoogleConfig {
     #[serde(skip)]
@@ -1,6 +1,6 @@
 //! Custom Serde deserializers
 
-use chrono::{DateTime, FixedOffset, Local};
+use chrono::{DateTime, FixedOffset, Local, ParseResult};
 use lazy_static::*;
 use regex::Regex;
 use serde::{de, Deserialize, Deserializer};
@@ -91,25 +91,30 @@ impl<'de> de::Visitor<'de> for DateTimeString {
     }
 
     fn visit_str<E: de::Error>(self, value: &str) -> Result<Self::Value, E> {
-        lazy_static! {
-            static ref TZ: Regex = Regex::new(r"[+-]\d{2}:\d{2}$").unwrap();
-            static ref OFFSET: String =
-                format!("{}", Local::today().format("%:z"));
-        }
-        let mut d = value.to_owned();
-
-        if !TZ.is_match(value) {
-            // append local timezone offset if not included
-            d.push_str(&OFFSET);
-        }
-
-        match DateTime::parse_from_str(&d, "%Y:%m:%d %H:%M:%S%:z") {
+        match parse_date_time(value) {
             Ok(d) => Ok(Some(d)),
             Err(e) => Err(de::Error::custom(e)),
         }
     }
 }
 
+/// Parse EXIF formatted date-time (`2018:02:08 11:01:12`), appending the local
+/// timezone offset if the text doesn't include one
+pub fn parse_date_time(value: &str) -> ParseResult<DateTime<FixedOffset>> {
+    lazy_static! {
+        static ref TZ: Regex = Regex::new(r"[+-]\d{2}:\d{2}$").unwrap();
+        static ref OFFSET: String = format!("{}", Local::today().format("%:z"));
+    }
+    let mut d = value.to_owned();
+
+    if !TZ.is_match(value) {
+        // append local timezone offset if not included
+        d.push_str(&OFFSET);
+    }
+
+    DateTime::parse_from_str(&d, "%Y:%m:%d %H:%M:%S%:z")
+}
+
 pub fn date_time_string<'de, D>(
     deserializer: D,
 ) -> Result<Option<DateTime<FixedOffset>>, D::Error>
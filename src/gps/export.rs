@@ -0,0 +1,220 @@
+//! Downloadable GPX and KML files of cleaned post tracks with photo waypoints
+//!
+//! https://www.topografix.com/GPX/1/1/
+//! https://developers.google.com/kml/documentation/kmlreference
+
+use crate::{
+    html,
+    models::{Photo, Post, TrackPoint},
+};
+
+/// Post sub-folder for downloadable tracks. It's kept apart from the post
+/// folder so written files aren't read back as source GPX files.
+pub const DOWNLOAD_FOLDER: &str = "download";
+pub const DOWNLOAD_GPX: &str = "track.gpx";
+pub const DOWNLOAD_KML: &str = "track.kml";
+
+/// GPX document with a waypoint for each located photo and the post tracks
+///
+/// - `url` Absolute URL of the post page that photo waypoints link to
+pub fn to_gpx(post: &Post, url: &str) -> String {
+    let mut xml = format!(
+        "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n\
+         <gpx version=\"1.1\" creator=\"exifweb\" \
+         xmlns=\"http://www.topografix.com/GPX/1/1\">\n\
+         <metadata><name>{}</name><link href=\"{}\"/></metadata>\n",
+        escape(&post.title),
+        escape(url)
+    );
+
+    for (photo, lat, lon) in located(&post.photos) {
+        xml.push_str(&format!(
+            "<wpt lat=\"{}\" lon=\"{}\"><name>{}</name>\
+             <link href=\"{}#{}\"/></wpt>\n",
+            lat,
+            lon,
+            escape(&waypoint_name(photo)),
+            escape(url),
+            html::photo_id(photo.index)
+        ));
+    }
+
+    for t in post.tracks.iter() {
+        xml.push_str("<trk>");
+        if let Some(name) = &t.name {
+            xml.push_str(&format!("<name>{}</name>", escape(name)));
+        }
+        for s in t.segments.iter() {
+            xml.push_str("\n<trkseg>\n");
+            for p in s {
+                xml.push_str(&gpx_point(p));
+            }
+            xml.push_str("</trkseg>");
+        }
+        xml.push_str("\n</trk>\n");
+    }
+
+    xml.push_str("</gpx>\n");
+    xml
+}
+
+/// KML document with a placemark for each located photo and a line for every
+/// track segment
+///
+/// - `url` Absolute URL of the post page that photo placemarks link to
+pub fn to_kml(post: &Post, url: &str) -> String {
+    let mut xml = format!(
+        "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n\
+         <kml xmlns=\"http://www.opengis.net/kml/2.2\"><Document>\n\
+         <name>{}</name>\n",
+        escape(&post.title)
+    );
+
+    for (photo, lat, lon) in located(&post.photos) {
+        xml.push_str(&format!(
+            "<Placemark><name>{}</name><description>{}#{}</description>\
+             <Point><coordinates>{},{}</coordinates></Point></Placemark>\n",
+            escape(&waypoint_name(photo)),
+            escape(url),
+            html::photo_id(photo.index),
+            lon,
+            lat
+        ));
+    }
+
+    for t in post.tracks.iter() {
+        xml.push_str(&format!(
+            "<Placemark><name>{}</name><MultiGeometry>\n",
+            escape(t.name.as_deref().unwrap_or(&post.title))
+        ));
+        for s in t.segments.iter().filter(|s| s.len() > 1) {
+            let coordinates: Vec<String> = s.iter().map(kml_point).collect();
+
+            xml.push_str(&format!(
+                "<LineString><tessellate>1</tessellate>\
+                 <coordinates>{}</coordinates></LineString>\n",
+                coordinates.join(" ")
+            ));
+        }
+        xml.push_str("</MultiGeometry></Placemark>\n");
+    }
+
+    xml.push_str("</Document></kml>\n");
+    xml
+}
+
+/// Photos with their latitude and longitude
+fn located(photos: &[Photo]) -> impl Iterator<Item = (&Photo, f32, f32)> {
+    photos.iter().filter_map(|p| {
+        p.location.as_ref().map(|l| (p, l.latitude, l.longitude))
+    })
+}
+
+/// Photo title or, if untitled, its position in the post
+fn waypoint_name(photo: &Photo) -> String {
+    photo
+        .title
+        .clone()
+        .unwrap_or_else(|| format!("Photo {}", photo.index))
+}
+
+fn gpx_point(p: &TrackPoint) -> String {
+    let mut xml =
+        format!("<trkpt lat=\"{}\" lon=\"{}\">", p.latitude, p.longitude);
+
+    if let Some(e) = p.elevation {
+        xml.push_str(&format!("<ele>{}</ele>", e));
+    }
+    if let Some(t) = p.time {
+        xml.push_str(&format!("<time>{}</time>", t.to_rfc3339()));
+    }
+    xml.push_str("</trkpt>\n");
+    xml
+}
+
+/// Longitude, latitude and, if known, elevation
+fn kml_point(p: &TrackPoint) -> String {
+    match p.elevation {
+        Some(e) => format!("{},{},{}", p.longitude, p.latitude, e),
+        _ => format!("{},{}", p.longitude, p.latitude),
+    }
+}
+
+/// Escape characters with special meaning in XML
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{to_gpx, to_kml};
+    use crate::{
+        gps::gpx::parse,
+        models::{Location, Photo, Post, Track, TrackPoint},
+    };
+
+    fn post() -> Post {
+        Post {
+            title: "Rocks & Roads".to_owned(),
+            tracks: vec![Track {
+                name: Some("Ride".to_owned()),
+                segments: vec![vec![
+                    TrackPoint {
+                        elevation: Some(1000.0),
+                        ..TrackPoint::new(46.0, -116.0)
+                    },
+                    TrackPoint::new(46.1, -116.1),
+                ]],
+            }],
+            photos: vec![
+                Photo {
+                    index: 7,
+                    title: Some("Summit".to_owned()),
+                    location: Some(Location {
+                        latitude: 46.05,
+                        longitude: -116.05,
+                        inferred: false,
+                    }),
+                    ..Photo::default()
+                },
+                Photo {
+                    index: 8,
+                    ..Photo::default()
+                },
+            ],
+            ..Post::default()
+        }
+    }
+
+    #[test]
+    fn gpx_test() {
+        let xml = to_gpx(&post(), "https://example.com/ride");
+
+        assert!(xml.contains("<name>Rocks &amp; Roads</name>"));
+        assert!(xml.contains(
+            "<wpt lat=\"46.05\" lon=\"-116.05\"><name>Summit</name>\
+             <link href=\"https://example.com/ride#007\"/></wpt>"
+        ));
+        assert!(!xml.contains("Photo 8"));
+
+        // written tracks can be read back
+        let tracks = parse(&xml).unwrap();
+
+        assert_eq!(tracks.len(), 1);
+        assert_eq!(tracks[0], post().tracks[0]);
+    }
+
+    #[test]
+    fn kml_test() {
+        let xml = to_kml(&post(), "https://example.com/ride");
+
+        assert!(xml.contains("<Point><coordinates>-116.05,46.05</coordinates>"));
+        assert!(
+            xml.contains("<coordinates>-116,46,1000 -116.1,46.1</coordinates>")
+        );
+        assert!(xml.ends_with("</Document></kml>\n"));
+    }
+}
@@ -0,0 +1,272 @@
+//! Name photo locations from a list of places without an online service
+
+use super::{distance_feet, FEET_PER_MILE};
+use crate::models::Photo;
+use std::{cmp::Reverse, collections::BTreeMap, fs, path::Path};
+
+/// Named point from the place data
+#[derive(Debug, Clone, PartialEq)]
+pub struct Place {
+    pub name: String,
+    /// State, province or other first-level division
+    pub region: Option<String>,
+    pub latitude: f64,
+    pub longitude: f64,
+    pub population: u64,
+}
+
+/// Places sorted by latitude so those near a location can be found without
+/// measuring the distance to every one
+#[derive(Debug, Default)]
+pub struct Gazetteer {
+    places: Vec<Place>,
+}
+
+impl Gazetteer {
+    /// Read places from a comma or tab delimited file with a header row naming
+    /// at least `name`, `latitude` and `longitude` columns. Optional `admin1`
+    /// (or `state` or `region`) and `population` columns are also read, as
+    /// exported from GeoNames. Places smaller than `min_population` are
+    /// skipped.
+    pub fn load(path: &Path, min_population: u64) -> Result<Self, String> {
+        let text = fs::read_to_string(path).map_err(|e| format!("{:?}", e))?;
+        let places = parse(&text)?
+            .into_iter()
+            .filter(|p| p.population >= min_population)
+            .collect();
+
+        Ok(Gazetteer::new(places))
+    }
+
+    pub fn new(mut places: Vec<Place>) -> Self {
+        places.sort_by(|a, b| a.latitude.partial_cmp(&b.latitude).unwrap());
+        Gazetteer { places }
+    }
+
+    /// Place closest to a latitude and longitude if any is within `max_miles`
+    pub fn nearest(
+        &self,
+        lat_lon: (f64, f64),
+        max_miles: f32,
+    ) -> Option<&Place> {
+        let max_feet = max_miles as f64 * FEET_PER_MILE;
+        // a degree of latitude is at least 68 miles
+        let band = max_miles as f64 / 68.0;
+        let start = self
+            .places
+            .partition_point(|p| p.latitude < lat_lon.0 - band);
+
+        self.places[start..]
+            .iter()
+            .take_while(|p| p.latitude <= lat_lon.0 + band)
+            .map(|p| (p, distance_feet(lat_lon, (p.latitude, p.longitude))))
+            .filter(|(_, d)| *d <= max_feet)
+            .min_by(|a, b| a.1.partial_cmp(&b.1).unwrap())
+            .map(|(p, _)| p)
+    }
+
+    /// Set the city and state of located photos that have neither from the
+    /// nearest place. Returns the number of photos named.
+    pub fn name_photos(&self, photos: &mut [Photo], max_miles: f32) -> usize {
+        let mut named = 0;
+
+        for photo in photos
+            .iter_mut()
+            .filter(|p| p.city.is_none() && p.state.is_none())
+        {
+            let place = photo.location.as_ref().and_then(|l| {
+                self.nearest((l.latitude as f64, l.longitude as f64), max_miles)
+            });
+
+            if let Some(place) = place {
+                photo.city = Some(place.name.clone());
+                photo.state = place.region.clone();
+                named += 1;
+            }
+        }
+        named
+    }
+
+    /// Names of regions containing the longitude and latitude `locations`,
+    /// most frequent first, to use as "where" categories. The place name is
+    /// used for places without a region.
+    pub fn regions(
+        &self,
+        locations: &[(f32, f32)],
+        max_miles: f32,
+    ) -> Vec<String> {
+        let mut counts: BTreeMap<&str, usize> = BTreeMap::new();
+
+        for (lon, lat) in locations {
+            if let Some(place) =
+                self.nearest((*lat as f64, *lon as f64), max_miles)
+            {
+                let name = place.region.as_ref().unwrap_or(&place.name);
+                *counts.entry(name.as_str()).or_insert(0) += 1;
+            }
+        }
+
+        let mut regions: Vec<(&str, usize)> = counts.into_iter().collect();
+        // stable sort keeps names with equal counts alphabetical
+        regions.sort_by_key(|(_, count)| Reverse(*count));
+        regions
+            .into_iter()
+            .map(|(name, _)| name.to_string())
+            .collect()
+    }
+}
+
+/// Places from delimited text. Rows without a name or valid coordinates are
+/// skipped.
+fn parse(text: &str) -> Result<Vec<Place>, String> {
+    let first_line = text.lines().next().unwrap_or_default();
+    let delimiter = if first_line.contains('\t') {
+        b'\t'
+    } else {
+        b','
+    };
+    let mut reader = csv::ReaderBuilder::new()
+        .delimiter(delimiter)
+        .flexible(true)
+        .from_reader(text.as_bytes());
+
+    let headers: Vec<String> = reader
+        .headers()
+        .map_err(|e| format!("{:?}", e))?
+        .iter()
+        .map(|h| h.trim().to_lowercase())
+        .collect();
+    let column = |names: &[&str]| {
+        headers.iter().position(|h| names.contains(&h.as_str()))
+    };
+
+    let name = column(&["name", "asciiname"]);
+    let latitude = column(&["latitude", "lat"]);
+    let longitude = column(&["longitude", "lon", "lng"]);
+    let region = column(&["admin1", "state", "region", "province"]);
+    let population = column(&["population"]);
+
+    let (name, latitude, longitude) = match (name, latitude, longitude) {
+        (Some(n), Some(lat), Some(lon)) => (n, lat, lon),
+        _ => {
+            return Err("place data needs name, latitude and longitude columns"
+                .to_owned())
+        }
+    };
+
+    Ok(reader
+        .records()
+        .filter_map(|r| r.ok())
+        .filter_map(|r| {
+            let field =
+                |i: usize| r.get(i).map(str::trim).filter(|s| !s.is_empty());
+
+            Some(Place {
+                name: field(name)?.to_string(),
+                region: region.and_then(field).map(|s| s.to_string()),
+                latitude: field(latitude)?.parse().ok()?,
+                longitude: field(longitude)?.parse().ok()?,
+                population: population
+                    .and_then(field)
+                    .and_then(|s| s.parse().ok())
+                    .unwrap_or(0),
+            })
+        })
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{parse, Gazetteer};
+    use crate::models::{Location, Photo};
+    use std::path::Path;
+
+    fn fixture() -> Gazetteer {
+        Gazetteer::load(Path::new("./src/fixtures/places.csv"), 0).unwrap()
+    }
+
+    #[test]
+    fn parse_test() {
+        let places = parse(concat!(
+            "geonameid\tname\tlatitude\tlongitude\tpopulation\n",
+            "1\tStanley\t44.2157\t-114.9384\t116\n",
+            // skipped without a name or with invalid coordinates
+            "2\t\t44.0\t-114.0\t0\n",
+            "3\tNowhere\tnorth\t-114.0\t0\n",
+        ))
+        .unwrap();
+
+        assert_eq!(places.len(), 1);
+        assert_eq!(places[0].name, "Stanley");
+        assert_eq!(places[0].region, None);
+        assert_eq!(places[0].population, 116);
+        assert!(parse("city,state\nBoise,Idaho\n").is_err());
+    }
+
+    #[test]
+    fn nearest_test() {
+        let places = fixture();
+
+        // a few miles from downtown Boise
+        assert_eq!(
+            places
+                .nearest((43.65, -116.25), 20.0)
+                .map(|p| p.name.as_str()),
+            Some("Boise")
+        );
+        assert_eq!(places.nearest((40.0, -100.0), 20.0), None);
+
+        let towns =
+            Gazetteer::load(Path::new("./src/fixtures/places.csv"), 10_000)
+                .unwrap();
+
+        // Stanley is too small
+        assert_eq!(
+            towns
+                .nearest((44.22, -114.94), 20.0)
+                .map(|p| p.name.as_str()),
+            None
+        );
+    }
+
+    #[test]
+    fn name_photos_test() {
+        let located = |latitude: f32, longitude: f32| Photo {
+            location: Some(Location {
+                latitude,
+                longitude,
+                ..Location::default()
+            }),
+            ..Photo::default()
+        };
+        let mut photos = vec![
+            located(44.22, -114.94),
+            Photo {
+                city: Some("Sun Valley".to_owned()),
+                ..located(43.68, -114.36)
+            },
+            Photo::default(),
+        ];
+
+        assert_eq!(fixture().name_photos(&mut photos, 20.0), 1);
+        assert_eq!(photos[0].place_name(), Some("Stanley, Idaho".to_owned()));
+        assert_eq!(photos[1].place_name(), Some("Sun Valley".to_owned()));
+        assert_eq!(photos[2].place_name(), None);
+    }
+
+    #[test]
+    fn regions_test() {
+        let locations = vec![
+            (-116.2, 43.6),
+            (-117.0, 46.4),
+            (-114.94, 44.22),
+            (-117.04, 46.42),
+            (-100.0, 40.0),
+        ];
+
+        assert_eq!(
+            fixture().regions(&locations, 20.0),
+            vec!["Idaho".to_owned(), "Washington".to_owned()]
+        );
+    }
+}
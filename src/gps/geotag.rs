@@ -0,0 +1,168 @@
+//! Infer photo locations from GPS track points recorded around the time each
+//! photo was taken
+
+use crate::{
+    config::GpsTrackConfig,
+    models::{Location, Photo, Track, TrackPoint},
+};
+use chrono::{DateTime, Duration, FixedOffset};
+
+/// Set an inferred location for each photo that has a date but no location,
+/// interpolated between the track points recorded before and after it. The
+/// camera clock offset is added to photo dates before matching and photos
+/// further than the maximum gap from the nearest point are left alone.
+/// Returns the number of photos geotagged.
+pub fn geotag(
+    photos: &mut [Photo],
+    tracks: &[Track],
+    config: &GpsTrackConfig,
+) -> usize {
+    // only timed points can be matched to photos
+    let segments: Vec<Vec<&TrackPoint>> = tracks
+        .iter()
+        .flat_map(|t| t.segments.iter())
+        .map(|s| s.iter().filter(|p| p.time.is_some()).collect::<Vec<_>>())
+        .filter(|s| !s.is_empty())
+        .collect();
+
+    if segments.is_empty() {
+        return 0;
+    }
+
+    let offset = Duration::seconds(config.camera_offset_seconds);
+    let max_gap = Duration::seconds(config.max_geotag_gap_seconds as i64);
+    let mut count: usize = 0;
+
+    for photo in photos.iter_mut().filter(|p| p.location.is_none()) {
+        let time = match photo.date_taken {
+            Some(date) => date + offset,
+            _ => continue,
+        };
+
+        // segment point closest in time
+        photo.location = segments
+            .iter()
+            .filter_map(|s| locate(s, time))
+            .filter(|(_, gap)| *gap <= max_gap)
+            .min_by_key(|(_, gap)| *gap)
+            .map(|(location, _)| location);
+
+        if photo.location.is_some() {
+            count += 1;
+        }
+    }
+    count
+}
+
+/// Location at `time` along timed, chronological `points` and the longest
+/// time between it and the points it was derived from. Times before or after
+/// the points resolve to the first or last point.
+fn locate(
+    points: &[&TrackPoint],
+    time: DateTime<FixedOffset>,
+) -> Option<(Location, Duration)> {
+    let first = points.first()?;
+    let last = points.last()?;
+    let at = |p: &TrackPoint| p.time.unwrap();
+
+    match points.iter().position(|p| at(p) >= time) {
+        None => {
+            Some((inferred(last.latitude, last.longitude), time - at(last)))
+        }
+        Some(0) => {
+            Some((inferred(first.latitude, first.longitude), at(first) - time))
+        }
+        Some(i) => {
+            let (a, b) = (points[i - 1], points[i]);
+            let span = (at(b) - at(a)).num_milliseconds() as f64;
+            let fraction = (time - at(a)).num_milliseconds() as f64 / span;
+            let between = |x: f64, y: f64| x + (y - x) * fraction;
+
+            Some((
+                inferred(
+                    between(a.latitude, b.latitude),
+                    between(a.longitude, b.longitude),
+                ),
+                (time - at(a)).max(at(b) - time),
+            ))
+        }
+    }
+}
+
+fn inferred(latitude: f64, longitude: f64) -> Location {
+    Location {
+        latitude: latitude as f32,
+        longitude: longitude as f32,
+        inferred: true,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::geotag;
+    use crate::{
+        config::GpsTrackConfig,
+        models::{Location, Photo, PhotoFile, Track, TrackPoint},
+    };
+    use chrono::{DateTime, Duration};
+
+    fn at_minute(minutes: i64) -> DateTime<chrono::FixedOffset> {
+        DateTime::parse_from_rfc3339("2019-06-02T09:00:00-06:00").unwrap()
+            + Duration::minutes(minutes)
+    }
+
+    fn photo(name: &str, minutes: i64) -> Photo {
+        Photo {
+            file: PhotoFile {
+                name: name.to_owned(),
+            },
+            date_taken: Some(at_minute(minutes)),
+            ..Photo::default()
+        }
+    }
+
+    #[test]
+    fn geotag_test() {
+        let point = |latitude: f64, minutes: i64| TrackPoint {
+            time: Some(at_minute(minutes)),
+            ..TrackPoint::new(latitude, -116.0)
+        };
+        let tracks = vec![Track {
+            name: None,
+            segments: vec![vec![point(46.0, 0), point(46.1, 10)]],
+        }];
+        let config = GpsTrackConfig {
+            // camera clock is two minutes fast
+            camera_offset_seconds: -120,
+            max_geotag_gap_seconds: 300,
+            ..GpsTrackConfig::default()
+        };
+        let mut photos = vec![
+            photo("middle.jpg", 7),
+            photo("after.jpg", 13),
+            photo("late.jpg", 20),
+            Photo {
+                location: Some(Location {
+                    latitude: 47.0,
+                    longitude: -115.0,
+                    inferred: false,
+                }),
+                ..photo("tagged.jpg", 5)
+            },
+        ];
+
+        assert_eq!(geotag(&mut photos, &tracks, &config), 2);
+
+        let middle = photos[0].location.as_ref().unwrap();
+
+        assert!(middle.inferred);
+        assert!((middle.latitude - 46.05).abs() < 0.000_01);
+        assert_eq!(middle.longitude, -116.0);
+
+        let after = photos[1].location.as_ref().unwrap();
+
+        assert!((after.latitude - 46.1).abs() < 0.000_01);
+        assert!(photos[2].location.is_none());
+        assert!(!photos[3].location.as_ref().unwrap().inferred);
+    }
+}
@@ -0,0 +1,183 @@
+//! Read tracks and routes from GPX files
+//!
+//! https://www.topografix.com/GPX/1/1/
+
+use crate::{
+    models::{Track, TrackPoint},
+    tools::folder_name,
+};
+use chrono::DateTime;
+use colored::*;
+use serde::Deserialize;
+use std::{fs, path::Path};
+
+/// Extension of GPS exchange files
+pub const GPX_EXT: &str = ".gpx";
+
+/// Track or route point. Elements like `extensions` are ignored.
+#[derive(Deserialize, Debug)]
+struct Point {
+    lat: f64,
+    lon: f64,
+    ele: Option<f32>,
+    time: Option<String>,
+}
+
+#[derive(Deserialize, Debug)]
+struct Segment {
+    #[serde(default, rename = "trkpt")]
+    points: Vec<Point>,
+}
+
+#[derive(Deserialize, Debug)]
+struct Trk {
+    name: Option<String>,
+    #[serde(default, rename = "trkseg")]
+    segments: Vec<Segment>,
+}
+
+/// Planned route which, unlike a track, has no segments
+#[derive(Deserialize, Debug)]
+struct Rte {
+    name: Option<String>,
+    #[serde(default, rename = "rtept")]
+    points: Vec<Point>,
+}
+
+#[derive(Deserialize, Debug)]
+struct Gpx {
+    #[serde(default, rename = "trk")]
+    tracks: Vec<Trk>,
+    #[serde(default, rename = "rte")]
+    routes: Vec<Rte>,
+}
+
+impl Point {
+    fn into_track_point(self) -> TrackPoint {
+        TrackPoint {
+            latitude: self.lat,
+            longitude: self.lon,
+            elevation: self.ele,
+            time: self
+                .time
+                .and_then(|t| DateTime::parse_from_rfc3339(t.trim()).ok()),
+        }
+    }
+}
+
+fn track_points(points: Vec<Point>) -> Vec<TrackPoint> {
+    points.into_iter().map(Point::into_track_point).collect()
+}
+
+/// Tracks and routes from every GPX file in `path`, ordered by file name.
+/// Files that can't be read or parsed are reported and skipped.
+pub fn load_tracks(path: &Path) -> Vec<Track> {
+    let mut files: Vec<_> = match fs::read_dir(path) {
+        Ok(entries) => entries
+            .filter_map(|e| e.ok())
+            .map(|e| e.path())
+            .filter(|p| folder_name(p).ends_with(GPX_EXT))
+            .collect(),
+        _ => return Vec::new(),
+    };
+
+    files.sort();
+    files.iter().flat_map(|f| read_file(f)).collect()
+}
+
+fn read_file(path: &Path) -> Vec<Track> {
+    let text = match fs::read_to_string(path) {
+        Ok(text) => text,
+        Err(e) => {
+            println!(
+                "   {} {} {:?}",
+                "failed to read".red(),
+                folder_name(path).red(),
+                e
+            );
+            return Vec::new();
+        }
+    };
+
+    match parse(&text) {
+        Ok(tracks) => tracks,
+        Err(e) => {
+            println!(
+                "   {} {} {:?}",
+                "failed to parse".red(),
+                folder_name(path).red(),
+                e
+            );
+            Vec::new()
+        }
+    }
+}
+
+/// Parse GPX text into tracks, with each route becoming a track of one
+/// segment. Tracks without points are excluded.
+pub fn parse(text: &str) -> Result<Vec<Track>, serde_xml_rs::Error> {
+    let gpx: Gpx = serde_xml_rs::from_str(text)?;
+    let tracks = gpx.tracks.into_iter().map(|t| Track {
+        name: t.name,
+        segments: t
+            .segments
+            .into_iter()
+            .map(|s| track_points(s.points))
+            .filter(|s| !s.is_empty())
+            .collect(),
+    });
+    let routes = gpx.routes.into_iter().map(|r| Track {
+        name: r.name,
+        segments: vec![track_points(r.points)],
+    });
+
+    Ok(tracks.chain(routes).filter(|t| !t.is_empty()).collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{load_tracks, parse};
+    use chrono::DateTime;
+    use std::path::Path;
+
+    #[test]
+    fn parse_fixture_test() {
+        let tracks = load_tracks(Path::new("./src/fixtures"));
+
+        assert_eq!(tracks.len(), 2);
+
+        let track = &tracks[0];
+
+        assert_eq!(track.name, Some("Moscow Mountain".to_owned()));
+        assert_eq!(track.segments.len(), 2);
+        assert_eq!(track.point_count(), 5);
+
+        let point = &track.segments[0][1];
+
+        assert_eq!(point.latitude, 46.7721);
+        assert_eq!(point.longitude, -116.9522);
+        assert_eq!(point.elevation, Some(801.6));
+        assert_eq!(
+            point.time,
+            Some(DateTime::parse_from_rfc3339("2019-06-02T15:04:31Z").unwrap())
+        );
+
+        let last = &track.segments[1][1];
+
+        assert_eq!(last.elevation, None);
+        assert_eq!(last.time, None);
+
+        assert_eq!(tracks[1].name, Some("Planned".to_owned()));
+        assert_eq!(tracks[1].point_count(), 2);
+    }
+
+    #[test]
+    fn empty_test() {
+        let tracks =
+            parse(r#"<gpx version="1.1"><wpt lat="46.7" lon="-116.9"/></gpx>"#)
+                .unwrap();
+
+        assert!(tracks.is_empty());
+        assert!(parse("<gpx><trk>").is_err());
+    }
+}
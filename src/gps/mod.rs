@@ -0,0 +1,51 @@
+//! GPS tracks recorded alongside post photos
+
+mod export;
+mod gazetteer;
+mod geotag;
+pub mod gpx;
+mod privacy;
+mod profile;
+mod simplify;
+mod stats;
+
+pub use export::{to_gpx, to_kml, DOWNLOAD_FOLDER, DOWNLOAD_GPX, DOWNLOAD_KML};
+pub use gazetteer::Gazetteer;
+pub use geotag::geotag;
+pub use gpx::{load_tracks, GPX_EXT};
+pub use privacy::{hide_locations, hide_photos, hide_tracks};
+pub use profile::elevation_svg;
+pub use simplify::{clean, simplify};
+pub use stats::track_stats;
+
+/// GeoJSON file written to post folders with tracks
+pub const TRACK_FILE: &str = "track.json";
+
+/// Mean radius of the earth
+const EARTH_RADIUS_FEET: f64 = 20_902_231.0;
+const FEET_PER_MILE: f64 = 5280.0;
+
+/// Great-circle distance between latitude and longitude pairs using the
+/// haversine formula
+pub fn distance_feet(a: (f64, f64), b: (f64, f64)) -> f64 {
+    let (lat1, lat2) = (a.0.to_radians(), b.0.to_radians());
+    let d_lat = lat2 - lat1;
+    let d_lon = (b.1 - a.1).to_radians();
+    let h = (d_lat / 2.0).sin().powi(2)
+        + lat1.cos() * lat2.cos() * (d_lon / 2.0).sin().powi(2);
+
+    2.0 * EARTH_RADIUS_FEET * h.sqrt().asin()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::distance_feet;
+
+    #[test]
+    fn distance_test() {
+        // one degree of latitude is about 69 miles
+        let miles = distance_feet((46.0, -116.0), (47.0, -116.0)) / 5280.0;
+
+        assert!((miles - 69.09).abs() < 0.1);
+    }
+}
@@ -0,0 +1,161 @@
+//! Hide locations within privacy zones, such as around home, so they aren't
+//! published
+
+use super::{distance_feet, FEET_PER_MILE};
+use crate::{
+    config::GpsPrivacy,
+    models::{Photo, Track, TrackPoint},
+};
+
+impl GpsPrivacy {
+    /// Whether latitude and longitude are within the zone
+    pub fn contains(&self, lat_lon: (f64, f64)) -> bool {
+        let center =
+            (self.center.latitude as f64, self.center.longitude as f64);
+
+        distance_feet(center, lat_lon) <= self.miles as f64 * FEET_PER_MILE
+    }
+}
+
+/// Remove photo locations within the zone and return names of the photos
+/// affected
+pub fn hide_photos(photos: &mut [Photo], zone: &GpsPrivacy) -> Vec<String> {
+    let mut hidden: Vec<String> = Vec::new();
+
+    for p in photos.iter_mut() {
+        if let Some(l) = &p.location {
+            if zone.contains((l.latitude as f64, l.longitude as f64)) {
+                p.location = None;
+                hidden.push(p.file.name.clone());
+            }
+        }
+    }
+    hidden
+}
+
+/// Remove longitude, latitude pairs within the zone and return how many were
+/// removed
+pub fn hide_locations(
+    locations: &mut Vec<(f32, f32)>,
+    zone: &GpsPrivacy,
+) -> usize {
+    let before = locations.len();
+
+    locations.retain(|(lon, lat)| !zone.contains((*lat as f64, *lon as f64)));
+    before - locations.len()
+}
+
+/// Remove track points within the zone, splitting segments where points are
+/// removed so lines aren't drawn through the zone. Returns the tracks and the
+/// number of points removed.
+pub fn hide_tracks(
+    tracks: Vec<Track>,
+    zone: &GpsPrivacy,
+) -> (Vec<Track>, usize) {
+    let mut removed: usize = 0;
+    let tracks = tracks
+        .into_iter()
+        .map(|t| {
+            let mut segments: Vec<Vec<TrackPoint>> = Vec::new();
+
+            for s in t.segments {
+                let mut current: Vec<TrackPoint> = Vec::new();
+
+                for p in s {
+                    if zone.contains(p.lat_lon()) {
+                        removed += 1;
+
+                        if !current.is_empty() {
+                            segments.push(current);
+                            current = Vec::new();
+                        }
+                    } else {
+                        current.push(p);
+                    }
+                }
+                if !current.is_empty() {
+                    segments.push(current);
+                }
+            }
+
+            Track {
+                name: t.name,
+                segments,
+            }
+        })
+        .filter(|t| !t.is_empty())
+        .collect();
+
+    (tracks, removed)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{hide_locations, hide_photos, hide_tracks};
+    use crate::{
+        config::GpsPrivacy,
+        models::{Location, Photo, PhotoFile, Track, TrackPoint},
+    };
+
+    fn zone() -> GpsPrivacy {
+        GpsPrivacy {
+            center: Location {
+                latitude: 46.0,
+                longitude: -116.0,
+                inferred: false,
+            },
+            miles: 1,
+            verify: true,
+        }
+    }
+
+    #[test]
+    fn hide_photos_test() {
+        let photo = |name: &str, latitude: f32| Photo {
+            file: PhotoFile {
+                name: name.to_owned(),
+            },
+            location: Some(Location {
+                latitude,
+                longitude: -116.0,
+                inferred: false,
+            }),
+            ..Photo::default()
+        };
+        // a hundredth degree of latitude is less than a mile
+        let mut photos =
+            vec![photo("home.jpg", 46.01), photo("away.jpg", 46.1)];
+
+        assert_eq!(hide_photos(&mut photos, &zone()), vec!["home.jpg"]);
+        assert!(photos[0].location.is_none());
+        assert!(photos[1].location.is_some());
+
+        let mut locations = vec![(-116.0, 46.0), (-116.0, 47.0)];
+
+        assert_eq!(hide_locations(&mut locations, &zone()), 1);
+        assert_eq!(locations, vec![(-116.0, 47.0)]);
+    }
+
+    #[test]
+    fn hide_tracks_test() {
+        let points = [46.2, 46.1, 46.01, 46.0, 46.01, 46.1]
+            .iter()
+            .map(|lat| TrackPoint::new(*lat, -116.0))
+            .collect();
+        let home = Track {
+            name: None,
+            segments: vec![vec![TrackPoint::new(46.0, -116.0)]],
+        };
+        let track = Track {
+            name: None,
+            segments: vec![points],
+        };
+        let (tracks, removed) = hide_tracks(vec![track, home], &zone());
+
+        assert_eq!(removed, 4);
+        assert_eq!(tracks.len(), 1);
+        assert_eq!(tracks[0].segments.len(), 2);
+        assert_eq!(tracks[0].segments[0].len(), 2);
+        assert_eq!(tracks[0].segments[1].len(), 1);
+    }
+}
@@ -0,0 +1,189 @@
+//! Static SVG elevation profile that can be inlined in post pages
+
+use super::{distance_feet, FEET_PER_MILE};
+use crate::{
+    html,
+    models::{Photo, Track},
+};
+
+const FEET_PER_METER: f64 = 3.280_84;
+
+/// Pixel height of the profile
+pub const PROFILE_HEIGHT: u16 = 120;
+
+/// Photos further than this from the track aren't marked on the profile
+const MAX_PHOTO_FEET: f64 = 1000.0;
+
+/// Space above and below the elevation line for labels
+const MARGIN: f64 = 16.0;
+
+/// Point along the profile
+struct Sample {
+    miles: f64,
+    feet: f64,
+    lat_lon: (f64, f64),
+}
+
+/// SVG with distance on the x axis and elevation on the y axis, marking where
+/// located photos were taken with links to their anchors. `None` is returned
+/// if tracks have fewer than two points with elevation.
+pub fn elevation_svg(
+    tracks: &[Track],
+    photos: &[Photo],
+    width: u16,
+) -> Option<String> {
+    let samples = samples(tracks);
+
+    if samples.len() < 2 {
+        return None;
+    }
+
+    let (w, h) = (width as f64, PROFILE_HEIGHT as f64);
+    let total_miles = samples.last().unwrap().miles.max(0.01);
+    let (low, high) = samples.iter().fold((f64::MAX, f64::MIN), |(l, h), s| {
+        (l.min(s.feet), h.max(s.feet))
+    });
+    // avoid dividing by zero for flat tracks
+    let range = (high - low).max(10.0);
+    let x = |miles: f64| miles / total_miles * w;
+    let y = |feet: f64| h - MARGIN - (feet - low) / range * (h - 2.0 * MARGIN);
+
+    let mut line = String::new();
+    let mut last_x = f64::MIN;
+
+    for s in samples.iter() {
+        let px = x(s.miles);
+
+        // skip points that would share a pixel column
+        if px - last_x >= 1.0 || s.miles == total_miles {
+            line.push_str(&format!(
+                "{}{:.1},{:.1}",
+                if line.is_empty() { "M" } else { " L" },
+                px,
+                y(s.feet)
+            ));
+            last_x = px;
+        }
+    }
+
+    let mut svg = format!(
+        "<svg class=\"elevation\" xmlns=\"http://www.w3.org/2000/svg\" \
+         viewBox=\"0 0 {w} {h}\" width=\"{w}\" height=\"{h}\" role=\"img\">\
+         <title>Elevation profile</title>\
+         <path class=\"area\" d=\"{line} L{w},{h} L0,{h} Z\" fill=\"#d2d8ac\"/>\
+         <path class=\"line\" d=\"{line}\" fill=\"none\" stroke=\"#6b7140\" \
+         stroke-width=\"1.5\"/>\
+         <text x=\"4\" y=\"12\" font-size=\"11\">{high} ft</text>\
+         <text x=\"4\" y=\"{bottom}\" font-size=\"11\">{low} ft</text>\
+         <text x=\"{right}\" y=\"{bottom}\" font-size=\"11\" \
+         text-anchor=\"end\">{miles:.1} mi</text>",
+        w = width,
+        h = PROFILE_HEIGHT,
+        line = line,
+        high = high.round(),
+        low = low.round(),
+        bottom = h - 4.0,
+        right = w - 4.0,
+        miles = total_miles
+    );
+
+    for (photo, sample) in photos.iter().filter_map(|p| {
+        p.location.as_ref().and_then(|l| {
+            nearest(&samples, (l.latitude as f64, l.longitude as f64))
+                .map(|s| (p, s))
+        })
+    }) {
+        svg.push_str(&format!(
+            "<a href=\"#{id}\"><circle cx=\"{x:.1}\" cy=\"{y:.1}\" r=\"4\" \
+             fill=\"#f22\" fill-opacity=\"0.7\"><title>Photo {index}</title>\
+             </circle></a>",
+            id = html::photo_id(photo.index),
+            x = x(sample.miles),
+            y = y(sample.feet),
+            index = photo.index
+        ));
+    }
+
+    svg.push_str("</svg>");
+    Some(svg)
+}
+
+/// Cumulative distance and elevation for every track point with elevation.
+/// Distance between segments isn't counted.
+fn samples(tracks: &[Track]) -> Vec<Sample> {
+    let mut samples: Vec<Sample> = Vec::new();
+    let mut miles = 0.0;
+
+    for segment in tracks.iter().flat_map(|t| t.segments.iter()) {
+        let mut previous: Option<(f64, f64)> = None;
+
+        for p in segment {
+            if let Some(prev) = previous {
+                miles += distance_feet(prev, p.lat_lon()) / FEET_PER_MILE;
+            }
+            previous = Some(p.lat_lon());
+
+            if let Some(meters) = p.elevation {
+                samples.push(Sample {
+                    miles,
+                    feet: meters as f64 * FEET_PER_METER,
+                    lat_lon: p.lat_lon(),
+                });
+            }
+        }
+    }
+    samples
+}
+
+/// Sample closest to `lat_lon` if within the maximum photo distance
+fn nearest(samples: &[Sample], lat_lon: (f64, f64)) -> Option<&Sample> {
+    samples
+        .iter()
+        .map(|s| (s, distance_feet(s.lat_lon, lat_lon)))
+        .filter(|(_, feet)| *feet <= MAX_PHOTO_FEET)
+        .min_by(|a, b| a.1.partial_cmp(&b.1).unwrap())
+        .map(|(s, _)| s)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::elevation_svg;
+    use crate::models::{Location, Photo, Track, TrackPoint};
+
+    #[test]
+    fn elevation_svg_test() {
+        let point = |latitude: f64, meters: f32| TrackPoint {
+            elevation: Some(meters),
+            ..TrackPoint::new(latitude, -116.0)
+        };
+        let track = Track {
+            name: None,
+            segments: vec![(0..10)
+                .map(|i| {
+                    point(46.0 + i as f64 * 0.01, 1000.0 + i as f32 * 10.0)
+                })
+                .collect()],
+        };
+        let photo = |index: u8, latitude: f32| Photo {
+            index,
+            location: Some(Location {
+                latitude,
+                longitude: -116.0,
+                inferred: false,
+            }),
+            ..Photo::default()
+        };
+        // second photo is miles from the track
+        let photos = vec![photo(7, 46.05), photo(8, 47.0)];
+        let svg = elevation_svg(&[track], &photos, 500).unwrap();
+
+        assert!(svg.starts_with("<svg class=\"elevation\""));
+        assert!(svg.contains("M0.0,104.0 L"));
+        assert!(svg.contains("3281 ft"));
+        assert!(svg.contains("<a href=\"#007\">"));
+        assert!(!svg.contains("#008"));
+        assert!(svg.ends_with("</svg>"));
+
+        assert_eq!(elevation_svg(&[Track::default()], &photos, 500), None);
+    }
+}
@@ -0,0 +1,279 @@
+//! Remove implausible points from GPS tracks and reduce their size
+
+use super::{distance_feet, EARTH_RADIUS_FEET, FEET_PER_MILE};
+use crate::{
+    config::GpsTrackConfig,
+    models::{Track, TrackPoint},
+};
+
+/// Speed implied by travel between timed points or `None` if either point is
+/// untimed
+pub fn speed_mph(a: &TrackPoint, b: &TrackPoint) -> Option<f64> {
+    let (start, end) = (a.time?, b.time?);
+    let hours = (end - start).num_milliseconds() as f64 / 3_600_000.0;
+    let miles = distance_feet(a.lat_lon(), b.lat_lon()) / FEET_PER_MILE;
+
+    if hours > 0.0 {
+        Some(miles / hours)
+    } else if miles > 0.0 {
+        // moved without time passing
+        Some(f64::INFINITY)
+    } else {
+        Some(0.0)
+    }
+}
+
+/// Drop points that couldn't be reached from their neighbours without
+/// exceeding the configured speed then remove segments with fewer than the
+/// minimum points. Tracks left without segments are removed.
+pub fn clean(tracks: Vec<Track>, config: &GpsTrackConfig) -> Vec<Track> {
+    tracks
+        .into_iter()
+        .map(|t| Track {
+            name: t.name,
+            segments: t
+                .segments
+                .into_iter()
+                .map(|s| remove_speeding(s, config.max_possible_speed_mph))
+                .filter(|s| s.len() >= config.min_track_points)
+                .collect(),
+        })
+        .filter(|t| !t.is_empty())
+        .collect()
+}
+
+/// Reduce the points in cleaned tracks to those needed to stay within the
+/// configured deviation
+pub fn simplify(tracks: Vec<Track>, config: &GpsTrackConfig) -> Vec<Track> {
+    tracks
+        .into_iter()
+        .map(|t| Track {
+            name: t.name,
+            segments: t
+                .segments
+                .into_iter()
+                .map(|s| douglas_peucker(s, config.max_point_deviation_feet))
+                .collect(),
+        })
+        .collect()
+}
+
+/// Remove points implying travel faster than `max_mph`, as when a point has
+/// been manually dragged in an editor. Isolated spikes are removed first by
+/// comparing each point with both neighbours so that a bad first point isn't
+/// used as the reference for everything after it.
+fn remove_speeding(points: Vec<TrackPoint>, max_mph: f32) -> Vec<TrackPoint> {
+    let too_fast = |a: &TrackPoint, b: &TrackPoint| matches!(speed_mph(a, b), Some(mph) if mph > max_mph as f64);
+    let spikes: Vec<bool> = (0..points.len())
+        .map(|i| {
+            let p = &points[i];
+            let prev = if i > 0 { points.get(i - 1) } else { None };
+            let next = points.get(i + 1);
+
+            match (prev, next) {
+                (Some(a), Some(b)) => {
+                    too_fast(a, p) && too_fast(p, b) && !too_fast(a, b)
+                }
+                // an end point is a spike if its only neighbour agrees with
+                // the point beyond it
+                (None, Some(b)) => {
+                    too_fast(p, b)
+                        && matches!(points.get(i + 2), Some(c) if !too_fast(b, c))
+                }
+                (Some(a), None) => {
+                    too_fast(a, p)
+                        && i >= 2
+                        && !too_fast(&points[i - 2], a)
+                }
+                (None, None) => false,
+            }
+        })
+        .collect();
+    let mut kept: Vec<TrackPoint> = Vec::with_capacity(points.len());
+
+    for (p, spike) in points.into_iter().zip(spikes) {
+        if spike || matches!(kept.last(), Some(last) if too_fast(last, &p)) {
+            continue;
+        }
+        kept.push(p);
+    }
+    kept
+}
+
+/// Keep only points that deviate more than `tolerance_feet` from the line
+/// between the points kept around them
+///
+/// https://en.wikipedia.org/wiki/Ramer–Douglas–Peucker_algorithm
+fn douglas_peucker(
+    points: Vec<TrackPoint>,
+    tolerance_feet: f32,
+) -> Vec<TrackPoint> {
+    if points.len() < 3 {
+        return points;
+    }
+
+    let tolerance = tolerance_feet as f64;
+    let origin = &points[0];
+    let xy: Vec<(f64, f64)> = points.iter().map(|p| plane(origin, p)).collect();
+    let mut keep = vec![false; points.len()];
+    // ranges to examine, handled with a stack rather than recursion so long
+    // tracks can't overflow
+    let mut ranges = vec![(0, points.len() - 1)];
+
+    keep[0] = true;
+    keep[points.len() - 1] = true;
+
+    while let Some((start, end)) = ranges.pop() {
+        let mut max_distance = 0.0;
+        let mut index = start;
+
+        for i in start + 1..end {
+            let d = segment_distance(xy[i], xy[start], xy[end]);
+
+            if d > max_distance {
+                max_distance = d;
+                index = i;
+            }
+        }
+
+        if max_distance > tolerance {
+            keep[index] = true;
+            ranges.push((start, index));
+            ranges.push((index, end));
+        }
+    }
+
+    points
+        .into_iter()
+        .zip(keep)
+        .filter_map(|(p, k)| if k { Some(p) } else { None })
+        .collect()
+}
+
+/// Position in feet east and north of `origin` using an equirectangular
+/// projection, which is accurate enough over the span of a track
+fn plane(origin: &TrackPoint, p: &TrackPoint) -> (f64, f64) {
+    let x = (p.longitude - origin.longitude).to_radians()
+        * origin.latitude.to_radians().cos();
+    let y = (p.latitude - origin.latitude).to_radians();
+
+    (x * EARTH_RADIUS_FEET, y * EARTH_RADIUS_FEET)
+}
+
+/// Distance from point `p` to the line segment between `a` and `b`
+fn segment_distance(p: (f64, f64), a: (f64, f64), b: (f64, f64)) -> f64 {
+    let (dx, dy) = (b.0 - a.0, b.1 - a.1);
+    let length_squared = dx * dx + dy * dy;
+    let t = if length_squared == 0.0 {
+        0.0
+    } else {
+        (((p.0 - a.0) * dx + (p.1 - a.1) * dy) / length_squared).clamp(0.0, 1.0)
+    };
+
+    ((p.0 - a.0 - t * dx).powi(2) + (p.1 - a.1 - t * dy).powi(2)).sqrt()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{clean, douglas_peucker, remove_speeding, simplify};
+    use crate::{
+        config::GpsTrackConfig,
+        models::{Track, TrackPoint},
+    };
+    use chrono::{DateTime, Duration};
+
+    /// Point `minutes` after a fixed start time
+    fn timed(latitude: f64, longitude: f64, minutes: i64) -> TrackPoint {
+        let start =
+            DateTime::parse_from_rfc3339("2019-06-02T15:00:00Z").unwrap();
+
+        TrackPoint {
+            time: Some(start + Duration::minutes(minutes)),
+            ..TrackPoint::new(latitude, longitude)
+        }
+    }
+
+    #[test]
+    fn remove_speeding_test() {
+        let points = vec![
+            timed(46.0, -116.0, 0),
+            // about 0.7 miles in a minute is about 41 mph
+            timed(46.01, -116.0, 1),
+            // 69 miles in a minute
+            timed(47.01, -116.0, 2),
+            timed(46.02, -116.0, 3),
+            // untimed points can't be checked
+            TrackPoint::new(46.03, -116.0),
+        ];
+        let kept = remove_speeding(points, 150.0);
+
+        assert_eq!(kept.len(), 4);
+        assert_eq!(kept[2].latitude, 46.02);
+    }
+
+    #[test]
+    fn remove_speeding_first_point_test() {
+        let points = vec![
+            // 69 miles from the rest of the track
+            timed(47.0, -116.0, 0),
+            timed(46.0, -116.0, 1),
+            timed(46.01, -116.0, 2),
+            timed(46.02, -116.0, 3),
+        ];
+        let kept = remove_speeding(points, 150.0);
+
+        assert_eq!(kept.len(), 3);
+        assert_eq!(kept[0].latitude, 46.0);
+        assert_eq!(kept[2].latitude, 46.02);
+    }
+
+    #[test]
+    fn douglas_peucker_test() {
+        // points along a meridian are collinear
+        let line: Vec<TrackPoint> = (0..10)
+            .map(|i| TrackPoint::new(46.0 + i as f64 * 0.001, -116.0))
+            .collect();
+        let simple = douglas_peucker(line, 1.0);
+
+        assert_eq!(simple.len(), 2);
+        assert_eq!(simple[1].latitude, 46.009);
+
+        let mut bend: Vec<TrackPoint> = (0..10)
+            .map(|i| TrackPoint::new(46.0 + i as f64 * 0.001, -116.0))
+            .collect();
+        // about 250 feet east
+        bend[5].longitude = -115.999;
+
+        assert_eq!(douglas_peucker(bend.clone(), 1.0).len(), 5);
+        assert_eq!(douglas_peucker(bend, 500.0).len(), 2);
+    }
+
+    #[test]
+    fn simplify_test() {
+        let config = GpsTrackConfig {
+            min_track_points: 3,
+            ..GpsTrackConfig::default()
+        };
+        let track = Track {
+            name: None,
+            segments: vec![
+                vec![
+                    TrackPoint::new(46.0, -116.0),
+                    TrackPoint::new(46.1, -116.0),
+                ],
+                (0..5)
+                    .map(|i| TrackPoint::new(46.0 + i as f64 * 0.001, -116.0))
+                    .collect(),
+            ],
+        };
+        let short = Track {
+            name: None,
+            segments: vec![vec![TrackPoint::new(46.0, -116.0)]],
+        };
+        let tracks = simplify(clean(vec![track, short], &config), &config);
+
+        assert_eq!(tracks.len(), 1);
+        assert_eq!(tracks[0].segments.len(), 1);
+        assert_eq!(tracks[0].point_count(), 2);
+    }
+}
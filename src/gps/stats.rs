@@ -0,0 +1,146 @@
+//! Trip statistics from GPS tracks
+
+use super::{distance_feet, simplify::speed_mph, FEET_PER_MILE};
+use crate::models::{Track, TrackStats};
+
+const FEET_PER_METER: f32 = 3.280_84;
+
+/// Slower travel between points is considered stopped, allowing for GPS drift
+const MIN_MOVING_MPH: f64 = 1.0;
+
+/// Smaller elevation changes are ignored as GPS noise until they add up
+const MIN_CLIMB_FEET: f32 = 10.0;
+
+/// Distance, elevation change and timing across all track segments or `None`
+/// if there are no segments with more than one point. Travel between segments
+/// isn't counted.
+pub fn track_stats(tracks: &[Track]) -> Option<TrackStats> {
+    let segments: Vec<_> = tracks
+        .iter()
+        .flat_map(|t| t.segments.iter())
+        .filter(|s| s.len() > 1)
+        .collect();
+
+    if segments.is_empty() {
+        return None;
+    }
+
+    let mut stats = TrackStats::default();
+    let mut feet = 0.0;
+
+    for s in segments {
+        // elevation last counted toward gain or loss
+        let mut base: Option<f32> = None;
+
+        for p in s {
+            if let Some(e) = p.elevation.map(|e| e * FEET_PER_METER) {
+                match base {
+                    Some(b) if e - b >= MIN_CLIMB_FEET => {
+                        stats.elevation_gain_feet += e - b;
+                        base = Some(e);
+                    }
+                    Some(b) if b - e >= MIN_CLIMB_FEET => {
+                        stats.elevation_loss_feet += b - e;
+                        base = Some(e);
+                    }
+                    Some(_) => (),
+                    None => base = Some(e),
+                }
+            }
+        }
+
+        for pair in s.windows(2) {
+            let (a, b) = (&pair[0], &pair[1]);
+
+            feet += distance_feet(a.lat_lon(), b.lat_lon());
+
+            if let (Some(from), Some(to), Some(mph)) =
+                (a.time, b.time, speed_mph(a, b))
+            {
+                if mph >= MIN_MOVING_MPH && mph.is_finite() {
+                    stats.moving_seconds += (to - from).num_seconds();
+                }
+            }
+        }
+
+        for p in s {
+            if let Some(e) = p.elevation.map(|e| e * FEET_PER_METER) {
+                stats.max_elevation_feet =
+                    Some(stats.max_elevation_feet.map_or(e, |max| max.max(e)));
+            }
+            if let Some(t) = p.time {
+                stats.start = Some(stats.start.map_or(t, |start| start.min(t)));
+                stats.end = Some(stats.end.map_or(t, |end| end.max(t)));
+            }
+        }
+    }
+
+    stats.distance_miles = (feet / FEET_PER_MILE) as f32;
+
+    Some(stats)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::track_stats;
+    use crate::models::{Track, TrackPoint};
+    use chrono::{DateTime, Duration};
+
+    #[test]
+    fn track_stats_test() {
+        let start =
+            DateTime::parse_from_rfc3339("2019-06-02T09:00:00-06:00").unwrap();
+        let point = |latitude: f64, meters: f32, minutes: i64| TrackPoint {
+            elevation: Some(meters),
+            time: Some(start + Duration::minutes(minutes)),
+            ..TrackPoint::new(latitude, -116.0)
+        };
+        let track = Track {
+            name: None,
+            segments: vec![
+                vec![
+                    point(46.0, 1000.0, 0),
+                    // about 6.9 miles in an hour
+                    point(46.1, 1100.0, 60),
+                    // stopped for half an hour
+                    point(46.1, 1050.0, 90),
+                ],
+                vec![point(46.5, 1200.0, 120)],
+            ],
+        };
+
+        assert_eq!(track_stats(&[]), None);
+
+        let stats = track_stats(&[track]).unwrap();
+
+        assert!((stats.distance_miles - 6.9).abs() < 0.1);
+        assert!((stats.elevation_gain_feet - 328.1).abs() < 0.1);
+        assert!((stats.elevation_loss_feet - 164.0).abs() < 0.1);
+        // single point segments are ignored
+        assert!((stats.max_elevation_feet.unwrap() - 3608.9).abs() < 0.1);
+        assert_eq!(stats.moving_seconds, 3600);
+        assert_eq!(stats.start, Some(start));
+        assert_eq!(stats.end, Some(start + Duration::minutes(90)));
+    }
+
+    #[test]
+    fn elevation_noise_test() {
+        // meters alternating by about 6.6 feet then climbing about 33 feet
+        let elevations = [1000.0, 1002.0, 1000.0, 1002.0, 1000.0, 1010.0];
+        let track = Track {
+            name: None,
+            segments: vec![elevations
+                .iter()
+                .enumerate()
+                .map(|(i, e)| TrackPoint {
+                    elevation: Some(*e),
+                    ..TrackPoint::new(46.0 + i as f64 * 0.001, -116.0)
+                })
+                .collect()],
+        };
+        let stats = track_stats(&[track]).unwrap();
+
+        assert!((stats.elevation_gain_feet - 32.8).abs() < 0.1);
+        assert_eq!(stats.elevation_loss_feet, 0.0);
+    }
+}
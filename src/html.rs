@@ -3,10 +3,11 @@ use crate::{
     models::{Category, CategoryKind},
     tools::slugify,
 };
-use chrono::{DateTime, FixedOffset};
+use chrono::{DateTime, FixedOffset, Local};
 use hashbrown::HashMap;
 use lazy_static::*;
 use regex::{Captures, NoExpand, Regex};
+use std::fmt::Display;
 
 lazy_static! {
     static ref TRAILING_SPACE: Regex = Regex::new(r"[\r\n\s]*$").unwrap();
@@ -26,6 +27,24 @@ pub fn date_string(d: DateTime<FixedOffset>) -> String {
     d.format("%B %e, %Y").to_string()
 }
 
+/// Hour and minute (7:48 AM) in the timezone of `at`, usually when the post
+/// photos were taken, since GPX times are usually UTC. The local timezone is
+/// used if there is no `at`.
+pub fn time_string(
+    d: DateTime<FixedOffset>,
+    at: Option<DateTime<FixedOffset>>,
+) -> String {
+    let offset =
+        at.map_or_else(|| *d.with_timezone(&Local).offset(), |a| *a.offset());
+
+    d.with_timezone(&offset).format("%-I:%M %p").to_string()
+}
+
+/// Zero-padded photo index used for anchors within post pages, such as `007`
+pub fn photo_id(index: impl Display) -> String {
+    format!("{:03}", index)
+}
+
 /// HTML tag for post category icon
 pub fn category_icon(kind: &CategoryKind, config: &CategoryIcon) -> String {
     let icon = match kind.to_string().to_lowercase().as_str() {
@@ -376,6 +395,15 @@ mod tests {
         );
     }
 
+    #[test]
+    fn time_in_photo_timezone() {
+        let utc = DateTime::parse_from_rfc3339("2019-06-02T15:48:00Z").unwrap();
+        let taken =
+            DateTime::parse_from_rfc3339("2019-06-02T08:00:00-06:00").unwrap();
+
+        assert_eq!(time_string(utc, Some(taken)), "9:48 AM");
+    }
+
     #[test]
     fn fraction_html() {
         assert_eq!(fraction("1/2"), "<sup>1</sup>&frasl;<sub>2</sub>");
@@ -2,10 +2,11 @@
 // brew install webp
 use crate::{
     config::PhotoConfig,
-    models::{suffix, Photo},
+    image::{resize::render_sizes, NativeResize, PhotoResizer},
+    models::Photo,
 };
-use colored::*;
-use std::process::{self, Command};
+use lazy_static::*;
+use std::{path::Path, process::Command};
 
 /// Create vector of owned strings
 macro_rules! string_vec {
@@ -14,28 +15,61 @@ macro_rules! string_vec {
 
 //cwebp -near_lossless 0 -mt -m 6 -noalpha -sharp_yuv -metadata icc 028.tif -o 028_test.webp
 
-/// https://developers.google.com/speed/webp/docs/cwebp
-pub fn create_sizes(path: &str, p: &Photo, c: &PhotoConfig) {
-    let size = &c.size.render;
-    create_size(path, p, c, size.large, suffix::LARGE, false);
-    create_size(path, p, c, size.medium, suffix::MEDIUM, false);
-    create_size(path, p, c, size.small, suffix::SMALL, false);
-    create_size(path, p, c, size.thumb, suffix::THUMB, true);
+/// Resizes photos with `cwebp`, handing other formats to `NativeResize`
+pub struct CWebP;
+
+impl PhotoResizer for CWebP {
+    fn create_sizes(
+        &self,
+        folder: &Path,
+        photo: &Photo,
+        config: &PhotoConfig,
+        formats: &[&str],
+    ) -> Vec<String> {
+        let (webp, other): (Vec<&str>, Vec<&str>) = formats
+            .iter()
+            .partition(|ext| ext.eq_ignore_ascii_case(".webp"));
+        let mut errors =
+            NativeResize.create_sizes(folder, photo, config, &other);
+
+        for ext in webp {
+            for (size, suffix, square) in render_sizes(config).iter() {
+                if let Err(e) = create_size(
+                    folder, photo, config, *size, suffix, ext, *square,
+                ) {
+                    errors.push(e);
+                }
+            }
+        }
+        errors
+    }
+}
+
+/// Whether `cwebp` can be run on this machine
+pub fn is_installed() -> bool {
+    lazy_static! {
+        static ref INSTALLED: bool =
+            Command::new("cwebp").arg("-version").output().is_ok();
+    }
+    *INSTALLED
 }
 
+/// Write one size of `photo` in `path`, returning any `cwebp` error output
+///
+/// https://developers.google.com/speed/webp/docs/cwebp
 fn create_size(
-    path: &str,
+    path: &Path,
     photo: &Photo,
     config: &PhotoConfig,
     size: u16,
     suffix: &'static str,
+    ext: &str,
     make_square: bool,
-) {
+) -> Result<(), String> {
     let setting =
         |key: &str, value: &str| string_vec![format!("-{}", key), value];
 
-    let file_name =
-        || format!("{:03}_{}{}", photo.index, suffix, config.output_ext);
+    let file_name = || format!("{:03}_{}{}", photo.index, suffix, ext);
 
     // The cwebp parameter is `-resize width height`. Leaving either width or
     // height `0` causes it to be computed to preserve the aspect ratio.
@@ -67,17 +101,11 @@ fn create_size(
         .args(setting("o", &file_name()))   // output file
         .output()
     {
-        Ok(out) => {
-            if let Ok(err) = String::from_utf8(out.stderr) {
-                if !err.is_empty() {
-                    println!("   {}", err.red());
-                    process::exit(1);
-                }
-            }
-        }
-        Err(e) => {
-            println!("   {:?}", e);
-            process::exit(1);
-        }
-    };
+        Ok(out) => match String::from_utf8(out.stderr) {
+            Ok(err) if !err.trim().is_empty() => Err(err.trim().to_string()),
+            _ if !out.status.success() => Err(format!("{}", out.status)),
+            _ => Ok(()),
+        },
+        Err(e) => Err(format!("{:?}", e)),
+    }
 }
@@ -4,6 +4,7 @@ use crate::{
     config::PhotoConfig,
     deserialize::{date_time_string, string_number, string_sequence},
     html,
+    image::MetadataSource,
     models::{
         Camera, ExposureMode, Location, Photo, PhotoFile, SizeCollection,
     },
@@ -11,8 +12,17 @@ use crate::{
 };
 use chrono::{DateTime, FixedOffset};
 use colored::*;
+use lazy_static::*;
 use serde::Deserialize;
-use std::{path::Path, process::Command};
+use std::{
+    cell::RefCell,
+    io::{self, BufRead, BufReader, Write},
+    mem,
+    path::Path,
+    process::{Child, ChildStdin, ChildStdout, Command, Stdio},
+    sync::mpsc::{self, Receiver},
+    thread,
+};
 
 #[derive(Deserialize, Debug)]
 pub struct ExifToolOutput {
@@ -64,14 +74,6 @@ pub struct ExifToolOutput {
     #[serde(rename = "FOV")]
     field_of_view: Option<f32>,
 
-    /// When the *file*, not the photo, was created
-    #[serde(
-        default,
-        rename = "FileCreateDate",
-        deserialize_with = "date_time_string"
-    )]
-    file_created_on: Option<DateTime<FixedOffset>>,
-
     #[serde(rename = "FileName")]
     file_name: String,
 
@@ -172,139 +174,330 @@ impl PartialEq for ExifToolOutput {
 
 impl Eq for ExifToolOutput {}
 
-/// Execute exif_tool for each image file in given `path` and capture output as
-/// `Photo` structs
-pub fn parse_dir(path: &Path, config: &PhotoConfig) -> Vec<Photo> {
-    let pattern = format!("*{}", config.source_ext);
-    let mut photos: Vec<Photo> = Vec::new();
-
-    for i in read_dir(&path, &pattern) {
-        // Photo index based on its file name pattern
-        let index =
-            pos_from_name(&config.capture_index, &i.file_name).unwrap_or(0);
-
-        if index == 0 {
-            println!(
-                "   {} {}",
-                "failed to infer index of".red(),
-                i.file_name.red()
-            );
-            continue;
-        }
+/// Reads metadata with a single ExifTool process that is started when first
+/// needed and stays open until dropped
+#[derive(Default)]
+pub struct ExifTool {
+    session: RefCell<Option<Session>>,
+}
 
-        let mut photo = Photo {
-            file: PhotoFile {
-                name: i.file_name.clone(),
-                created: i.file_created_on.map_or(0, |d| d.timestamp()),
-            },
-            title: i.title,
-            artist: i.artist,
-            caption: i.caption.map(|s| html::caption(&s)),
-            software: i.software,
-            tags: i.tags,
-            index,
-            size: SizeCollection::from(i.width, i.height, index, config),
-            date_taken: i.taken_on.or(i.created_on),
-            ..Photo::default()
-        };
+impl MetadataSource for ExifTool {
+    /// Read ExifTool output for the named `files` in `path` and capture it as
+    /// `Photo` structs
+    fn parse_files(
+        &self,
+        path: &Path,
+        files: &[String],
+        config: &PhotoConfig,
+    ) -> Vec<Photo> {
+        self.read_files(path, files)
+            .into_iter()
+            .filter_map(|i| to_photo(i, config))
+            .collect()
+    }
+}
+
+impl ExifTool {
+    /// Read metadata for `files` in `path`, starting the ExifTool session if
+    /// it isn't already running
+    fn read_files(&self, path: &Path, files: &[String]) -> Vec<ExifToolOutput> {
+        if files.is_empty() {
+            return Vec::new();
+        }
+        let mut session = self.session.borrow_mut();
+
+        if session.is_none() {
+            match Session::start() {
+                Ok(s) => *session = Some(s),
+                Err(e) => {
+                    println!("   {} {:?}", "failed to start ExifTool".red(), e);
+                    return Vec::new();
+                }
+            }
+        }
 
-        if let Some(make) = &i.camera_make {
-            photo.camera = Some(Camera {
-                name: i.camera_model.unwrap_or_else(|| make.clone()),
-                compensation: i.exposure_compensation,
-                shutter_speed: i.shutter_speed,
-                mode: i.exposure_mode,
-                aperture: i.aperture,
-                focal_length: i.focal_length,
-                iso: i.iso,
-                lens: i.lens,
-            });
+        let paths: Vec<String> = files
+            .iter()
+            .map(|f| path.join(f).to_string_lossy().to_string())
+            .collect();
+        let mut args: Vec<&str> = vec!["-json", "-quiet"];
+
+        args.extend_from_slice(&FIELDS);
+        args.extend(paths.iter().map(|p| p.as_str()));
+
+        match session.as_mut().unwrap().execute(&args) {
+            Ok((text, errors)) => {
+                for e in errors.lines().filter(|l| !l.trim().is_empty()) {
+                    println!("   {} {}", "ExifTool".red(), e.trim());
+                }
+                parse_json(&text)
+            }
+            Err(e) => {
+                println!("   {} {:?}", "failed to retrieve EXIF".red(), e);
+                // restart the session on the next request
+                *session = None;
+                Vec::new()
+            }
         }
+    }
+}
 
-        if i.latitude.is_some() && i.longitude.is_some() {
-            let loc = Location {
-                latitude: i.latitude.unwrap(),
-                longitude: i.longitude.unwrap(),
-            };
+/// ExifTool process reading arguments from stdin (`-stay_open True -@ -`)
+/// so it needn't be started for every folder
+///
+/// https://exiftool.org/exiftool_pod.html#stay_open-FLAG
+struct Session {
+    process: Child,
+    input: ChildStdin,
+    output: BufReader<ChildStdout>,
+    /// Error text for each request, read on its own thread
+    errors: Receiver<String>,
+    /// Number of the last request, echoed by ExifTool as `{ready<number>}`
+    /// after its output
+    request: usize,
+}
 
-            if loc.is_valid() {
-                photo.location = Some(loc);
+impl Session {
+    fn start() -> io::Result<Self> {
+        let mut command = Command::new("exiftool");
+
+        command.args(["-stay_open", "True", "-@", "-"]);
+        Self::spawn(command)
+    }
+
+    /// Run `command` with piped input, output and errors
+    fn spawn(mut command: Command) -> io::Result<Self> {
+        let mut process = command
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()?;
+
+        let input = process.stdin.take().unwrap();
+        let output = BufReader::new(process.stdout.take().unwrap());
+        let errors =
+            drain_errors(BufReader::new(process.stderr.take().unwrap()));
+
+        Ok(Session {
+            process,
+            input,
+            output,
+            errors,
+            request: 0,
+        })
+    }
+
+    /// Send `args` then read output and error text until ExifTool signals the
+    /// request is complete
+    fn execute(&mut self, args: &[&str]) -> io::Result<(String, String)> {
+        self.request += 1;
+
+        let ready = write_request(&mut self.input, args, self.request)?;
+        let output = read_response(&mut self.output, &ready)?;
+        let errors = self.errors.recv().map_err(|_| {
+            io::Error::new(io::ErrorKind::UnexpectedEof, "ExifTool exited")
+        })?;
+
+        Ok((output, errors))
+    }
+}
+
+/// Read error text on its own thread so ExifTool can't block on a full
+/// stderr pipe while output is read from stdout. Text before each
+/// `{ready<number>}` line is sent as the errors for one request.
+fn drain_errors<R: BufRead + Send + 'static>(
+    mut reader: R,
+) -> Receiver<String> {
+    let (sender, receiver) = mpsc::channel();
+
+    thread::spawn(move || {
+        let mut text = String::new();
+        let mut line = String::new();
+
+        while matches!(reader.read_line(&mut line), Ok(n) if n > 0) {
+            let marker = line.trim_end();
+
+            if marker.starts_with("{ready") && marker.ends_with('}') {
+                if sender.send(mem::take(&mut text)).is_err() {
+                    break;
+                }
+            } else {
+                text.push_str(&line);
             }
+            line.clear();
         }
+    });
+
+    receiver
+}
 
-        photos.push(photo);
+/// Write `args`, one per line, followed by commands that mark the end of the
+/// request's output and error text with the returned line
+fn write_request<W: Write>(
+    input: &mut W,
+    args: &[&str],
+    request: usize,
+) -> io::Result<String> {
+    for arg in args {
+        writeln!(input, "{}", arg)?;
     }
+    // `-echo4` writes to stderr after the other output, like `{ready}` does
+    // to stdout
+    writeln!(input, "-echo4\n{{ready{}}}", request)?;
+    writeln!(input, "-execute{}", request)?;
+    input.flush()?;
 
-    photos
+    Ok(format!("{{ready{}}}", request))
 }
 
-pub fn read_dir(path: &Path, file_pattern: &str) -> Vec<ExifToolOutput> {
-    // exiftool *.jpg -json -quiet -coordFormat %.6f
-    // exiftool 002.jpg -json -quiet -coordFormat %.6f -ExposureProgram#
-    // exiftool *.jpg -json -quiet -Aperture# -ColorTemperature# -DateTimeCreated -FocalLength# -FOV# -Keywords# -ShutterSpeed
-
-    // suffix field name with # to disable ExifTool formatting
-    let output = match Command::new("exiftool")
-        .current_dir(path.to_string_lossy().to_string())
-        .arg(file_pattern)
-        .arg("-json")
-        .arg("-quiet")
-        .arg("-Aperture#")
-        .arg("-Artist")
-        .arg("-City")
-        .arg("-ColorTemperature#")
-        .arg("-Copyright")
-        .arg("-DateTimeCreated")
-        .arg("-CreateDate")
-        .arg("-FileCreateDate")
-        // Offsets seem only to be present for software modified dates
-        //.arg("-OffsetTimeOriginal")
-        //.arg("-OffsetTimeDigitized")
-        .arg("-Description")
-        .arg("-ExposureCompensation")
-        .arg("-ExposureProgram#")
-        .arg("-FileName")
-        .arg("-FocalLength#")
-        .arg("-FOV#")
-        .arg("-GPSLatitude#")
-        .arg("-GPSLongitude#")
-        .arg("-ImageHeight")
-        .arg("-ImageWidth")
-        .arg("-ISO")
-        .arg("-Keywords")
-        .arg("-Lens")
-        .arg("-Make")
-        .arg("-MaxApertureValue")
-        .arg("-Model")
-        .arg("-ProfileDescription")
-        .arg("-ShutterSpeed")
-        .arg("-Software")
-        .arg("-State")
-        .arg("-Title")
-        .arg("-UsageTerms")
-        .output()
-    {
-        Ok(out) => out,
-        _ => {
-            println!("   {}", "failed to retrieve EXIF".red());
-            return Vec::new();
+/// Text read until the `ready` line, which isn't included
+fn read_response<R: BufRead>(
+    reader: &mut R,
+    ready: &str,
+) -> io::Result<String> {
+    let mut text = String::new();
+    let mut line = String::new();
+
+    loop {
+        line.clear();
+
+        if reader.read_line(&mut line)? == 0 {
+            return Err(io::Error::new(
+                io::ErrorKind::UnexpectedEof,
+                "ExifTool exited",
+            ));
+        }
+        if line.trim_end() == ready {
+            return Ok(text);
         }
+        text.push_str(&line);
+    }
+}
+
+impl Drop for Session {
+    /// Ask ExifTool to exit and wait for it so no process is left running
+    fn drop(&mut self) {
+        let _ = writeln!(self.input, "-stay_open\nFalse");
+        let _ = self.input.flush();
+        let _ = self.process.wait();
+    }
+}
+
+/// Convert ExifTool output to a `Photo` or `None` if its index can't be
+/// inferred from the file name
+fn to_photo(i: ExifToolOutput, config: &PhotoConfig) -> Option<Photo> {
+    // Photo index based on its file name pattern
+    let index = pos_from_name(&config.capture_index, &i.file_name).unwrap_or(0);
+
+    if index == 0 {
+        println!(
+            "   {} {}",
+            "failed to infer index of".red(),
+            i.file_name.red()
+        );
+        return None;
+    }
+
+    let mut photo = Photo {
+        file: PhotoFile {
+            name: i.file_name.clone(),
+        },
+        title: i.title,
+        artist: i.artist,
+        caption: i.caption.map(|s| html::caption(&s)),
+        software: i.software,
+        tags: i.tags,
+        city: i.city,
+        state: i.state,
+        usage_terms: i.usage_terms,
+        index,
+        size: SizeCollection::from(i.width, i.height, index, config),
+        date_taken: i.taken_on.or(i.created_on),
+        ..Photo::default()
     };
 
-    let text = match String::from_utf8(output.stdout) {
-        Ok(text) => text,
-        _ => {
-            println!("   {}", "Failed to convert EXIF output to UTF-8".red());
-            return Vec::new();
+    if let Some(make) = &i.camera_make {
+        photo.camera = Some(Camera {
+            name: i.camera_model.unwrap_or_else(|| make.clone()),
+            compensation: i.exposure_compensation,
+            shutter_speed: i.shutter_speed,
+            mode: i.exposure_mode,
+            aperture: i.aperture,
+            focal_length: i.focal_length,
+            iso: i.iso,
+            lens: i.lens,
+        });
+    }
+
+    if i.latitude.is_some() && i.longitude.is_some() {
+        let loc = Location {
+            latitude: i.latitude.unwrap(),
+            longitude: i.longitude.unwrap(),
+            ..Location::default()
+        };
+
+        if loc.is_valid() {
+            photo.location = Some(loc);
         }
-    };
+    }
 
-    if text.is_empty() {
+    Some(photo)
+}
+
+/// Whether ExifTool can be run on this machine
+pub fn is_installed() -> bool {
+    lazy_static! {
+        static ref INSTALLED: bool =
+            Command::new("exiftool").arg("-ver").output().is_ok();
+    }
+    *INSTALLED
+}
+
+/// Fields to read with `#` suffix to disable ExifTool formatting
+///
+/// `exiftool *.jpg -json -quiet -Aperture# -ColorTemperature# -FocalLength#`
+const FIELDS: [&str; 29] = [
+    "-Aperture#",
+    "-Artist",
+    "-City",
+    "-ColorTemperature#",
+    "-Copyright",
+    "-DateTimeCreated",
+    "-CreateDate",
+    // Offsets seem only to be present for software modified dates
+    //"-OffsetTimeOriginal",
+    //"-OffsetTimeDigitized",
+    "-Description",
+    "-ExposureCompensation",
+    "-ExposureProgram#",
+    "-FileName",
+    "-FocalLength#",
+    "-FOV#",
+    "-GPSLatitude#",
+    "-GPSLongitude#",
+    "-ImageHeight",
+    "-ImageWidth",
+    "-ISO",
+    "-Keywords",
+    "-Lens",
+    "-Make",
+    "-MaxApertureValue",
+    "-Model",
+    "-ProfileDescription",
+    "-ShutterSpeed",
+    "-Software",
+    "-State",
+    "-Title",
+    "-UsageTerms",
+];
+
+fn parse_json(text: &str) -> Vec<ExifToolOutput> {
+    if text.trim().is_empty() {
         println!("   {}", "EXIF JSON is empty".red());
         return Vec::new();
     }
 
-    match serde_json::from_str::<Vec<ExifToolOutput>>(&text) {
+    match serde_json::from_str::<Vec<ExifToolOutput>>(text) {
         Ok(info) => info,
         Err(e) => {
             println!("   {}", "unable to parse EXIF JSON".red());
@@ -317,41 +510,44 @@ pub fn read_dir(path: &Path, file_pattern: &str) -> Vec<ExifToolOutput> {
 
 #[cfg(test)]
 mod tests {
-    use super::ExifToolOutput;
-    use crate::models::ExposureMode;
+    use super::{
+        read_response, to_photo, write_request, ExifToolOutput, Session,
+    };
+    use crate::{config::PhotoConfig, models::ExposureMode};
     use chrono::DateTime;
+    use regex::Regex;
+
+    const JSON: &str = r#"[{
+        "SourceFile": "001.jpg",
+        "Aperture": 2.2,
+        "Artist": "Jason Abbott",
+        "ColorTemperature": 5800,
+        "Copyright": "© Copyright 2017 Jason Abbott",
+        "DateTimeCreated": "2017:08:06 11:25:41",
+        "FileCreateDate": "2020:04:22 23:39:28-06:00",
+        "Description": "We worked all day yesterday, and various days before that, to get the bikes in working order. A hot and hazy day isn’t my first choice to ride the Boise Ridge but Nick and I want to put the bikes through their paces before a four-day ride in a few weeks.",
+        "ExposureProgram": 2,
+        "FileName": "001.jpg",
+        "FocalLength": 4.15,
+        "FOV": 63.6549469203798,
+        "GPSLatitude": 43.579192,
+        "GPSLongitude": -116.173061,
+        "ImageHeight": 75,
+        "ImageWidth": 100,
+        "ISO": 25,
+        "Keywords": ["Gas Station","KTM 500 XC-W","Motorcycle"],
+        "Lens": "iPhone 6s back camera 4.15mm f/2.2",
+        "Make": "Apple",
+        "Model": "iPhone 6s",
+        "ProfileDescription": "ProPhoto RGB",
+        "ShutterSpeed": "1/500",
+        "Software": "Adobe Photoshop Lightroom Classic 9.2 (Windows)",
+        "Title": "Fuel stop",
+        "UsageTerms": "All Rights Reserved"
+      }]"#;
 
     #[test]
     fn deserialize_test() {
-        let json = r#"[{
-            "SourceFile": "001.jpg",
-            "Aperture": 2.2,
-            "Artist": "Jason Abbott",
-            "ColorTemperature": 5800,
-            "Copyright": "© Copyright 2017 Jason Abbott",
-            "DateTimeCreated": "2017:08:06 11:25:41",
-            "FileCreateDate": "2020:04:22 23:39:28-06:00",
-            "Description": "We worked all day yesterday, and various days before that, to get the bikes in working order. A hot and hazy day isn’t my first choice to ride the Boise Ridge but Nick and I want to put the bikes through their paces before a four-day ride in a few weeks.",
-            "ExposureProgram": 2,
-            "FileName": "001.jpg",
-            "FocalLength": 4.15,
-            "FOV": 63.6549469203798,
-            "GPSLatitude": 43.579192,
-            "GPSLongitude": -116.173061,
-            "ImageHeight": 75,
-            "ImageWidth": 100,
-            "ISO": 25,
-            "Keywords": ["Gas Station","KTM 500 XC-W","Motorcycle"],
-            "Lens": "iPhone 6s back camera 4.15mm f/2.2",
-            "Make": "Apple",
-            "Model": "iPhone 6s",
-            "ProfileDescription": "ProPhoto RGB",
-            "ShutterSpeed": "1/500",
-            "Software": "Adobe Photoshop Lightroom Classic 9.2 (Windows)",
-            "Title": "Fuel stop",
-            "UsageTerms": "All Rights Reserved"
-          }]"#;
-
         let target = vec![ExifToolOutput {
             file_name: "001.jpg".to_owned(),
             artist: Some("Jason Abbott".to_owned()),
@@ -375,7 +571,6 @@ mod tests {
             camera_model: Some("iPhone 6s".to_owned()),
             taken_on: Some(DateTime::parse_from_rfc3339("2017-08-06T11:25:41-06:00").unwrap()),
             created_on: None,
-            file_created_on: Some(DateTime::parse_from_rfc3339("2020-04-22T22:39:28-06:00").unwrap()),
             latitude: Some(43.579192),
             longitude: Some(-116.173061),
             color_profile: Some("ProPhoto RGB".to_owned()),
@@ -385,7 +580,7 @@ mod tests {
             height: 75
         }];
 
-        match serde_json::from_str::<Vec<ExifToolOutput>>(&json) {
+        match serde_json::from_str::<Vec<ExifToolOutput>>(JSON) {
             Ok(exif) => assert_eq!(exif, target),
             Err(e) => {
                 eprintln!("{:?}", e);
@@ -393,4 +588,91 @@ mod tests {
             }
         }
     }
+
+    #[test]
+    fn photo_test() {
+        let mut config = PhotoConfig::fixture();
+        config.capture_index = Regex::new(r"(\d{3})\.jpg$").unwrap();
+
+        let exif: Vec<ExifToolOutput> = serde_json::from_str(JSON).unwrap();
+        let photo = exif
+            .into_iter()
+            .filter_map(|i| to_photo(i, &config))
+            .next()
+            .unwrap();
+
+        assert_eq!(photo.index, 1);
+        assert_eq!(photo.title, Some("Fuel stop".to_owned()));
+        assert_eq!(photo.tags.len(), 3);
+        assert!(photo.caption.unwrap().starts_with("<p>We worked all day"));
+        assert_eq!(photo.size.original.width, 100);
+        assert_eq!(photo.size.original.height, 75);
+
+        let camera = photo.camera.unwrap();
+
+        assert_eq!(camera.name, "iPhone 6s");
+        assert_eq!(camera.shutter_speed, Some("1/500".to_owned()));
+        assert_eq!(camera.mode, ExposureMode::ProgramAE);
+        assert_eq!(camera.iso, Some(25));
+
+        let location = photo.location.unwrap();
+
+        assert!((location.latitude - 43.579_192).abs() < 0.000_01);
+    }
+
+    #[test]
+    fn request_framing_test() {
+        let mut input: Vec<u8> = Vec::new();
+        let ready = write_request(&mut input, &["-json", "a.jpg"], 3).unwrap();
+
+        assert_eq!(ready, "{ready3}");
+        assert_eq!(
+            String::from_utf8(input).unwrap(),
+            "-json\na.jpg\n-echo4\n{ready3}\n-execute3\n"
+        );
+
+        let mut output = "[{}]\n{ready3}\n[{}]\n{ready4}\n".as_bytes();
+
+        assert_eq!(read_response(&mut output, "{ready3}").unwrap(), "[{}]\n");
+        // the next response is left for the next request
+        assert_eq!(read_response(&mut output, "{ready4}").unwrap(), "[{}]\n");
+        assert!(read_response(&mut output, "{ready5}").is_err());
+    }
+
+    /// Stand-in for ExifTool that writes far more than a pipe buffer of
+    /// warnings before its output
+    #[cfg(unix)]
+    #[test]
+    fn heavy_stderr_test() {
+        let script = r#"
+            while read line; do
+                case "$line" in
+                    -execute*)
+                        n=${line#-execute}
+                        i=0
+                        while [ $i -lt 4000 ]; do
+                            echo "Warning: too much to say about line $i" >&2
+                            i=$((i+1))
+                        done
+                        echo '[{}]'
+                        echo "{ready$n}"
+                        echo "{ready$n}" >&2
+                        ;;
+                    -stay_open) exit 0 ;;
+                esac
+            done
+        "#;
+        let mut command = std::process::Command::new("sh");
+
+        command.args(["-c", script]);
+
+        let mut session = Session::spawn(command).unwrap();
+
+        for _ in 0..2 {
+            let (output, errors) = session.execute(&["a.jpg"]).unwrap();
+
+            assert_eq!(output, "[{}]\n");
+            assert_eq!(errors.lines().count(), 4000);
+        }
+    }
 }
@@ -1,201 +1,189 @@
 use crate::{
     config::PhotoConfig,
+    deserialize::parse_date_time,
     html,
+    image::{resize::render_sizes, MetadataSource, PhotoResizer},
     models::{
-        suffix, Camera, ExposureMode, Location, Photo, PhotoFile,
-        SizeCollection,
+        Camera, ExposureMode, Location, Photo, PhotoFile, SizeCollection,
+    },
+    num_traits::FromPrimitive,
+    tools::{
+        folder_name, parse_rational, pos_from_name, rational_parts,
+        reduce_fraction,
     },
-    tools::{folder_name, pos_from_name},
 };
 use chrono::{DateTime, FixedOffset};
 use colored::*;
-use encoding::{all::*, DecoderTrap, Encoding};
 use serde::Deserialize;
-use std::{
-    path::Path,
-    process::{self, Command},
-};
-
-/// Create vector of owned strings
-macro_rules! string_vec {
-    ($($x:expr),*) => (vec![$($x.to_string()),*]);
-}
+use std::{path::Path, process::Command};
 
-// magic convert 001.jpg test.webp
 // magick convert 028.tif -quiet -define webp:method=6 webp:thread-level=true
 //  ( +clone -write *-large.webp +delete )
 //  ( +clone -resize 1024x -write *-regular.webp +delete )
 //  ( +clone -resize 320x -write *-small.webp +delete )
 //  -resize 256x256^ -gravity center -extent 256x256 001-thumb.webp
 
-// magick convert 028.jpg -define webp:method=6 -define webp:thread-level=4 -resize 2048x 028_l.webp
 // magick convert 028.tif -quiet -dither FloydSteinberg -define dither:diffusion-amount=35% 028_test.jpg
 // magick convert 028.tif -quiet -quality 96 028_test.jpg
-//-unsharp 0x1+1+0.05
-// cwebp
 
-/// https://imagemagick.org/script/webp.php
-// https://imagemagick.org/script/command-line-options.php#unsharp
-// readl webp config https://github.com/ImageMagick/webp/blob/master/src/webp/encode.h#L98
-pub fn create_sizes(path: &str, photo: &Photo, config: &PhotoConfig) {
-    // Generate file name from photo index and configured extenstion
-    let file_name = |suffix: &'static str| {
-        format!("{:03}_{}{}", photo.index, suffix, config.output_ext)
-    };
+/// Sharpening applied after every resize
+const UNSHARP: &str = "0x1+1+0.05";
+
+impl PhotoResizer for ImageMagick {
+    /// Run `magick convert` once for all sizes and formats of the photo so the
+    /// source is only decoded once
+    fn create_sizes(
+        &self,
+        folder: &Path,
+        photo: &Photo,
+        config: &PhotoConfig,
+        formats: &[&str],
+    ) -> Vec<String> {
+        if formats.is_empty() {
+            return Vec::new();
+        }
+        match Command::new("magick")
+            .current_dir(folder)
+            .args(resize_args(photo, config, formats))
+            .output()
+        {
+            Ok(out) => match String::from_utf8(out.stderr) {
+                Ok(err) if !err.trim().is_empty() => {
+                    vec![err.trim().to_string()]
+                }
+                _ if !out.status.success() => vec![format!("{}", out.status)],
+                _ => Vec::new(),
+            },
+            Err(e) => vec![format!("{:?}", e)],
+        }
+    }
+}
 
-    let size = &config.size.render;
-
-    // TODO: remove EXIF but ensure copyright is still present
-    // TODO: test sharpening levels and maybe don't sharpen TIFF
-
-    let sub_command = |size: u16, suffix: &'static str| {
-        if size != config.source_size {
-            string_vec![
-                "(",
-                "+clone",
-                "-resize",
-                format!("{}x", size),
-                "-unsharp",
-                "0x1+1+0.05",
-                "-write",
-                file_name(suffix),
-                "+delete",
-                ")"
-            ]
-        } else {
-            string_vec![
-                "(",
-                "+clone",
-                "-write",
-                file_name(suffix),
-                "+delete",
-                ")"
+/// `magick` arguments that write each rendered size of `photo` in each of the
+/// `formats` from clones of the source, ending with the square thumbnail
+///
+/// https://imagemagick.org/script/webp.php
+/// https://imagemagick.org/script/command-line-options.php#unsharp
+fn resize_args(
+    photo: &Photo,
+    config: &PhotoConfig,
+    formats: &[&str],
+) -> Vec<String> {
+    let write = |suffix: &'static str| {
+        formats.iter().flat_map(move |ext| {
+            vec![
+                "-write".to_owned(),
+                format!("{:03}_{}{}", photo.index, suffix, ext),
             ]
-        }
+        })
     };
-
-    let thumb_command = string_vec![
-        "-resize",
-        format!("{px}x{px}^", px = size.thumb),
-        "-unsharp",
-        "0x1+1+0.05",
-        "-gravity",
-        "center",
-        "-extent",
-        format!("{px}x{px}", px = size.thumb),
-        file_name(suffix::THUMB)
+    let mut args: Vec<String> = vec![
+        "convert".to_owned(),
+        photo.file.name.clone(),
+        "-quiet".to_owned(),
     ];
 
-    match Command::new("magick")
-        .current_dir(path)
-        .arg("convert")
-        .arg(&photo.file.name)
-        .arg("-quiet")
-        //.args(setting("unsharp", "0x1+1+0.05"))
-        .args(sub_command(size.large, suffix::LARGE))
-        .args(sub_command(size.medium, suffix::MEDIUM))
-        .args(sub_command(size.small, suffix::SMALL))
-        .args(&thumb_command)
-        .output()
-    {
-        Ok(out) => {
-            if let Ok(err) = String::from_utf8(out.stderr) {
-                if !err.is_empty() {
-                    println!(
-                        "Running\nmagick convert {} -quiet {} {} {} {}\n",
-                        &photo.file.name,
-                        sub_command(size.large, suffix::LARGE).join(" "),
-                        sub_command(size.medium, suffix::MEDIUM).join(" "),
-                        sub_command(size.small, suffix::SMALL).join(" "),
-                        thumb_command.join(" ")
-                    );
-                    println!("{}", err.red());
-                    process::exit(1);
-                }
+    for (size, suffix, square) in render_sizes(config).iter() {
+        if *square {
+            args.extend(vec![
+                "-resize".to_owned(),
+                format!("{px}x{px}^", px = size),
+                "-unsharp".to_owned(),
+                UNSHARP.to_owned(),
+                "-gravity".to_owned(),
+                "center".to_owned(),
+                "-extent".to_owned(),
+                format!("{px}x{px}", px = size),
+            ]);
+            args.extend(write(suffix));
+        } else {
+            args.extend(vec!["(".to_owned(), "+clone".to_owned()]);
+
+            if *size != config.source_size {
+                // limit the long edge without enlarging
+                args.extend(vec![
+                    "-resize".to_owned(),
+                    format!("{px}x{px}>", px = size),
+                    "-unsharp".to_owned(),
+                    UNSHARP.to_owned(),
+                ]);
             }
+            args.extend(write(suffix));
+            args.extend(vec!["+delete".to_owned(), ")".to_owned()]);
         }
-        Err(e) => {
-            println!("   {:?}", e);
-            process::exit(1);
-        }
-    };
+    }
+    // every file is written above so discard the final image
+    args.push("null:".to_owned());
+    args
 }
 
-// https://www.awaresystems.be/imaging/tiff/tifftags/private.html
+/// ImageMagick reports EXIF values as text, including rationals (`240/10`)
+/// and lists (`47/1, 18133333/10000000, 0/1`), so they're converted when
+/// creating the `Photo`
+///
+/// https://www.awaresystems.be/imaging/tiff/tifftags/private.html
 #[derive(Deserialize, Debug)]
 struct ImageProperties {
-    #[serde(rename = "exif:ApertureValue")] // or exif:FNumber
-    aperture: String,
+    #[serde(rename = "exif:FNumber")] // or exif:ApertureValue
+    aperture: Option<String>,
 
-    #[serde(rename = "exif:artist")]
+    #[serde(rename = "exif:Artist")]
     artist: Option<String>,
 
     #[serde(rename = "exif:Make")]
     camera_make: Option<String>,
 
-    #[serde(rename = "exif:model")]
+    #[serde(rename = "exif:Model")]
     camera_model: Option<String>,
 
     #[serde(rename = "exif:ImageDescription")]
     caption: Option<String>,
 
-    //city: Option<String>,
-    #[serde(rename = "icc:description")]
-    color_profile: Option<String>,
-
-    color_temperature: Option<String>,
-
-    #[serde(rename = "exif:copyright")]
-    copyright: String,
-
     /// When the *photo*, not the file, was created
-    #[serde(rename = "date:create")]
-    created_on: Option<DateTime<FixedOffset>>,
+    #[serde(rename = "exif:DateTimeDigitized")]
+    created_on: Option<String>,
+
+    #[serde(rename = "exif:OffsetTimeDigitized")]
+    created_offset: Option<String>,
 
     #[serde(rename = "exif:ExposureBiasValue")]
     exposure_compensation: Option<String>,
 
-    #[serde(rename = "exif:FocalLengthIn35mmFilm")]
-    field_of_view: Option<f32>,
-
-    /// When the *file*, not the photo, was created
-    file_created_on: Option<DateTime<FixedOffset>>,
-
-    file_name: String,
+    #[serde(rename = "exif:ExposureProgram")]
+    exposure_mode: Option<String>,
 
     #[serde(rename = "exif:FocalLength")]
-    focal_length: Option<f32>,
+    focal_length: Option<String>,
 
-    #[serde(rename = "exif:LensModel")]
-    iso: Option<u16>,
+    #[serde(rename = "exif:PhotographicSensitivity")]
+    iso: Option<String>,
 
     #[serde(rename = "exif:GPSLatitude")]
-    latitude: Option<f32>,
+    latitude: Option<String>,
 
-    #[serde(rename = "exif:PhotographicSensitivity")]
+    #[serde(rename = "exif:GPSLatitudeRef")]
+    latitude_ref: Option<String>,
+
+    #[serde(rename = "exif:LensModel")]
     lens: Option<String>,
 
     #[serde(rename = "exif:GPSLongitude")]
-    longitude: Option<f32>,
+    longitude: Option<String>,
 
-    #[serde(rename = "exif:MaxApertureValue")]
-    max_aperture: Option<f32>,
+    #[serde(rename = "exif:GPSLongitudeRef")]
+    longitude_ref: Option<String>,
 
     #[serde(rename = "exif:Software")]
-    software: String,
-
-    // state: Option<String>,
-
-    //tags: Vec<String>,
-
-    //title: Option<String>,
-    usage_terms: Option<String>,
+    software: Option<String>,
 
     #[serde(rename = "exif:ExposureTime")] // or ShutterSpeedValue
     shutter_speed: Option<String>,
 
     #[serde(rename = "exif:DateTimeOriginal")]
-    taken_on: Option<DateTime<FixedOffset>>,
+    taken_on: Option<String>,
+
+    #[serde(rename = "exif:OffsetTimeOriginal")]
+    taken_offset: Option<String>,
 }
 
 #[derive(Deserialize, Debug)]
@@ -211,15 +199,15 @@ struct ProfileFields {
 
 /// IPTC field values are all string arrays even if they should have only a
 /// single element
-#[derive(Deserialize, Debug)]
+#[derive(Deserialize, Debug, Default)]
 struct IptcFields {
-    #[serde(rename = "Caption[2,120]")]
+    #[serde(default, rename = "Caption[2,120]")]
     caption: Vec<String>,
 
-    #[serde(rename = "Keyword[2,25]")]
+    #[serde(default, rename = "Keyword[2,25]")]
     tags: Vec<String>,
 
-    #[serde(rename = "Image Name[2,5]")]
+    #[serde(default, rename = "Image Name[2,5]")]
     title: Vec<String>,
 }
 
@@ -227,103 +215,169 @@ struct IptcFields {
 struct ImageFields {
     #[serde(rename = "baseName")]
     file_name: String,
-    format: String,
     properties: ImageProperties,
     geometry: GeometryFields,
     profiles: ProfileFields,
 }
+
 #[derive(Deserialize, Debug)]
 struct ImageMagickInfo {
     pub image: ImageFields,
 }
 
-pub fn parse_dir(path: &Path, config: &PhotoConfig) -> Vec<Photo> {
-    let mut photos: Vec<Photo> = Vec::new();
-
-    for i in read_dir(&path) {
-        let i = i.image;
+/// Reads metadata by running ImageMagick
+pub struct ImageMagick;
+
+impl MetadataSource for ImageMagick {
+    /// Execute ImageMagick for the named `files` in `path` and capture output
+    /// as `Photo` structs
+    fn parse_files(
+        &self,
+        path: &Path,
+        files: &[String],
+        config: &PhotoConfig,
+    ) -> Vec<Photo> {
+        read_files(path, files)
+            .into_iter()
+            .filter_map(|i| to_photo(i.image, config))
+            .collect()
+    }
+}
 
-        let index =
-            pos_from_name(&config.capture_index, &i.file_name).unwrap_or(0);
+/// Convert ImageMagick output to a `Photo` or `None` if its index can't be
+/// inferred from the file name
+fn to_photo(i: ImageFields, config: &PhotoConfig) -> Option<Photo> {
+    let index = pos_from_name(&config.capture_index, &i.file_name).unwrap_or(0);
 
-        if index == 0 {
-            println!(
-                "   {} {}",
-                "failed to infer index of".red(),
-                i.file_name.red(),
-            );
-            continue;
-        }
+    if index == 0 {
+        println!(
+            "   {} {}",
+            "failed to infer index of".red(),
+            i.file_name.red(),
+        );
+        return None;
+    }
 
-        let p = i.properties;
-        let iptc = i.profiles.iptc.unwrap();
+    let p = i.properties;
+    let date_taken = date_time(&p.taken_on, &p.taken_offset)
+        .or_else(|| date_time(&p.created_on, &p.created_offset));
+    let mut iptc = i.profiles.iptc.unwrap_or_default();
+    // ImageMagick mangles non-ASCII EXIF text so prefer the IPTC caption
+    let caption = if iptc.caption.is_empty() {
+        p.caption
+    } else {
+        Some(iptc.caption.remove(0))
+    };
 
-        let mut photo = Photo {
-            file: PhotoFile {
-                name: i.file_name.clone(),
-                created: 0, // i.file_created_on.map_or(0, |d| d.timestamp()),
-            },
-            title: None, // mem::replace(&mut iptc.title, None),
-            artist: p.artist,
-            caption: p.caption.map(|s| html::caption(&s)),
-            software: p.software,
-            tags: iptc.tags,
+    let mut photo = Photo {
+        file: PhotoFile { name: i.file_name },
+        title: iptc.title.into_iter().next(),
+        artist: p.artist,
+        caption: caption.map(|s| html::caption(&s)),
+        software: p.software.unwrap_or_default(),
+        tags: iptc.tags,
+        index,
+        size: SizeCollection::from(
+            i.geometry.width,
+            i.geometry.height,
             index,
-            size: SizeCollection::from(
-                i.geometry.width,
-                i.geometry.height,
-                index,
-                &config,
-            ),
-            date_taken: p.taken_on.or(p.created_on),
-            ..Photo::default()
+            config,
+        ),
+        date_taken,
+        ..Photo::default()
+    };
+
+    if let Some(make) = p.camera_make {
+        photo.camera = Some(Camera {
+            name: p.camera_model.unwrap_or(make),
+            compensation: p.exposure_compensation.as_deref().and_then(fraction),
+            shutter_speed: p.shutter_speed.as_deref().and_then(fraction),
+            mode: p
+                .exposure_mode
+                .and_then(|m| m.parse::<u8>().ok())
+                .and_then(ExposureMode::from_u8)
+                .unwrap_or_default(),
+            aperture: p.aperture.as_deref().and_then(number),
+            focal_length: p.focal_length.as_deref().and_then(number),
+            iso: p.iso.and_then(|n| n.parse().ok()),
+            lens: p.lens,
+        });
+    }
+
+    let latitude = coordinate(&p.latitude, &p.latitude_ref, "S");
+    let longitude = coordinate(&p.longitude, &p.longitude_ref, "W");
+
+    if let (Some(latitude), Some(longitude)) = (latitude, longitude) {
+        let loc = Location {
+            latitude,
+            longitude,
+            ..Location::default()
         };
 
-        if let Some(make) = &p.camera_make {
-            let name = match &p.camera_model {
-                Some(model) => format!("{} {}", make, model),
-                _ => make.clone(),
-            };
-
-            let camera = Camera {
-                name,
-                compensation: p.exposure_compensation,
-                shutter_speed: p.shutter_speed,
-                mode: ExposureMode::Manual, // p.exposure_mode,
-                aperture: None,             // p.aperture,
-                focal_length: p.focal_length,
-                iso: p.iso,
-                lens: p.lens,
-            };
-
-            photo.camera = Some(camera);
+        if loc.is_valid() {
+            photo.location = Some(loc);
         }
+    }
 
-        if p.latitude.is_some() && p.longitude.is_some() {
-            let loc = Location {
-                latitude: p.latitude.unwrap(),
-                longitude: p.longitude.unwrap(),
-            };
+    Some(photo)
+}
 
-            if loc.is_valid() {
-                photo.location = Some(loc);
-            }
-        }
+fn number(text: &str) -> Option<f32> {
+    parse_rational(text).map(|n| n as f32)
+}
+
+/// Rational text written as a reduced fraction (`1/500`) or, if it divides
+/// evenly, as a whole number
+fn fraction(text: &str) -> Option<String> {
+    rational_parts(text).and_then(|(num, denom)| reduce_fraction(num, denom))
+}
 
-        photos.push(photo);
+/// Decimal degrees from degree, minute and second rationals, negated if the
+/// reference matches `negative` (`S` or `W`)
+fn coordinate(
+    dms: &Option<String>,
+    reference: &Option<String>,
+    negative: &str,
+) -> Option<f32> {
+    let parts: Vec<f64> = dms
+        .as_ref()?
+        .split(',')
+        .filter_map(parse_rational)
+        .collect();
+
+    if parts.len() < 3 {
+        return None;
     }
+    let degrees = parts[0] + parts[1] / 60.0 + parts[2] / 3600.0;
+
+    Some(if reference.as_deref() == Some(negative) {
+        -degrees
+    } else {
+        degrees
+    } as f32)
+}
 
-    photos
+/// Date and time combined with separately recorded timezone offset, if any
+fn date_time(
+    value: &Option<String>,
+    offset: &Option<String>,
+) -> Option<DateTime<FixedOffset>> {
+    let value =
+        format!("{}{}", value.as_ref()?, offset.as_deref().unwrap_or(""));
+    parse_date_time(&value).ok()
 }
 
-fn read_dir(path: &Path) -> Vec<ImageMagickInfo> {
+fn read_files(path: &Path, files: &[String]) -> Vec<ImageMagickInfo> {
+    if files.is_empty() {
+        return Vec::new();
+    }
     // magick convert -quiet 001.jpg json:
     // magick convert -quiet *.tif xmp:
     let output = match Command::new("magick")
         .current_dir(path.to_string_lossy().to_string())
         .arg("convert")
         .arg("-quiet")
-        .arg("*.jpg")
+        .args(files)
         .arg("json:")
         .output()
     {
@@ -332,45 +386,116 @@ fn read_dir(path: &Path) -> Vec<ImageMagickInfo> {
             println!(
                 "   {} {}",
                 "Failed to generate EXIF for".red(),
-                folder_name(&path).magenta(),
+                folder_name(path).magenta(),
             );
             return Vec::new();
         }
     };
 
-    let text = match ISO_8859_1.decode(&output.stdout[..], DecoderTrap::Ignore)
-    {
-        Ok(text) => text,
-        _ => {
-            println!(
-                "   {} {}",
-                "Failed to convert EXIF output to UTF-8 for".red(),
-                folder_name(&path).magenta(),
-            );
-            return Vec::new();
-        }
-    };
+    // ImageMagick passes along invalid UTF-8 from some EXIF fields
+    let text = String::from_utf8_lossy(&output.stdout);
 
     if text.is_empty() {
         println!(
             "   {} {}",
             "EXIF JSON is empty for".red(),
-            folder_name(&path).magenta()
+            folder_name(path).magenta()
         );
         return Vec::new();
     }
 
-    match serde_json::from_str::<Vec<ImageMagickInfo>>(&text) {
+    match parse_json(&text) {
         Ok(info) => info,
-        Err(_e) => {
+        Err(e) => {
             println!(
                 "   {} {}",
                 "Unable to parse EXIF JSON for".red(),
-                folder_name(&path).magenta(),
+                folder_name(path).magenta(),
             );
-            //println!("{}", text);
-            //println!("{:?}", e);
+            println!("   —\n   {:?}\n   —", e);
             Vec::new()
         }
     }
 }
+
+fn parse_json(text: &str) -> serde_json::Result<Vec<ImageMagickInfo>> {
+    serde_json::from_str::<Vec<ImageMagickInfo>>(text)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{parse_json, resize_args, to_photo};
+    use crate::{
+        config::PhotoConfig,
+        models::{ExposureMode, Photo, PhotoFile},
+    };
+    use chrono::DateTime;
+    use std::fs;
+
+    #[test]
+    fn parse_fixture_test() {
+        let bytes = fs::read("./src/fixtures/out.json").unwrap();
+        let info = parse_json(&String::from_utf8_lossy(&bytes)).unwrap();
+        let photo = info
+            .into_iter()
+            .filter_map(|i| to_photo(i.image, &PhotoConfig::fixture()))
+            .next()
+            .unwrap();
+
+        assert_eq!(photo.file.name, "img_006-of-021.jpg");
+        assert_eq!(photo.index, 6);
+        assert_eq!(photo.title, Some("Time to move on".to_owned()));
+        assert_eq!(photo.artist, Some("Jason Abbott".to_owned()));
+        assert_eq!(photo.tags.len(), 7);
+        assert!(photo.tags.contains(&"Fire".to_owned()));
+        assert_eq!(
+            photo.date_taken,
+            Some(
+                DateTime::parse_from_rfc3339("2008-09-20T07:48:42-06:00")
+                    .unwrap()
+            )
+        );
+        assert!(photo
+            .caption
+            .unwrap()
+            .starts_with("<p>Joel said he’d always wanted"));
+
+        let camera = photo.camera.unwrap();
+
+        assert_eq!(camera.name, "NIKON D3");
+        assert_eq!(camera.lens, Some("24.0-70.0 mm f/2.8".to_owned()));
+        assert_eq!(camera.shutter_speed, Some("1/10".to_owned()));
+        assert_eq!(camera.compensation, Some("0".to_owned()));
+        assert_eq!(camera.mode, ExposureMode::AperturePriority);
+        assert_eq!(camera.aperture, Some(8.0));
+        assert_eq!(camera.focal_length, Some(24.0));
+        assert_eq!(camera.iso, Some(200));
+
+        let location = photo.location.unwrap();
+
+        assert!((location.latitude - 47.030_222).abs() < 0.000_01);
+        assert!((location.longitude + 115.985_11).abs() < 0.000_01);
+    }
+
+    #[test]
+    fn resize_args_test() {
+        let photo = Photo {
+            file: PhotoFile {
+                name: "img_006-of-021.jpg".to_owned(),
+            },
+            index: 6,
+            ..Photo::default()
+        };
+        let args =
+            resize_args(&photo, &PhotoConfig::fixture(), &[".webp", ".jpg"])
+                .join(" ");
+
+        // large size matches the source size so is only written
+        assert!(args.starts_with(
+            "convert img_006-of-021.jpg -quiet ( +clone -write 006_l.webp -write 006_l.jpg +delete ) ( +clone -resize 1024x1024> -unsharp"
+        ));
+        assert!(args.ends_with(
+            "-resize 256x256^ -unsharp 0x1+1+0.05 -gravity center -extent 256x256 -write 006_t.webp -write 006_t.jpg null:"
+        ));
+    }
+}
@@ -1,102 +1,373 @@
 //! Methods to interact with MapBox services
 
-use crate::config::BlogConfig;
-use crate::models::Post;
-use std::{fs::File, io, path::Path};
+use super::StaticMapProvider;
+use crate::config::MapBoxConfig;
+use std::{f64::consts::PI, io::Read};
 use url::form_urlencoded::byte_serialize;
 
+/// Longest request URL MapBox accepts
+///
+/// https://docs.mapbox.com/api/maps/static-images/#static-images-api-errors
+const MAX_URL_LENGTH: usize = 8192;
+
+/// Pins closer than this many points at the map zoom level are combined
+const CLUSTER_POINTS: f64 = 20.0;
+
+/// Cluster distances tried, each double the last, before falling back to a
+/// line since larger clusters would misplace pins
+const CLUSTER_ATTEMPTS: usize = 2;
+
+/// Points of padding assumed around pins when estimating the zoom level
+const MAP_PADDING: f64 = 20.0;
+
+/// Points per tile edge, so the world width at zoom zero
+const TILE_POINTS: f64 = 512.0;
+
+/// Closest zoom level of automatically positioned maps
+const MAX_ZOOM: f64 = 16.0;
+
+/// Decimal places kept in pin coordinates, about 36 feet
+const COORDINATE_PRECISION: f64 = 10_000.0;
+
+/// Width, color and opacity of the line drawn when there are too many pins
+///
+/// https://docs.mapbox.com/api/maps/static-images/#path
+const PATH_STYLE: &str = "path-3+c0392b-0.8";
+
+/// Retrieves static map images from the MapBox API, or any service at the
+/// configured base URL that answers the same requests
 pub struct MapBox<'a> {
-    root: &'a Path,
-    config: &'a BlogConfig,
+    config: &'a MapBoxConfig,
 }
-impl<'a> MapBox<'a> {
-    /// Retrieve and save MapBox static map image for post photo locations
-    ///
-    /// https://docs.mapbox.com/help/how-mapbox-works/static-maps/
-    /// https://docs.mapbox.com/api/maps/#static-images
-    ///
-    pub fn save_static(post: &'a Post, root: &'a Path, config: &'a BlogConfig) {
-        let mapbox = MapBox { root, config };
 
-        mapbox.download_maps(post);
+impl<'a> MapBox<'a> {
+    pub fn new(config: &'a MapBoxConfig) -> Self {
+        MapBox { config }
     }
 
     /// Generate markers as `url-{url}({lon},{lat})`
     ///
     /// https://docs.mapbox.com/api/maps/#marker
     ///
-    fn pin_list(&self, post: &Post) -> Vec<String> {
-        post.photo_locations
-            .iter()
+    fn pin_list(&self, pins: &[(f64, f64)]) -> String {
+        pins.iter()
             .map(|(lon, lat)| {
-                format!("url-{}({},{})", self.config.mapbox.pin_image, lon, lat)
+                format!("url-{}({},{})", self.config.pin_image, lon, lat)
             })
-            .collect()
+            .collect::<Vec<String>>()
+            .join(",")
     }
 
-    fn download_maps(&self, post: &'a Post) {
-        let pins = self.pin_list(post);
+    /// Markers for the pins or, if there are too many to fit in a request,
+    /// markers for clusters of nearby pins. If even clusters don't fit then a
+    /// line is drawn through the pins, thinned as needed.
+    fn overlay(&self, pins: &[(f32, f32)], width: u16, height: u16) -> String {
+        let pins = round(pins);
+        let zoom = fit_zoom(&pins, width, height);
+        let fits = |overlay: &str| {
+            self.url_length(overlay, width, height) <= MAX_URL_LENGTH
+        };
+        let mut radius = CLUSTER_POINTS;
 
-        self.download_static_map(
-            post,
-            "map.png",
-            &pins,
-            self.config.style.content_width,
-            self.config.style.inline_map_height,
-        );
+        for _ in 0..CLUSTER_ATTEMPTS {
+            let markers = self.pin_list(&cluster(&pins, zoom, radius));
 
-        self.download_static_map(
-            post,
-            "map_small.png",
-            &pins,
-            post.cover_map_size.0,
-            post.cover_map_size.1,
-        );
+            if fits(&markers) {
+                return markers;
+            }
+            radius *= 2.0;
+        }
+
+        let mut step = 1;
+
+        loop {
+            let points: Vec<(f64, f64)> =
+                pins.iter().step_by(step).cloned().collect();
+            let line = format!("{}({})", PATH_STYLE, encode_polyline(&points));
+
+            if fits(&line) || points.len() <= 2 {
+                return line;
+            }
+            step *= 2;
+        }
+    }
+
+    /// Static image path for an overlay, without the access token
+    fn path(&self, overlay: &str, width: u16, height: u16) -> String {
+        let overlay: String = byte_serialize(overlay.as_bytes()).collect();
+
+        format!(
+            "/styles/v1/{}/static/{}/auto/{}x{}@2x",
+            self.config.style.r#static, overlay, width, height
+        )
     }
 
-    fn download_static_map(
+    fn base_url(&self) -> &str {
+        self.config.base_url.trim_end_matches('/')
+    }
+
+    fn query(&self) -> String {
+        format!(
+            "?access_token={}&attribution=false&logo=false",
+            self.config.access_token
+        )
+    }
+
+    /// Length of the full request URL for an overlay
+    fn url_length(&self, overlay: &str, width: u16, height: u16) -> usize {
+        self.base_url().len()
+            + self.path(overlay, width, height).len()
+            + self.query().len()
+    }
+}
+
+impl<'a> StaticMapProvider for MapBox<'a> {
+    fn cache_key(
         &self,
-        post: &Post,
-        name: &str,
-        pins: &[String],
+        pins: &[(f32, f32)],
         width: u16,
         height: u16,
-    ) {
-        let pins: String = byte_serialize(pins.join(",").as_bytes()).collect();
-
-        let url = format!("https://api.mapbox.com/styles/v1/{}/static/{}/auto/{}x{}@2x?access_token={}&attribution=false&logo=false",
-            self.config.mapbox.style.r#static,
-            pins,
-            width,
-            height,
-            self.config.mapbox.access_token);
-
-        // GOAL: refactor match nesting
-        match reqwest::blocking::get(&url) {
-            Ok(mut res) => {
-                if res.status().is_success() {
-                    match File::create(self.root.join(&post.path).join(name)) {
-                        Ok(mut dest) => match io::copy(&mut res, &mut dest) {
-                            Ok(_bytes_copied) => (),
-                            Err(e) => {
-                                println!("{:?}", e);
-                            }
-                        },
-                        Err(e) => {
-                            println!("{:?}", e);
-                        }
-                    }
-                } else {
-                    println!(
-                        "Failed to download {} {}",
-                        url,
-                        res.text().unwrap_or_else(|_| String::new())
-                    );
-                }
-            }
-            Err(e) => {
-                println!("{:?}", e);
-            }
+    ) -> String {
+        format!(
+            "{}{}",
+            self.base_url(),
+            self.path(&self.overlay(pins, width, height), width, height)
+        )
+    }
+
+    /// Retrieve static map image for pin locations
+    ///
+    /// https://docs.mapbox.com/help/how-mapbox-works/static-maps/
+    /// https://docs.mapbox.com/api/maps/#static-images
+    ///
+    fn fetch(
+        &self,
+        pins: &[(f32, f32)],
+        width: u16,
+        height: u16,
+    ) -> Result<Vec<u8>, String> {
+        let url =
+            format!("{}{}", self.cache_key(pins, width, height), self.query());
+        let mut res =
+            reqwest::blocking::get(&url).map_err(|e| format!("{:?}", e))?;
+
+        if !res.status().is_success() {
+            return Err(format!(
+                "{} {}",
+                res.status(),
+                res.text().unwrap_or_default()
+            ));
+        }
+
+        let mut bytes: Vec<u8> = Vec::new();
+
+        res.read_to_end(&mut bytes)
+            .map_err(|e| format!("{:?}", e))?;
+        Ok(bytes)
+    }
+}
+
+/// Longitude and latitude pairs rounded to `COORDINATE_PRECISION` with
+/// duplicates removed
+fn round(pins: &[(f32, f32)]) -> Vec<(f64, f64)> {
+    let round = |n: f32| {
+        (n as f64 * COORDINATE_PRECISION).round() / COORDINATE_PRECISION
+    };
+    let mut rounded: Vec<(f64, f64)> = Vec::with_capacity(pins.len());
+
+    for (lon, lat) in pins {
+        let pin = (round(*lon), round(*lat));
+
+        if !rounded.contains(&pin) {
+            rounded.push(pin);
+        }
+    }
+    rounded
+}
+
+/// Position of a longitude and latitude on a Web Mercator map of the world
+/// one unit wide
+fn mercator(lon: f64, lat: f64) -> (f64, f64) {
+    let sin = lat.to_radians().sin();
+
+    (
+        (lon + 180.0) / 360.0,
+        0.5 - ((1.0 + sin) / (1.0 - sin)).ln() / (4.0 * PI),
+    )
+}
+
+/// Approximate zoom level at which automatic positioning fits every pin in a
+/// map `width` by `height` points
+fn fit_zoom(pins: &[(f64, f64)], width: u16, height: u16) -> f64 {
+    let points: Vec<(f64, f64)> =
+        pins.iter().map(|(lon, lat)| mercator(*lon, *lat)).collect();
+    let span = |values: Vec<f64>| {
+        values.iter().cloned().fold(f64::NEG_INFINITY, f64::max)
+            - values.iter().cloned().fold(f64::INFINITY, f64::min)
+    };
+    let zoom = |size: u16, span: f64| {
+        if span > 0.0 {
+            ((size as f64 - MAP_PADDING * 2.0).max(1.0) / (TILE_POINTS * span))
+                .log2()
+        } else {
+            MAX_ZOOM
+        }
+    };
+    let x = zoom(width, span(points.iter().map(|p| p.0).collect()));
+    let y = zoom(height, span(points.iter().map(|p| p.1).collect()));
+
+    x.min(y).clamp(0.0, MAX_ZOOM)
+}
+
+/// Pins combined by `cluster()`
+#[derive(Default)]
+struct Cluster {
+    /// Sums of pin longitudes and latitudes
+    coordinates: (f64, f64),
+    /// Sums of pin positions in points at the map zoom level
+    position: (f64, f64),
+    count: usize,
+}
+
+impl Cluster {
+    fn add(&mut self, coordinates: (f64, f64), position: (f64, f64)) {
+        self.coordinates.0 += coordinates.0;
+        self.coordinates.1 += coordinates.1;
+        self.position.0 += position.0;
+        self.position.1 += position.1;
+        self.count += 1;
+    }
+
+    fn distance(&self, position: (f64, f64)) -> f64 {
+        let n = self.count as f64;
+
+        ((self.position.0 / n - position.0).powi(2)
+            + (self.position.1 / n - position.1).powi(2))
+        .sqrt()
+    }
+
+    /// Mean longitude and latitude, rounded
+    fn center(&self) -> (f64, f64) {
+        let n = self.count as f64;
+        let round = |c: f64| {
+            (c / n * COORDINATE_PRECISION).round() / COORDINATE_PRECISION
         };
+
+        (round(self.coordinates.0), round(self.coordinates.1))
+    }
+}
+
+/// Combine pins within `radius` points of each other at `zoom` into one at
+/// their mean position
+fn cluster(pins: &[(f64, f64)], zoom: f64, radius: f64) -> Vec<(f64, f64)> {
+    let scale = TILE_POINTS * 2f64.powf(zoom);
+    let mut clusters: Vec<Cluster> = Vec::new();
+
+    for (lon, lat) in pins {
+        let (x, y) = mercator(*lon, *lat);
+        let position = (x * scale, y * scale);
+
+        match clusters.iter_mut().find(|c| c.distance(position) <= radius) {
+            Some(c) => c.add((*lon, *lat), position),
+            _ => {
+                let mut c = Cluster::default();
+                c.add((*lon, *lat), position);
+                clusters.push(c);
+            }
+        }
+    }
+
+    clusters.iter().map(Cluster::center).collect()
+}
+
+/// Encode longitude and latitude pairs with the polyline algorithm
+///
+/// https://developers.google.com/maps/documentation/utilities/polylinealgorithm
+fn encode_polyline(points: &[(f64, f64)]) -> String {
+    let mut text = String::new();
+    let mut previous = (0i64, 0i64);
+
+    for (lon, lat) in points {
+        let (lat, lon) =
+            ((lat * 1e5).round() as i64, (lon * 1e5).round() as i64);
+
+        encode_value(lat - previous.0, &mut text);
+        encode_value(lon - previous.1, &mut text);
+        previous = (lat, lon);
+    }
+    text
+}
+
+/// Append value as five-bit chunks offset into printable characters
+fn encode_value(value: i64, text: &mut String) {
+    let mut v = if value < 0 { !(value << 1) } else { value << 1 };
+
+    while v >= 0x20 {
+        text.push((((v & 0x1f) | 0x20) as u8 + 63) as char);
+        v >>= 5;
+    }
+    text.push((v as u8 + 63) as char);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        cluster, encode_polyline, fit_zoom, round, MapBox, MAX_URL_LENGTH,
+    };
+    use crate::config::MapBoxConfig;
+
+    #[test]
+    fn round_test() {
+        assert_eq!(
+            round(&[(-116.123_44, 46.0), (-116.123_41, 46.0), (-116.2, 46.1)]),
+            vec![(-116.1234, 46.0), (-116.2, 46.1)]
+        );
+    }
+
+    #[test]
+    fn cluster_test() {
+        // about 200 feet apart then about 7 miles away
+        let pins = vec![(-116.0, 46.0), (-116.0, 46.0004), (-116.1, 46.08)];
+        let zoom = fit_zoom(&pins, 400, 300);
+
+        assert!(zoom > 10.0 && zoom < 12.0);
+        assert_eq!(
+            cluster(&pins, zoom, 20.0),
+            vec![(-116.0, 46.0002), (-116.1, 46.08)]
+        );
+        assert_eq!(cluster(&pins, 16.0, 20.0).len(), 3);
+        assert_eq!(fit_zoom(&pins[..1], 400, 300), 16.0);
+    }
+
+    #[test]
+    fn polyline_test() {
+        assert_eq!(
+            encode_polyline(&[
+                (-120.2, 38.5),
+                (-120.95, 40.7),
+                (-126.453, 43.252)
+            ]),
+            "_p~iF~ps|U_ulLnnqC_mqNvxq`@"
+        );
+    }
+
+    #[test]
+    fn overlay_test() {
+        let config = MapBoxConfig::fixture("https://api.mapbox.com");
+        let mapbox = MapBox::new(&config);
+        let few: Vec<(f32, f32)> =
+            (0..10).map(|i| (-116.0 + i as f32 * 0.01, 46.0)).collect();
+        // grid of pins about 50 points apart at the map zoom, too far to
+        // cluster
+        let many: Vec<(f32, f32)> = (0..200)
+            .map(|i| ((i % 20) as f32 - 10.0, (i / 20) as f32 - 4.5))
+            .collect();
+
+        assert!(mapbox.overlay(&few, 1000, 600).starts_with("url-"));
+        assert!(mapbox.overlay(&many, 1000, 600).starts_with("path-"));
+        assert!(
+            mapbox.url_length(&mapbox.overlay(&many, 1000, 600), 1000, 600)
+                <= MAX_URL_LENGTH
+        );
     }
 }
@@ -1,6 +1,80 @@
 pub mod cwebp;
 pub mod exif_tool;
-//pub mod image_magick;
+pub mod image_magick;
 mod mapbox;
+pub mod native_exif;
+mod native_resize;
+pub mod resize;
+mod static_map;
+pub mod xmp;
 
+use crate::{
+    config::{MetadataBackend, PhotoConfig, ResizeBackend},
+    models::Photo,
+};
+use std::path::Path;
+
+pub use cwebp::CWebP;
+pub use exif_tool::ExifTool;
+pub use image_magick::ImageMagick;
 pub use mapbox::MapBox;
+pub use native_exif::NativeExif;
+pub use native_resize::NativeResize;
+pub use static_map::{
+    prune_static, save_static, static_map_provider, StaticMapProvider,
+};
+
+/// Reads metadata for the photos in a folder
+pub trait MetadataSource {
+    /// Create a `Photo` for each of the named `files` in `path`. Photos that
+    /// can't be read are reported and skipped.
+    fn parse_files(
+        &self,
+        path: &Path,
+        files: &[String],
+        config: &PhotoConfig,
+    ) -> Vec<Photo>;
+}
+
+/// Metadata source for the configured backend. If none is configured then
+/// ExifTool is used when installed, otherwise EXIF is read in-process.
+///
+/// Create one source per render since some hold an external process open
+/// until dropped.
+pub fn metadata_source(config: &PhotoConfig) -> Box<dyn MetadataSource> {
+    match config.metadata {
+        Some(MetadataBackend::ExifTool) => Box::new(ExifTool::default()),
+        Some(MetadataBackend::ImageMagick) => Box::new(ImageMagick),
+        Some(MetadataBackend::Native) => Box::new(NativeExif),
+        None if exif_tool::is_installed() => Box::new(ExifTool::default()),
+        None => Box::new(NativeExif),
+    }
+}
+
+/// Writes resized copies of photos
+pub trait PhotoResizer: Sync {
+    /// Write each rendered size of `photo`, which is in `folder`, in each of
+    /// the `formats`, given as extensions with leading period. Copies are
+    /// named for the photo index and size suffix with the format extension.
+    /// Returns an error for each copy that couldn't be written.
+    fn create_sizes(
+        &self,
+        folder: &Path,
+        photo: &Photo,
+        config: &PhotoConfig,
+        formats: &[&str],
+    ) -> Vec<String>;
+}
+
+/// Resizer for the configured backend. If none is configured then `cwebp` is
+/// used when installed, otherwise photos are resized in-process. When `cwebp`
+/// is used, formats other than WebP are resized in-process.
+pub fn photo_resizer(config: &PhotoConfig) -> Box<dyn PhotoResizer> {
+    match config.resize {
+        Some(ResizeBackend::CWebP) => Box::new(CWebP),
+        Some(ResizeBackend::ImageMagick) => Box::new(ImageMagick),
+        Some(ResizeBackend::Native) => Box::new(NativeResize),
+        None if cwebp::is_installed() => Box::new(CWebP),
+        None => Box::new(NativeResize),
+    }
+}
@@ -0,0 +1,440 @@
+//! Read photo metadata in-process with kamadak-exif rather than spawning an
+//! external tool
+
+use crate::{
+    config::PhotoConfig,
+    deserialize::parse_date_time,
+    html,
+    image::{xmp, MetadataSource},
+    models::{
+        Camera, ExposureMode, Location, Photo, PhotoFile, SizeCollection,
+    },
+    num_traits::FromPrimitive,
+    tools::{folder_name, pos_from_name, reduce_fraction},
+};
+use chrono::{DateTime, FixedOffset};
+use colored::*;
+use exif::{Exif, In, Reader, Tag, Value};
+use std::{
+    fs::File,
+    io::{BufRead, BufReader, Read, Seek, SeekFrom},
+    path::Path,
+};
+
+/// Identifier at the start of the JPEG APP1 segment holding an XMP packet
+const XMP_HEADER: &[u8] = b"http://ns.adobe.com/xap/1.0/\0";
+
+/// Reads EXIF in-process
+pub struct NativeExif;
+
+impl MetadataSource for NativeExif {
+    fn parse_files(
+        &self,
+        path: &Path,
+        files: &[String],
+        config: &PhotoConfig,
+    ) -> Vec<Photo> {
+        let mut photos: Vec<Photo> = Vec::new();
+
+        for file_name in files {
+            // Photo index based on its file name pattern
+            let index =
+                pos_from_name(&config.capture_index, file_name).unwrap_or(0);
+
+            if index == 0 {
+                println!(
+                    "   {} {}",
+                    "failed to infer index of".red(),
+                    file_name.red()
+                );
+                continue;
+            }
+
+            if let Some(photo) =
+                parse_file(&path.join(file_name), index, config)
+            {
+                photos.push(photo);
+            }
+        }
+
+        photos
+    }
+}
+
+/// Create photo from EXIF and, for JPEGs, XMP embedded in the file at `path`
+pub fn parse_file(
+    path: &Path,
+    index: u8,
+    config: &PhotoConfig,
+) -> Option<Photo> {
+    let file_name = folder_name(path).to_string();
+    let mut reader = match File::open(path) {
+        Ok(file) => BufReader::new(file),
+        Err(e) => {
+            println!(
+                "   {} {} {:?}",
+                "failed to open".red(),
+                file_name.red(),
+                e
+            );
+            return None;
+        }
+    };
+
+    let exif = match Reader::new().read_from_container(&mut reader) {
+        Ok(exif) => exif,
+        Err(e) => {
+            println!(
+                "   {} {} {:?}",
+                "failed to read EXIF from".red(),
+                file_name.red(),
+                e
+            );
+            return None;
+        }
+    };
+
+    let (width, height) = match image_size(&exif, &mut reader) {
+        Some(size) => size,
+        _ => {
+            println!(
+                "   {} {}",
+                "failed to find dimensions of".red(),
+                file_name.red()
+            );
+            return None;
+        }
+    };
+
+    let mut photo = Photo {
+        file: PhotoFile { name: file_name },
+        title: None,
+        artist: text(&exif, Tag::Artist),
+        caption: text(&exif, Tag::ImageDescription).map(|s| html::caption(&s)),
+        software: text(&exif, Tag::Software).unwrap_or_default(),
+        index,
+        size: SizeCollection::from(width, height, index, config),
+        date_taken: date_time(
+            &exif,
+            Tag::DateTimeOriginal,
+            Tag::OffsetTimeOriginal,
+        )
+        .or_else(|| {
+            date_time(&exif, Tag::DateTimeDigitized, Tag::OffsetTimeDigitized)
+        }),
+        ..Photo::default()
+    };
+
+    if let Some(make) = text(&exif, Tag::Make) {
+        photo.camera = Some(Camera {
+            name: text(&exif, Tag::Model).unwrap_or(make),
+            compensation: fraction(&exif, Tag::ExposureBiasValue),
+            shutter_speed: fraction(&exif, Tag::ExposureTime),
+            mode: unsigned(&exif, Tag::ExposureProgram)
+                .and_then(ExposureMode::from_u32)
+                .unwrap_or_default(),
+            aperture: number(&exif, Tag::FNumber),
+            focal_length: number(&exif, Tag::FocalLength),
+            iso: unsigned(&exif, Tag::PhotographicSensitivity)
+                .map(|n| n as u16),
+            lens: text(&exif, Tag::LensModel),
+        });
+    }
+
+    let latitude =
+        coordinate(&exif, Tag::GPSLatitude, Tag::GPSLatitudeRef, b'S');
+    let longitude =
+        coordinate(&exif, Tag::GPSLongitude, Tag::GPSLongitudeRef, b'W');
+
+    if let (Some(latitude), Some(longitude)) = (latitude, longitude) {
+        let loc = Location {
+            latitude,
+            longitude,
+            ..Location::default()
+        };
+
+        if loc.is_valid() {
+            photo.location = Some(loc);
+        }
+    }
+
+    // title, tags and place names are only written to XMP
+    let packet = reader
+        .seek(SeekFrom::Start(0))
+        .ok()
+        .and_then(|_| jpeg_xmp(&mut reader));
+
+    if let Some(text) = packet {
+        match xmp::parse(&text) {
+            Ok(embedded) => embedded.apply(&mut photo),
+            Err(e) => println!(
+                "   {} {} {:?}",
+                "failed to parse XMP in".red(),
+                photo.file.name.red(),
+                e
+            ),
+        }
+    }
+
+    Some(photo)
+}
+
+/// Trimmed text value of an ASCII field. EXIF text is nominally ASCII but
+/// Lightroom writes UTF-8.
+fn text(exif: &Exif, tag: Tag) -> Option<String> {
+    match exif.get_field(tag, In::PRIMARY).map(|f| &f.value) {
+        Some(Value::Ascii(parts)) => parts
+            .first()
+            .map(|b| String::from_utf8_lossy(b).trim().to_string())
+            .filter(|s| !s.is_empty()),
+        _ => None,
+    }
+}
+
+fn unsigned(exif: &Exif, tag: Tag) -> Option<u32> {
+    exif.get_field(tag, In::PRIMARY)
+        .and_then(|f| f.value.get_uint(0))
+}
+
+fn number(exif: &Exif, tag: Tag) -> Option<f32> {
+    match exif.get_field(tag, In::PRIMARY).map(|f| &f.value) {
+        Some(Value::Rational(v)) if !v.is_empty() => Some(v[0].to_f32()),
+        Some(Value::SRational(v)) if !v.is_empty() => Some(v[0].to_f32()),
+        Some(v) => v.get_uint(0).map(|n| n as f32),
+        _ => None,
+    }
+}
+
+/// Rational value written as a reduced fraction (`1/500`) or, if it divides
+/// evenly, as a whole number
+fn fraction(exif: &Exif, tag: Tag) -> Option<String> {
+    match exif.get_field(tag, In::PRIMARY).map(|f| &f.value) {
+        Some(Value::Rational(v)) if !v.is_empty() => {
+            reduce_fraction(v[0].num as i64, v[0].denom as i64)
+        }
+        Some(Value::SRational(v)) if !v.is_empty() => {
+            reduce_fraction(v[0].num as i64, v[0].denom as i64)
+        }
+        _ => None,
+    }
+}
+
+/// Decimal degrees from degree, minute and second rationals, negated if the
+/// reference matches `negative` (`S` or `W`)
+fn coordinate(
+    exif: &Exif,
+    tag: Tag,
+    ref_tag: Tag,
+    negative: u8,
+) -> Option<f32> {
+    let degrees = match exif.get_field(tag, In::PRIMARY).map(|f| &f.value) {
+        Some(Value::Rational(v)) if v.len() >= 3 => {
+            v[0].to_f64() + v[1].to_f64() / 60.0 + v[2].to_f64() / 3600.0
+        }
+        _ => return None,
+    };
+    let is_negative =
+        match exif.get_field(ref_tag, In::PRIMARY).map(|f| &f.value) {
+            Some(Value::Ascii(parts)) => {
+                parts.first().and_then(|b| b.first()) == Some(&negative)
+            }
+            _ => false,
+        };
+
+    Some(if is_negative { -degrees } else { degrees } as f32)
+}
+
+/// Date and time combined with separately recorded timezone offset, if any
+fn date_time(
+    exif: &Exif,
+    tag: Tag,
+    offset_tag: Tag,
+) -> Option<DateTime<FixedOffset>> {
+    let mut value = text(exif, tag)?;
+
+    if let Some(offset) = text(exif, offset_tag) {
+        value.push_str(&offset);
+    }
+
+    parse_date_time(&value).ok()
+}
+
+/// Pixel width and height from EXIF or, since Lightroom doesn't always write
+/// those, from the JPEG frame header
+fn image_size<R: BufRead + Seek>(
+    exif: &Exif,
+    reader: &mut R,
+) -> Option<(u16, u16)> {
+    let size = |w: Tag, h: Tag| match (unsigned(exif, w), unsigned(exif, h)) {
+        (Some(w), Some(h)) if w > 0 && h > 0 => Some((w as u16, h as u16)),
+        _ => None,
+    };
+
+    size(Tag::PixelXDimension, Tag::PixelYDimension)
+        .or_else(|| size(Tag::ImageWidth, Tag::ImageLength))
+        .or_else(|| {
+            reader.seek(SeekFrom::Start(0)).ok()?;
+            jpeg_size(reader)
+        })
+}
+
+/// Read JPEG segments until a start-of-frame marker is found then return its
+/// width and height
+///
+/// https://www.w3.org/Graphics/JPEG/itu-t81.pdf (B.2.2)
+fn jpeg_size<R: Read>(reader: &mut R) -> Option<(u16, u16)> {
+    let mut marker = [0u8; 2];
+    let mut length = [0u8; 2];
+
+    reader.read_exact(&mut marker).ok()?;
+
+    if marker != [0xFF, 0xD8] {
+        // not a JPEG
+        return None;
+    }
+
+    loop {
+        reader.read_exact(&mut marker).ok()?;
+
+        if marker[0] != 0xFF {
+            return None;
+        }
+        reader.read_exact(&mut length).ok()?;
+
+        let len = u16::from_be_bytes(length) as usize;
+
+        match marker[1] {
+            // SOF markers except DHT (C4), JPG (C8) and DAC (CC)
+            0xC0..=0xCF if !matches!(marker[1], 0xC4 | 0xC8 | 0xCC) => {
+                // precision byte then height and width
+                let mut frame = [0u8; 5];
+                reader.read_exact(&mut frame).ok()?;
+
+                let height = u16::from_be_bytes([frame[1], frame[2]]);
+                let width = u16::from_be_bytes([frame[3], frame[4]]);
+
+                return Some((width, height));
+            }
+            _ => {
+                // skip segment (length includes its own two bytes)
+                let mut skip = vec![0u8; len.saturating_sub(2)];
+                reader.read_exact(&mut skip).ok()?;
+            }
+        }
+    }
+}
+
+/// Text of the XMP packet in a JPEG APP1 segment, if there is one, read from
+/// segments before the image data
+fn jpeg_xmp<R: Read>(reader: &mut R) -> Option<String> {
+    let mut marker = [0u8; 2];
+    let mut length = [0u8; 2];
+
+    reader.read_exact(&mut marker).ok()?;
+
+    if marker != [0xFF, 0xD8] {
+        return None;
+    }
+
+    loop {
+        reader.read_exact(&mut marker).ok()?;
+
+        // start of scan (DA) is followed by image data
+        if marker[0] != 0xFF || marker[1] == 0xDA {
+            return None;
+        }
+        reader.read_exact(&mut length).ok()?;
+
+        let len = u16::from_be_bytes(length) as usize;
+        let mut segment = vec![0u8; len.saturating_sub(2)];
+
+        reader.read_exact(&mut segment).ok()?;
+
+        if marker[1] == 0xE1 && segment.starts_with(XMP_HEADER) {
+            return Some(
+                String::from_utf8_lossy(&segment[XMP_HEADER.len()..])
+                    .to_string(),
+            );
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::parse_file;
+    use crate::{config::PhotoConfig, models::ExposureMode};
+    use chrono::DateTime;
+    use std::path::Path;
+
+    #[test]
+    fn parse_fixture_test() {
+        let photo = parse_file(
+            Path::new("./src/fixtures/img_006-of-021.jpg"),
+            6,
+            &PhotoConfig::fixture(),
+        )
+        .unwrap();
+
+        assert_eq!(photo.file.name, "img_006-of-021.jpg");
+        assert_eq!(photo.title, Some("Time to move on".to_owned()));
+        assert_eq!(
+            photo.tags,
+            vec![
+                "BMW R1200GS Adventure",
+                "Fire",
+                "Honda CRF 230L",
+                "Motorcycle",
+                "Mountain",
+                "Tent",
+                "Yamaha XT 250"
+            ]
+        );
+        assert_eq!(photo.state, Some("Idaho".to_owned()));
+        assert_eq!(photo.index, 6);
+        assert_eq!(photo.artist, Some("Jason Abbott".to_owned()));
+        assert_eq!(
+            photo.software,
+            "Adobe Photoshop Lightroom Classic 9.2 (Windows)"
+        );
+        assert_eq!(
+            photo.date_taken,
+            Some(
+                DateTime::parse_from_rfc3339("2008-09-20T07:48:42-06:00")
+                    .unwrap()
+            )
+        );
+        assert!(photo
+            .caption
+            .unwrap()
+            .starts_with("<p>Joel said he’d always wanted"));
+
+        assert_eq!(photo.size.original.width, 100);
+        assert_eq!(photo.size.original.height, 67);
+
+        let camera = photo.camera.unwrap();
+
+        assert_eq!(camera.name, "NIKON D3");
+        assert_eq!(camera.lens, Some("24.0-70.0 mm f/2.8".to_owned()));
+        assert_eq!(camera.shutter_speed, Some("1/10".to_owned()));
+        assert_eq!(camera.compensation, Some("0".to_owned()));
+        assert_eq!(camera.mode, ExposureMode::AperturePriority);
+        assert_eq!(camera.aperture, Some(8.0));
+        assert_eq!(camera.focal_length, Some(24.0));
+        assert_eq!(camera.iso, Some(200));
+
+        let location = photo.location.unwrap();
+
+        assert!((location.latitude - 47.030_222).abs() < 0.000_01);
+        assert!((location.longitude + 115.985_11).abs() < 0.000_01);
+
+        let other = parse_file(
+            Path::new("./src/fixtures/img_003-of-016.jpg"),
+            3,
+            &PhotoConfig::fixture(),
+        )
+        .unwrap();
+
+        assert_eq!(other.title, Some("Bigger, bigger".to_owned()));
+        assert_eq!(other.tags, vec!["Fire", "Rain"]);
+    }
+}
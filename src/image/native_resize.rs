@@ -0,0 +1,289 @@
+//! Resize and encode photos in-process rather than spawning an external tool
+
+use crate::{
+    config::PhotoConfig,
+    image::{resize::render_sizes, PhotoResizer},
+    models::{Photo, Size},
+};
+use ::image::{
+    codecs::{avif::AvifEncoder, jpeg::JpegEncoder},
+    imageops::FilterType,
+    DynamicImage, ExtendedColorType, ImageDecoder, ImageEncoder, ImageReader,
+    RgbImage,
+};
+use moxcms::{ColorProfile, Layout, Transform8BitExecutor, TransformOptions};
+use std::{fs, path::Path, sync::Arc};
+
+/// Lossy encoding quality from `0` to `100`
+const QUALITY: u8 = 90;
+
+/// AVIF encoder speed from `1` (smallest files) to `10` (fastest)
+const AVIF_SPEED: u8 = 6;
+
+/// Gaussian blur radius of the unsharp mask applied to reduced photos,
+/// matching ImageMagick `-unsharp 0x1+1+0.05`
+const UNSHARP_SIGMA: f32 = 1.0;
+
+/// Smallest brightness difference the unsharp mask affects (5% of 255)
+const UNSHARP_THRESHOLD: i32 = 13;
+
+/// Decodes, resizes, sharpens and encodes photos in-process. Each photo is
+/// decoded once for all its sizes and formats. Colors are converted to sRGB
+/// from any embedded profile since not every encoder can embed one.
+pub struct NativeResize;
+
+impl PhotoResizer for NativeResize {
+    fn create_sizes(
+        &self,
+        folder: &Path,
+        photo: &Photo,
+        config: &PhotoConfig,
+        formats: &[&str],
+    ) -> Vec<String> {
+        if formats.is_empty() {
+            return Vec::new();
+        }
+        let (source, profile) = match open(&folder.join(&photo.file.name)) {
+            Ok(decoded) => decoded,
+            Err(e) => return vec![e],
+        };
+        let to_srgb = profile.as_deref().and_then(srgb_transform);
+        let mut errors: Vec<String> = Vec::new();
+
+        for (size, suffix, square) in render_sizes(config).iter() {
+            let mut image = resize(&source, *size, *square);
+
+            if let Some(transform) = &to_srgb {
+                match convert(&image, transform) {
+                    Ok(converted) => image = converted,
+                    Err(e) => errors.push(e),
+                }
+            }
+
+            for ext in formats {
+                let name = format!("{:03}_{}{}", photo.index, suffix, ext);
+
+                if let Err(e) = encode(&image, &folder.join(&name), ext) {
+                    errors.push(format!("{}: {}", name, e));
+                }
+            }
+        }
+        errors
+    }
+}
+
+/// Decode image along with any embedded ICC color profile
+fn open(path: &Path) -> Result<(DynamicImage, Option<Vec<u8>>), String> {
+    let mut decoder = ImageReader::open(path)
+        .and_then(|r| r.with_guessed_format())
+        .map_err(|e| e.to_string())?
+        .into_decoder()
+        .map_err(|e| e.to_string())?;
+    let profile = decoder.icc_profile().ok().flatten();
+    let image =
+        DynamicImage::from_decoder(decoder).map_err(|e| e.to_string())?;
+
+    Ok((image, profile))
+}
+
+/// Transform from an ICC color profile to sRGB or `None` if the profile
+/// can't be read, in which case colors are left as they are
+fn srgb_transform(icc: &[u8]) -> Option<Arc<Transform8BitExecutor>> {
+    ColorProfile::new_from_slice(icc)
+        .ok()?
+        .create_transform_8bit(
+            Layout::Rgb,
+            &ColorProfile::new_srgb(),
+            Layout::Rgb,
+            TransformOptions::default(),
+        )
+        .ok()
+}
+
+/// Apply color `transform` to every pixel
+fn convert(
+    image: &RgbImage,
+    transform: &Arc<Transform8BitExecutor>,
+) -> Result<RgbImage, String> {
+    let mut pixels = vec![0u8; image.as_raw().len()];
+
+    transform
+        .transform(image.as_raw(), &mut pixels)
+        .map_err(|e| format!("{:?}", e))?;
+
+    RgbImage::from_raw(image.width(), image.height(), pixels)
+        .ok_or_else(|| "converted image has the wrong size".to_owned())
+}
+
+/// Crop to a center square `size` on each edge or limit the long edge to
+/// `size`, sharpening whatever is reduced. Photos are never enlarged.
+fn resize(source: &DynamicImage, size: u16, make_square: bool) -> RgbImage {
+    let original =
+        Size::new(source.width() as u16, source.height() as u16, String::new());
+
+    let image = if make_square {
+        let (x, y, edge) = original.center_square();
+        let edge = edge as u32;
+        let size = (size as u32).min(edge);
+
+        source
+            .crop_imm(x as u32, y as u32, edge, edge)
+            .resize_exact(size, size, FilterType::Lanczos3)
+    } else {
+        let target = original.limit_to(size, String::new());
+
+        if target == original {
+            // drop any alpha channel without reducing
+            return source.to_rgb8();
+        }
+        source.resize_exact(
+            target.width as u32,
+            target.height as u32,
+            FilterType::Lanczos3,
+        )
+    };
+
+    image.unsharpen(UNSHARP_SIGMA, UNSHARP_THRESHOLD).to_rgb8()
+}
+
+/// Write image in the format matching `ext`, with leading period
+fn encode(image: &RgbImage, path: &Path, ext: &str) -> Result<(), String> {
+    match ext.trim_start_matches('.').to_lowercase().as_str() {
+        "webp" => {
+            let encoded = webp::Encoder::from_rgb(
+                image.as_raw(),
+                image.width(),
+                image.height(),
+            )
+            .encode(QUALITY as f32);
+
+            fs::write(path, &*encoded).map_err(|e| e.to_string())
+        }
+        "jpg" | "jpeg" => {
+            let file = fs::File::create(path).map_err(|e| e.to_string())?;
+
+            JpegEncoder::new_with_quality(file, QUALITY)
+                .encode_image(image)
+                .map_err(|e| e.to_string())
+        }
+        "avif" => {
+            let file = fs::File::create(path).map_err(|e| e.to_string())?;
+
+            AvifEncoder::new_with_speed_quality(file, AVIF_SPEED, QUALITY)
+                .write_image(
+                    image.as_raw(),
+                    image.width(),
+                    image.height(),
+                    ExtendedColorType::Rgb8,
+                )
+                .map_err(|e| e.to_string())
+        }
+        _ => image.save(path).map_err(|e| e.to_string()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::NativeResize;
+    use crate::{
+        config::PhotoConfig,
+        image::PhotoResizer,
+        models::{Photo, PhotoFile},
+    };
+    use ::image::{codecs::png::PngEncoder, ImageEncoder, RgbImage};
+    use moxcms::ColorProfile;
+    use std::{env, fs, path::Path};
+
+    /// Photo configured to render sizes no larger than the fixtures
+    fn setup(folder: &Path, name: &str) -> (PhotoConfig, Photo) {
+        let mut config = PhotoConfig::fixture();
+        let photo = Photo {
+            file: PhotoFile {
+                name: name.to_owned(),
+            },
+            index: 6,
+            ..Photo::default()
+        };
+        config.size.render.large = 200;
+        config.size.render.medium = 50;
+        config.size.render.small = 30;
+        config.size.render.thumb = 20;
+
+        let _ = fs::remove_dir_all(folder);
+        fs::create_dir_all(folder).unwrap();
+
+        (config, photo)
+    }
+
+    #[test]
+    fn create_sizes_test() {
+        let folder = env::temp_dir().join("exifweb-resize-test");
+        let name = "img_006-of-021.jpg";
+        let (config, photo) = setup(&folder, name);
+
+        fs::copy(format!("./src/fixtures/{}", name), folder.join(name))
+            .unwrap();
+
+        assert!(NativeResize
+            .create_sizes(&folder, &photo, &config, &[".webp", ".jpg", ".avif"])
+            .is_empty());
+
+        let size = |file: &str| {
+            let image = ::image::open(folder.join(file)).unwrap();
+            (image.width(), image.height())
+        };
+
+        // fixture is 100 by 67
+        assert_eq!(size("006_m.webp"), (50, 34));
+        assert_eq!(size("006_l.webp"), (100, 67));
+        assert_eq!(size("006_t.webp"), (20, 20));
+        assert_eq!(size("006_s.jpg"), (30, 20));
+        // AVIF can be encoded but not decoded so check the file type box
+        assert_eq!(
+            &fs::read(folder.join("006_s.avif")).unwrap()[4..12],
+            b"ftypavif"
+        );
+        // a missing photo is one error rather than one per size
+        assert_eq!(
+            NativeResize
+                .create_sizes(&folder, &Photo::default(), &config, &[".webp"])
+                .len(),
+            1
+        );
+
+        let _ = fs::remove_dir_all(&folder);
+    }
+
+    #[test]
+    fn color_profile_test() {
+        let folder = env::temp_dir().join("exifweb-color-profile-test");
+        let name = "p3.png";
+        let (config, photo) = setup(&folder, name);
+        let color = [200, 100, 50];
+        let mut encoder =
+            PngEncoder::new(fs::File::create(folder.join(name)).unwrap());
+
+        encoder
+            .set_icc_profile(ColorProfile::new_display_p3().encode().unwrap())
+            .unwrap();
+        encoder
+            .write_image(
+                RgbImage::from_pixel(40, 40, ::image::Rgb(color)).as_raw(),
+                40,
+                40,
+                ::image::ExtendedColorType::Rgb8,
+            )
+            .unwrap();
+
+        assert!(NativeResize
+            .create_sizes(&folder, &photo, &config, &[".png"])
+            .is_empty());
+
+        let saved = ::image::open(folder.join("006_l.png")).unwrap().to_rgb8();
+
+        // converted from Display P3 to sRGB, which is less saturated
+        assert!(saved.get_pixel(20, 20).0[0] > color[0]);
+
+        let _ = fs::remove_dir_all(&folder);
+    }
+}
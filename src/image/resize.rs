@@ -0,0 +1,249 @@
+//! Create photo sizes across a pool of worker threads
+
+use super::photo_resizer;
+use crate::{
+    config::PhotoConfig,
+    models::{suffix, Photo, Post},
+};
+use colored::*;
+use std::{
+    io::{self, Write},
+    iter,
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Mutex,
+    },
+    thread,
+};
+
+/// Photo that couldn't be resized
+#[derive(Debug, PartialEq)]
+pub struct ResizeFailure {
+    /// Full path to the source photo
+    pub path: PathBuf,
+    /// Error for each size that failed
+    pub errors: Vec<String>,
+}
+
+/// Create every size of each photo, given with the folder it's in, in every
+/// output format, running up to the configured number of workers at once.
+/// Progress is shown as photos complete and failures are returned rather than
+/// stopping the others.
+pub fn create_sizes(
+    photos: &[(PathBuf, &Photo)],
+    config: &PhotoConfig,
+) -> Vec<ResizeFailure> {
+    let resizer = photo_resizer(config);
+    let formats: Vec<&str> = output_formats(config).collect();
+
+    let errors = in_parallel(
+        photos,
+        worker_count(config),
+        |(folder, photo)| resizer.create_sizes(folder, photo, config, &formats),
+        progress,
+    );
+
+    if !photos.is_empty() {
+        println!();
+    }
+
+    photos
+        .iter()
+        .zip(errors)
+        .filter(|(_, errors)| !errors.is_empty())
+        .map(|((folder, photo), errors)| ResizeFailure {
+            path: folder.join(&photo.file.name),
+            errors,
+        })
+        .collect()
+}
+
+/// Edge size, file suffix and whether to crop square for each rendered size
+pub fn render_sizes(c: &PhotoConfig) -> [(u16, &'static str, bool); 4] {
+    let size = &c.size.render;
+
+    [
+        (size.large, suffix::LARGE, false),
+        (size.medium, suffix::MEDIUM, false),
+        (size.small, suffix::SMALL, false),
+        (size.thumb, suffix::THUMB, true),
+    ]
+}
+
+/// Extensions of every format photos are saved in, starting with the fallback
+/// `output_ext`
+pub fn output_formats(config: &PhotoConfig) -> impl Iterator<Item = &str> {
+    iter::once(config.output_ext.as_str())
+        .chain(config.output_formats.iter().map(String::as_str))
+}
+
+/// Whether `photo` in the post `folder` should be resized because it changed,
+/// is missing sizes or resizing is forced. Render and its dry run both use
+/// this so they agree.
+pub fn needs_resize(
+    folder: &Path,
+    post: &Post,
+    photo: &Photo,
+    force: bool,
+) -> bool {
+    force || post.photo_changed(photo) || !sizes_saved(folder, photo)
+}
+
+/// Whether every size of the photo has been saved in every output format, so
+/// newly configured formats are created without forcing a full resize
+fn sizes_saved(folder: &Path, photo: &Photo) -> bool {
+    let s = &photo.size;
+
+    [&s.large, &s.medium, &s.small, &s.thumb]
+        .iter()
+        .flat_map(|size| iter::once(&size.name).chain(size.alternates.iter()))
+        .all(|name| folder.join(name).is_file())
+}
+
+/// Whether the file name matches the `NNN_s.ext` pattern of a resized photo,
+/// whatever its extension
+pub fn is_resized(name: &str) -> bool {
+    let stem = match name.split_once('.') {
+        Some((stem, _)) => stem,
+        _ => return false,
+    };
+    let (index, size) = stem.split_at(stem.len().min(4));
+
+    index.len() == 4
+        && index[..3].chars().all(|c| c.is_ascii_digit())
+        && index.ends_with('_')
+        && [suffix::LARGE, suffix::MEDIUM, suffix::SMALL, suffix::THUMB]
+            .contains(&size)
+}
+
+/// Configured worker count or, if `0`, one per processor
+fn worker_count(config: &PhotoConfig) -> usize {
+    match config.resize_workers {
+        0 => thread::available_parallelism().map_or(1, |n| n.get()),
+        n => n,
+    }
+}
+
+/// Run `task` for each item on up to `workers` threads, calling `progress`
+/// with the number completed and the total as each finishes. Results are
+/// returned in item order.
+fn in_parallel<T, R, F, P>(
+    items: &[T],
+    workers: usize,
+    task: F,
+    progress: P,
+) -> Vec<R>
+where
+    T: Sync,
+    R: Send,
+    F: Fn(&T) -> R + Sync,
+    P: Fn(usize, usize) + Sync,
+{
+    let next = AtomicUsize::new(0);
+    let done = AtomicUsize::new(0);
+    let results: Mutex<Vec<(usize, R)>> = Mutex::new(Vec::new());
+    let total = items.len();
+
+    thread::scope(|scope| {
+        for _ in 0..workers.max(1).min(total) {
+            scope.spawn(|| loop {
+                let i = next.fetch_add(1, Ordering::SeqCst);
+
+                if i >= total {
+                    break;
+                }
+                let result = task(&items[i]);
+
+                results.lock().unwrap().push((i, result));
+                progress(done.fetch_add(1, Ordering::SeqCst) + 1, total);
+            });
+        }
+    });
+
+    let mut results = results.into_inner().unwrap();
+    results.sort_by_key(|(i, _)| *i);
+    results.into_iter().map(|(_, r)| r).collect()
+}
+
+/// Overwrite the progress line
+fn progress(done: usize, total: usize) {
+    let mut out = io::stdout();
+
+    write!(
+        out,
+        "\r   Resized {} of {} photos",
+        done.to_string().bold(),
+        total
+    )
+    .and_then(|_| out.flush())
+    .unwrap_or(());
+}
+
+/// Report photos that couldn't be resized
+pub fn report_failures(failures: &[ResizeFailure], root: &Path) {
+    for f in failures {
+        let path = f.path.strip_prefix(root).unwrap_or(&f.path);
+
+        println!(
+            "   {} {}",
+            "Failed to resize".red(),
+            path.to_string_lossy().red()
+        );
+        for e in f.errors.iter() {
+            println!("{:6}{}", "", e);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{in_parallel, is_resized};
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    #[test]
+    fn is_resized_test() {
+        assert!(is_resized("001_l.webp"));
+        assert!(is_resized("012_t.jpg"));
+        assert!(!is_resized("001_o.jpg"));
+        assert!(!is_resized("001_l"));
+        assert!(!is_resized("img_001-of-012.jpg"));
+        assert!(!is_resized("a01_m.jpg"));
+    }
+
+    #[test]
+    fn in_parallel_test() {
+        let items: Vec<usize> = (0..50).collect();
+        let calls = AtomicUsize::new(0);
+        let reported = AtomicUsize::new(0);
+        let results = in_parallel(
+            &items,
+            4,
+            |n| {
+                calls.fetch_add(1, Ordering::SeqCst);
+
+                if n % 10 == 3 {
+                    Err(format!("failed {}", n))
+                } else {
+                    Ok(())
+                }
+            },
+            |done, total| {
+                assert_eq!(total, 50);
+                reported.fetch_max(done, Ordering::SeqCst);
+            },
+        );
+
+        assert_eq!(calls.load(Ordering::SeqCst), 50);
+        assert_eq!(reported.load(Ordering::SeqCst), 50);
+        // results are in item order regardless of which finished first
+        assert_eq!(results.len(), 50);
+        assert_eq!(results.iter().filter(|r| r.is_err()).count(), 5);
+        assert_eq!(results[3], Err("failed 3".to_owned()));
+        assert_eq!(results[43], Err("failed 43".to_owned()));
+        assert_eq!(results[44], Ok(()));
+        assert!(
+            in_parallel(&Vec::<usize>::new(), 4, |_| (), |_, _| ()).is_empty()
+        );
+    }
+}
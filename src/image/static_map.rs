@@ -0,0 +1,449 @@
+//! Static map images of post photo locations, cached on disk so identical
+//! requests are only made once
+
+use super::MapBox;
+use crate::{
+    config::{BlogConfig, MapBoxConfig},
+    models::Post,
+};
+use colored::*;
+use sha1::{Digest, Sha1};
+use std::{collections::HashSet, fs, path::Path};
+
+/// Folder in the blog root where map images were once cached
+const LEGACY_CACHE_FOLDER: &str = ".map-cache";
+
+/// Background, grid and pin colors of placeholder maps
+const LAND: [u8; 3] = [0xe8, 0xe6, 0xdf];
+const GRID: [u8; 3] = [0xd6, 0xd3, 0xc9];
+const PIN: [u8; 3] = [0xc0, 0x39, 0x2b];
+const PIN_EDGE: [u8; 3] = [0xff, 0xff, 0xff];
+
+/// Pin radius in pixels
+const PIN_RADIUS: f64 = 9.0;
+
+/// Source of static map images
+pub trait StaticMapProvider {
+    /// Text identifying a request. Requests with the same key produce the same
+    /// image so it shouldn't include credentials that may change.
+    fn cache_key(&self, pins: &[(f32, f32)], width: u16, height: u16)
+        -> String;
+
+    /// PNG bytes of a map `width` by `height` points (doubled for high density
+    /// displays) showing `pins` at longitude, latitude pairs
+    fn fetch(
+        &self,
+        pins: &[(f32, f32)],
+        width: u16,
+        height: u16,
+    ) -> Result<Vec<u8>, String>;
+}
+
+/// MapBox if an access token is configured, otherwise locally drawn pins
+pub fn static_map_provider(
+    config: &MapBoxConfig,
+) -> Box<dyn StaticMapProvider + '_> {
+    if config.access_token.is_empty() {
+        Box::new(Placeholder)
+    } else {
+        Box::new(MapBox::new(config))
+    }
+}
+
+/// Save inline and category page map images for post photo locations
+pub fn save_static(
+    post: &Post,
+    root: &Path,
+    config: &BlogConfig,
+    provider: &dyn StaticMapProvider,
+) {
+    if post.photo_locations.is_empty() {
+        return;
+    }
+    let cache = config.mapbox.cache_path(root);
+    let pins = map_pins(post);
+
+    for (name, width, height) in map_sizes(post, config).iter() {
+        let result = cached_map(&cache, provider, &pins, *width, *height)
+            .and_then(|bytes| {
+                fs::write(root.join(&post.path).join(name), bytes)
+                    .map_err(|e| format!("{:?}", e))
+            });
+
+        if let Err(e) = result {
+            println!("   {} {} {}", "Failed to save".red(), name.red(), e);
+        }
+    }
+}
+
+/// Remove cached map images no longer used by any post, and the cache
+/// folder previously kept in the blog root
+pub fn prune_static<'a>(
+    posts: impl Iterator<Item = &'a Post>,
+    root: &Path,
+    config: &BlogConfig,
+    provider: &dyn StaticMapProvider,
+) {
+    let legacy = root.join(LEGACY_CACHE_FOLDER);
+
+    if legacy.is_dir() && fs::remove_dir_all(&legacy).is_ok() {
+        println!("   Removed {} from blog root", LEGACY_CACHE_FOLDER);
+    }
+
+    let used: HashSet<String> = posts
+        .filter(|p| !p.photo_locations.is_empty())
+        .flat_map(|p| {
+            let pins = map_pins(p);
+
+            map_sizes(p, config)
+                .iter()
+                .map(|(_, width, height)| {
+                    cache_file(provider, &pins, *width, *height)
+                })
+                .collect::<Vec<String>>()
+        })
+        .collect();
+
+    let removed = prune_cache(&config.mapbox.cache_path(root), &used);
+
+    if removed > 0 {
+        println!("   Removed {} unused cached map image(s)", removed);
+    }
+}
+
+/// Remove files in the cache `folder` not named in `used` and return how many
+/// were removed
+fn prune_cache(folder: &Path, used: &HashSet<String>) -> usize {
+    fs::read_dir(folder)
+        .map(|entries| {
+            entries
+                .filter_map(|e| e.ok())
+                .filter(|e| {
+                    !used.contains(e.file_name().to_string_lossy().as_ref())
+                })
+                .filter(|e| fs::remove_file(e.path()).is_ok())
+                .count()
+        })
+        .unwrap_or(0)
+}
+
+/// Pins at photo locations in photo order, so a line drawn through the pins
+/// follows the trip
+fn map_pins(post: &Post) -> Vec<(f32, f32)> {
+    if post.photo_points.is_empty() {
+        post.photo_locations.clone()
+    } else {
+        post.photo_points
+            .iter()
+            .map(|(_, lon, lat)| (*lon, *lat))
+            .collect()
+    }
+}
+
+/// File name, width and height of the inline and category page maps
+fn map_sizes(
+    post: &Post,
+    config: &BlogConfig,
+) -> [(&'static str, u16, u16); 2] {
+    [
+        (
+            "map.png",
+            config.style.content_width,
+            config.style.inline_map_height,
+        ),
+        (
+            "map_small.png",
+            post.cover_map_size.0,
+            post.cover_map_size.1,
+        ),
+    ]
+}
+
+/// Name of the cached image for a map request
+fn cache_file(
+    provider: &dyn StaticMapProvider,
+    pins: &[(f32, f32)],
+    width: u16,
+    height: u16,
+) -> String {
+    let digest: String =
+        Sha1::digest(provider.cache_key(pins, width, height).as_bytes())
+            .iter()
+            .map(|b| format!("{:02x}", b))
+            .collect();
+
+    format!("{}.png", digest)
+}
+
+/// Map image from the cache `folder` or, if it isn't there, from the provider
+fn cached_map(
+    folder: &Path,
+    provider: &dyn StaticMapProvider,
+    pins: &[(f32, f32)],
+    width: u16,
+    height: u16,
+) -> Result<Vec<u8>, String> {
+    let path = folder.join(cache_file(provider, pins, width, height));
+
+    if let Ok(bytes) = fs::read(&path) {
+        return Ok(bytes);
+    }
+
+    let bytes = provider.fetch(pins, width, height)?;
+
+    if let Err(e) =
+        fs::create_dir_all(folder).and_then(|_| fs::write(&path, &bytes))
+    {
+        println!("   {} {:?}", "Failed to cache map image".red(), e);
+    }
+    Ok(bytes)
+}
+
+/// Draws pins on a plain background, fitted to their bounds, for when no map
+/// service is available
+pub struct Placeholder;
+
+impl StaticMapProvider for Placeholder {
+    fn cache_key(
+        &self,
+        pins: &[(f32, f32)],
+        width: u16,
+        height: u16,
+    ) -> String {
+        let pins: Vec<String> = pins
+            .iter()
+            .map(|(lon, lat)| format!("{},{}", lon, lat))
+            .collect();
+
+        format!("placeholder/{}/{}x{}@2x", pins.join(";"), width, height)
+    }
+
+    fn fetch(
+        &self,
+        pins: &[(f32, f32)],
+        width: u16,
+        height: u16,
+    ) -> Result<Vec<u8>, String> {
+        let (w, h) = (width as usize * 2, height as usize * 2);
+        let mut pixels: Vec<u8> = Vec::with_capacity(w * h * 3);
+
+        for y in 0..h {
+            for x in 0..w {
+                let color = if x % 64 == 0 || y % 64 == 0 {
+                    GRID
+                } else {
+                    LAND
+                };
+                pixels.extend_from_slice(&color);
+            }
+        }
+
+        for (x, y) in fit(pins, w, h) {
+            draw_pin(&mut pixels, w, h, x, y);
+        }
+
+        encode_png(&pixels, w as u32, h as u32)
+    }
+}
+
+/// Pixel positions of longitude, latitude pairs scaled to fill an image `w`
+/// by `h` less a margin. Longitudes are narrowed by the cosine of the mean
+/// latitude so distances keep their proportions.
+fn fit(pins: &[(f32, f32)], w: usize, h: usize) -> Vec<(f64, f64)> {
+    if pins.is_empty() {
+        return Vec::new();
+    }
+    let mean_lat = pins.iter().map(|(_, lat)| *lat as f64).sum::<f64>()
+        / pins.len() as f64;
+    let narrow = mean_lat.to_radians().cos();
+    let points: Vec<(f64, f64)> = pins
+        .iter()
+        .map(|(lon, lat)| (*lon as f64 * narrow, *lat as f64))
+        .collect();
+
+    let min_x = points.iter().map(|p| p.0).fold(f64::INFINITY, f64::min);
+    let max_x = points.iter().map(|p| p.0).fold(f64::NEG_INFINITY, f64::max);
+    let min_y = points.iter().map(|p| p.1).fold(f64::INFINITY, f64::min);
+    let max_y = points.iter().map(|p| p.1).fold(f64::NEG_INFINITY, f64::max);
+
+    let margin = (w.min(h) as f64 * 0.15).max(PIN_RADIUS * 2.0);
+    let (room_x, room_y) = (w as f64 - margin * 2.0, h as f64 - margin * 2.0);
+    let (span_x, span_y) = (max_x - min_x, max_y - min_y);
+    let scale = match (span_x > 0.0, span_y > 0.0) {
+        (true, true) => (room_x / span_x).min(room_y / span_y),
+        (true, false) => room_x / span_x,
+        (false, true) => room_y / span_y,
+        // single location
+        (false, false) => 0.0,
+    };
+    let (center_x, center_y) = ((min_x + max_x) / 2.0, (min_y + max_y) / 2.0);
+
+    points
+        .iter()
+        .map(|(x, y)| {
+            (
+                w as f64 / 2.0 + (x - center_x) * scale,
+                // latitude increases upward
+                h as f64 / 2.0 - (y - center_y) * scale,
+            )
+        })
+        .collect()
+}
+
+/// Draw outlined circle centered at `cx`, `cy`
+fn draw_pin(pixels: &mut [u8], w: usize, h: usize, cx: f64, cy: f64) {
+    let edge = PIN_RADIUS + 2.0;
+    let left = (cx - edge).floor().max(0.0) as usize;
+    let right = ((cx + edge).ceil() as usize).min(w.saturating_sub(1));
+    let top = (cy - edge).floor().max(0.0) as usize;
+    let bottom = ((cy + edge).ceil() as usize).min(h.saturating_sub(1));
+
+    for y in top..=bottom {
+        for x in left..=right {
+            let d = ((x as f64 - cx).powi(2) + (y as f64 - cy).powi(2)).sqrt();
+            let color = if d <= PIN_RADIUS {
+                PIN
+            } else if d <= edge {
+                PIN_EDGE
+            } else {
+                continue;
+            };
+            let i = (y * w + x) * 3;
+
+            pixels[i..i + 3].copy_from_slice(&color);
+        }
+    }
+}
+
+/// Encode RGB `pixels` as PNG
+fn encode_png(
+    pixels: &[u8],
+    width: u32,
+    height: u32,
+) -> Result<Vec<u8>, String> {
+    let mut bytes: Vec<u8> = Vec::new();
+    {
+        let mut encoder = png::Encoder::new(&mut bytes, width, height);
+
+        encoder.set_color(png::ColorType::RGB);
+        encoder.set_depth(png::BitDepth::Eight);
+
+        let mut writer = encoder.write_header().map_err(|e| e.to_string())?;
+
+        writer.write_image_data(pixels).map_err(|e| e.to_string())?;
+    }
+    Ok(bytes)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        cache_file, cached_map, fit, prune_cache, Placeholder,
+        StaticMapProvider,
+    };
+    use crate::{config::MapBoxConfig, image::MapBox};
+    use std::{
+        collections::HashSet,
+        env, fs,
+        io::{Read, Write},
+        net::TcpListener,
+        thread,
+    };
+
+    const PINS: [(f32, f32); 2] = [(-116.0, 46.0), (-115.9, 46.1)];
+
+    #[test]
+    fn cached_map_test() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap();
+        // stand-in for the map service that answers a single request
+        let server = thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            let mut request = [0u8; 2048];
+            let read = stream.read(&mut request).unwrap();
+            let body = b"map image";
+
+            write!(
+                stream,
+                "HTTP/1.1 200 OK\r\nContent-Type: image/png\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+                body.len()
+            )
+            .unwrap();
+            stream.write_all(body).unwrap();
+
+            String::from_utf8_lossy(&request[..read]).to_string()
+        });
+        let config = MapBoxConfig::fixture(&format!("http://{}", address));
+
+        let provider = MapBox::new(&config);
+        let folder = env::temp_dir().join("exifweb-map-cache-test");
+        let _ = fs::remove_dir_all(&folder);
+
+        assert_eq!(
+            cached_map(&folder, &provider, &PINS, 300, 200).unwrap(),
+            b"map image"
+        );
+
+        let request = server.join().unwrap();
+
+        assert!(request.starts_with("GET /styles/v1/owner/static/static/"));
+        assert!(request.contains("/auto/300x200@2x?access_token=token&"));
+        assert!(!provider.cache_key(&PINS, 300, 200).contains("token"));
+
+        // served from the cache since the stand-in server has stopped
+        assert_eq!(
+            cached_map(&folder, &provider, &PINS, 300, 200).unwrap(),
+            b"map image"
+        );
+        assert!(cached_map(&folder, &provider, &PINS, 300, 201).is_err());
+
+        let _ = fs::remove_dir_all(&folder);
+    }
+
+    #[test]
+    fn prune_cache_test() {
+        let folder = env::temp_dir().join("exifweb-map-prune-test");
+        let _ = fs::remove_dir_all(&folder);
+
+        assert!(cached_map(&folder, &Placeholder, &PINS, 30, 20).is_ok());
+        assert!(cached_map(&folder, &Placeholder, &PINS, 40, 20).is_ok());
+
+        let used: HashSet<String> =
+            vec![cache_file(&Placeholder, &PINS, 30, 20)]
+                .into_iter()
+                .collect();
+
+        assert_eq!(prune_cache(&folder, &used), 1);
+        assert_eq!(fs::read_dir(&folder).unwrap().count(), 1);
+        assert!(folder
+            .join(cache_file(&Placeholder, &PINS, 30, 20))
+            .exists());
+        assert_eq!(prune_cache(&folder.join("missing"), &used), 0);
+
+        let _ = fs::remove_dir_all(&folder);
+    }
+
+    #[test]
+    fn placeholder_test() {
+        let bytes = Placeholder.fetch(&PINS, 100, 50).unwrap();
+        let decoder = png::Decoder::new(bytes.as_slice());
+        let (info, _) = decoder.read_info().unwrap();
+
+        assert_eq!((info.width, info.height), (200, 100));
+        assert_ne!(
+            Placeholder.cache_key(&PINS, 100, 50),
+            Placeholder.cache_key(&PINS[..1], 100, 50)
+        );
+    }
+
+    #[test]
+    fn fit_test() {
+        let points = fit(&PINS, 200, 100);
+
+        // fitted to the height, with the first pin at lower left
+        assert!((points[0].1 - 82.0).abs() < 0.001);
+        assert!((points[1].1 - 18.0).abs() < 0.001);
+        assert!(points[0].0 < points[1].0);
+        assert_eq!(fit(&PINS[..1], 200, 100), vec![(100.0, 50.0)]);
+    }
+}
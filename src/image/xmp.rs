@@ -0,0 +1,297 @@
+//! Read XMP sidecar files written by Lightroom next to exported images
+
+use crate::{html, models::Photo, tools::folder_name};
+use colored::*;
+use serde::Deserialize;
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+/// Extension of XMP sidecar files
+pub const SIDECAR_EXT: &str = ".xmp";
+
+/// Items in an `rdf:Alt`, `rdf:Bag` or `rdf:Seq`
+#[derive(Deserialize, Debug, Default)]
+struct Items {
+    #[serde(default, rename = "li")]
+    items: Vec<String>,
+}
+
+/// Language alternatives. Lightroom only writes `x-default`.
+#[derive(Deserialize, Debug)]
+struct LangAlt {
+    #[serde(rename = "Alt")]
+    alt: Items,
+}
+
+impl LangAlt {
+    fn first(self) -> Option<String> {
+        self.alt.items.into_iter().next()
+    }
+}
+
+#[derive(Deserialize, Debug)]
+struct Bag {
+    #[serde(rename = "Bag")]
+    bag: Items,
+}
+
+/// Simple values like `photoshop:State` may be written either as attributes
+/// or as child elements. Both are matched by local name.
+#[derive(Deserialize, Debug)]
+struct Description {
+    /// `dc:title`
+    title: Option<LangAlt>,
+    /// `dc:description`
+    description: Option<LangAlt>,
+    /// `dc:subject`
+    subject: Option<Bag>,
+    #[serde(rename = "City")]
+    city: Option<String>,
+    #[serde(rename = "State")]
+    state: Option<String>,
+    #[serde(rename = "UsageTerms")]
+    usage_terms: Option<LangAlt>,
+    #[serde(rename = "Rating")]
+    rating: Option<i8>,
+}
+
+#[derive(Deserialize, Debug)]
+struct Rdf {
+    #[serde(default, rename = "Description")]
+    descriptions: Vec<Description>,
+}
+
+#[derive(Deserialize, Debug)]
+struct XmpMeta {
+    #[serde(rename = "RDF")]
+    rdf: Rdf,
+}
+
+/// Photo metadata read from an XMP sidecar
+#[derive(Debug, Default, PartialEq)]
+pub struct Sidecar {
+    pub title: Option<String>,
+    pub caption: Option<String>,
+    pub tags: Option<Vec<String>>,
+    pub city: Option<String>,
+    pub state: Option<String>,
+    pub usage_terms: Option<String>,
+    /// Star rating from `1` to `5`. Rejected photos (`-1`) and unrated photos
+    /// (`0`) are `None`.
+    pub rating: Option<u8>,
+}
+
+impl Sidecar {
+    /// Update `photo` with sidecar values, which take precedence over those
+    /// embedded in the image
+    pub fn apply(self, photo: &mut Photo) {
+        if self.title.is_some() {
+            photo.title = self.title;
+        }
+        if let Some(caption) = self.caption {
+            photo.caption = Some(html::caption(&caption));
+        }
+        if let Some(tags) = self.tags {
+            photo.tags = tags;
+        }
+        if self.city.is_some() {
+            photo.city = self.city;
+        }
+        if self.state.is_some() {
+            photo.state = self.state;
+        }
+        if self.usage_terms.is_some() {
+            photo.usage_terms = self.usage_terms;
+        }
+        if self.rating.is_some() {
+            photo.rating = self.rating;
+        }
+    }
+}
+
+/// Path to the sidecar for image at `path`. Lightroom replaces the image
+/// extension (`001.xmp`) while other tools append to it (`001.jpg.xmp`).
+pub fn sidecar_path(path: &Path) -> Option<PathBuf> {
+    let replaced = path.with_extension(&SIDECAR_EXT[1..]);
+
+    if replaced.is_file() {
+        return Some(replaced);
+    }
+
+    let appended = PathBuf::from(format!("{}{}", path.display(), SIDECAR_EXT));
+
+    if appended.is_file() {
+        Some(appended)
+    } else {
+        None
+    }
+}
+
+/// Read the sidecar for image at `path`, if there is one
+pub fn read_sidecar(path: &Path) -> Option<Sidecar> {
+    let sidecar = sidecar_path(path)?;
+
+    let text = match fs::read_to_string(&sidecar) {
+        Ok(text) => text,
+        Err(e) => {
+            println!(
+                "   {} {} {:?}",
+                "failed to read".red(),
+                folder_name(&sidecar).red(),
+                e
+            );
+            return None;
+        }
+    };
+
+    match parse(&text) {
+        Ok(sidecar) => Some(sidecar),
+        Err(e) => {
+            println!(
+                "   {} {} {:?}",
+                "failed to parse".red(),
+                folder_name(&sidecar).red(),
+                e
+            );
+            None
+        }
+    }
+}
+
+/// Parse XMP text, combining values from all top-level `rdf:Description`
+/// elements
+pub fn parse(text: &str) -> Result<Sidecar, serde_xml_rs::Error> {
+    let meta: XmpMeta = serde_xml_rs::from_str(text)?;
+    let mut sidecar = Sidecar::default();
+
+    for d in meta.rdf.descriptions {
+        let Description {
+            title,
+            description,
+            subject,
+            city,
+            state,
+            usage_terms,
+            rating,
+        } = d;
+
+        sidecar.title = sidecar.title.or_else(|| title?.first());
+        sidecar.caption = sidecar.caption.or_else(|| description?.first());
+        sidecar.tags = sidecar.tags.or_else(|| subject.map(|s| s.bag.items));
+        sidecar.city = sidecar.city.or(city);
+        sidecar.state = sidecar.state.or(state);
+        sidecar.usage_terms =
+            sidecar.usage_terms.or_else(|| usage_terms?.first());
+        // Lightroom uses -1 for rejected and 0 for unrated
+        sidecar.rating = sidecar
+            .rating
+            .or_else(|| rating.filter(|r| *r > 0).map(|r| r as u8));
+    }
+
+    Ok(sidecar)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{parse, read_sidecar, sidecar_path, Sidecar};
+    use crate::models::Photo;
+    use std::{fs, path::Path};
+
+    #[test]
+    fn parse_fixture_test() {
+        let text = fs::read_to_string("./src/fixtures/out.xmp").unwrap();
+        let sidecar = parse(&text).unwrap();
+
+        assert_eq!(sidecar.title, Some("Time to move on".to_owned()));
+        assert!(sidecar
+            .caption
+            .unwrap()
+            .starts_with("Joel said he’d always wanted"));
+        assert_eq!(
+            sidecar.tags,
+            Some(vec![
+                "BMW R1200GS Adventure".to_owned(),
+                "Fire".to_owned(),
+                "Honda CRF 230L".to_owned(),
+                "Motorcycle".to_owned(),
+                "Mountain".to_owned(),
+                "Tent".to_owned(),
+                "Yamaha XT 250".to_owned(),
+            ])
+        );
+        assert_eq!(sidecar.city, None);
+        assert_eq!(sidecar.state, Some("Idaho".to_owned()));
+        assert_eq!(sidecar.usage_terms, Some("All Rights Reserved".to_owned()));
+        assert_eq!(sidecar.rating, None);
+    }
+
+    #[test]
+    fn parse_elements_test() {
+        let text = r#"<x:xmpmeta xmlns:x="adobe:ns:meta/">
+            <rdf:RDF xmlns:rdf="http://www.w3.org/1999/02/22-rdf-syntax-ns#">
+                <rdf:Description rdf:about=""
+                    xmlns:xmp="http://ns.adobe.com/xap/1.0/"
+                    xmlns:photoshop="http://ns.adobe.com/photoshop/1.0/"
+                    xmp:Rating="4"
+                    photoshop:City="Boise" />
+                <rdf:Description rdf:about=""
+                    xmlns:xmp="http://ns.adobe.com/xap/1.0/"
+                    xmlns:photoshop="http://ns.adobe.com/photoshop/1.0/">
+                    <photoshop:City>Ignored</photoshop:City>
+                    <photoshop:State>Idaho</photoshop:State>
+                </rdf:Description>
+            </rdf:RDF>
+        </x:xmpmeta>"#;
+
+        assert_eq!(
+            parse(text).unwrap(),
+            Sidecar {
+                city: Some("Boise".to_owned()),
+                state: Some("Idaho".to_owned()),
+                rating: Some(4),
+                ..Sidecar::default()
+            }
+        );
+    }
+
+    #[test]
+    fn apply_test() {
+        let mut photo = Photo {
+            title: Some("Embedded".to_owned()),
+            artist: Some("Jason Abbott".to_owned()),
+            tags: vec!["Old".to_owned()],
+            ..Photo::default()
+        };
+
+        Sidecar {
+            title: Some("Sidecar".to_owned()),
+            caption: Some("New caption".to_owned()),
+            rating: Some(5),
+            ..Sidecar::default()
+        }
+        .apply(&mut photo);
+
+        assert_eq!(photo.title, Some("Sidecar".to_owned()));
+        assert_eq!(photo.caption, Some("<p>New caption</p>".to_owned()));
+        assert_eq!(photo.artist, Some("Jason Abbott".to_owned()));
+        assert_eq!(photo.tags, vec!["Old".to_owned()]);
+        assert_eq!(photo.rating, Some(5));
+    }
+
+    #[test]
+    fn sidecar_path_test() {
+        let image = Path::new("./src/fixtures/out.jpg");
+
+        assert_eq!(
+            sidecar_path(image),
+            Some(Path::new("./src/fixtures/out.xmp").to_path_buf())
+        );
+        assert!(read_sidecar(image).is_some());
+        assert_eq!(
+            sidecar_path(Path::new("./src/fixtures/img_006-of-021.jpg")),
+            None
+        );
+    }
+}
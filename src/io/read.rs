@@ -1,29 +1,40 @@
 use crate::{
     config::{
-        BlogConfig, PhotoConfig, PostConfig, PostLog, SeriesConfig, CONFIG_FILE,
+        BlogConfig, GpsPrivacy, PhotoCache, PhotoConfig, PostConfig, PostLog,
+        SeriesConfig, CONFIG_FILE,
     },
-    image::exif_tool,
-    models::{Photo, Post, PostSeries},
-    tools::{folder_name, identify_outliers, path_slice},
+    gps::{self, GPX_EXT},
+    image::{resize, xmp, MetadataSource},
+    models::{Category, CategoryKind, Photo, Post, PostSeries, Track},
+    tools::{folder_name, identify_outliers, path_slice, FileStamp},
 };
 use colored::*;
 use std::{
     self,
+    collections::BTreeMap,
     fs::{self, DirEntry},
     path::{Path, PathBuf},
     process,
-    time::UNIX_EPOCH,
 };
 
 /// Create post that is not part of a series
-pub fn post(path: &Path, config: &BlogConfig) -> Option<Post> {
-    PostConfig::load(&path)
-        .and_then(|post_config| create_post(path, false, config, post_config))
+pub fn post(
+    path: &Path,
+    config: &BlogConfig,
+    metadata: &dyn MetadataSource,
+) -> Option<Post> {
+    PostConfig::load(&path).and_then(|post_config| {
+        create_post(path, false, config, post_config, metadata)
+    })
 }
 
 /// Attempt to load path entries as if they constitute a post series. `None` is
 /// returned if there are no subdirectories or they don't contain valid posts.
-pub fn series(path: &Path, config: &BlogConfig) -> Option<Vec<Post>> {
+pub fn series(
+    path: &Path,
+    config: &BlogConfig,
+    metadata: &dyn MetadataSource,
+) -> Option<Vec<Post>> {
     let sub_dirs: Vec<PathBuf> = match fs::read_dir(&path) {
         Ok(entries) => entries
             .map(|e: std::io::Result<DirEntry>| e.unwrap().path())
@@ -49,7 +60,7 @@ pub fn series(path: &Path, config: &BlogConfig) -> Option<Vec<Post>> {
             sub_dirs
                 .iter()
                 .filter_map(|p| {
-                    series_post(p.as_path(), config, &series_config)
+                    series_post(p.as_path(), config, &series_config, metadata)
                 })
                 .collect(),
         );
@@ -65,6 +76,7 @@ fn series_post(
     path: &Path,
     config: &BlogConfig,
     series_config: &SeriesConfig,
+    metadata: &dyn MetadataSource,
 ) -> Option<Post> {
     PostConfig::load(&path).and_then(|post_config| {
         let part = post_config.part;
@@ -81,7 +93,7 @@ fn series_post(
             process::exit(1);
         }
 
-        create_post(path, true, config, post_config).map(|mut p| {
+        create_post(path, true, config, post_config, metadata).map(|mut p| {
             p.series = Some(PostSeries {
                 part,
                 title: series_config.title.clone(),
@@ -97,9 +109,102 @@ fn series_post(
     })
 }
 
-/// Load information about each photo in `path`
-pub fn load_photos(path: &Path, config: &PhotoConfig) -> Vec<Photo> {
-    let mut photos: Vec<Photo> = exif_tool::parse_dir(&path, config);
+/// Load information about each photo in `path`. Photos unchanged since they
+/// were cached are loaded from the cache and the rest from the metadata source.
+/// Values in XMP sidecars take precedence over embedded metadata. Photos
+/// without a location are then geotagged from the post `tracks` and those
+/// without a place name are named from configured place data.
+///
+/// Locations within the privacy zone are left out of the cache, which is
+/// written to the post folder, and removed from photos after sidecars and
+/// geotagging are applied. The cache isn't updated for a dry run.
+///
+/// - `files` Stamps of post files, from `file_stamps()`
+/// - `tracks` Unsimplified post tracks, from `load_tracks()`
+pub fn load_photos(
+    path: &Path,
+    blog_config: &BlogConfig,
+    metadata: &dyn MetadataSource,
+    files: &BTreeMap<String, FileStamp>,
+    tracks: &[Track],
+) -> Vec<Photo> {
+    let config = &blog_config.photo;
+    let privacy = blog_config.track.privacy.as_ref();
+    let mut cache = PhotoCache::load(path, privacy);
+    let mut photos: Vec<Photo> = Vec::new();
+    let mut changed: Vec<String> = Vec::new();
+    let mut hidden: Vec<String> = Vec::new();
+
+    // drop files no longer present
+    cache.retain(files);
+
+    for (name, stamp) in files
+        .iter()
+        .filter(|(name, _)| name.ends_with(&config.source_ext))
+    {
+        match cache.get(name, stamp, config) {
+            Some(photo) => {
+                if cache.is_hidden(name) {
+                    hidden.push(name.clone());
+                }
+                photos.push(photo);
+            }
+            _ => changed.push(name.clone()),
+        }
+    }
+
+    if !changed.is_empty() {
+        for photo in metadata.parse_files(path, &changed, config) {
+            if let Some(stamp) = files.get(&photo.file.name) {
+                cache.insert(&photo, stamp.clone(), privacy);
+            }
+            photos.push(photo);
+        }
+        println!("   Read metadata from {} photo(s)", changed.len());
+    }
+
+    if !blog_config.dry_run {
+        cache.write(path);
+    }
+
+    for photo in photos.iter_mut() {
+        if let Some(sidecar) = xmp::read_sidecar(&path.join(&photo.file.name)) {
+            sidecar.apply(photo);
+        }
+    }
+
+    let inferred = gps::geotag(&mut photos, tracks, &blog_config.track);
+
+    if inferred > 0 {
+        println!(
+            "   Inferred location of {} photo(s) from GPS track",
+            inferred
+        );
+    }
+
+    if let Some(zone) = privacy {
+        // after sidecars and geotagging, which may add locations
+        hidden.extend(gps::hide_photos(&mut photos, zone));
+        hidden.sort();
+        hidden.dedup();
+
+        if zone.verify && !hidden.is_empty() {
+            println!(
+                "   Removed location of {} photo(s) in privacy zone: {}",
+                hidden.len(),
+                hidden.join(", ").yellow()
+            );
+        }
+    }
+
+    if let Some(places) = &blog_config.places.gazetteer {
+        let named = places
+            .name_photos(&mut photos, blog_config.places.max_distance_miles);
+
+        if named > 0 {
+            println!("   Named the place of {} photo(s)", named);
+        }
+    }
 
     if photos.is_empty() {
         println!("   {}", "found no photos".red());
@@ -121,12 +226,18 @@ fn create_post(
     is_series: bool,
     config: &BlogConfig,
     post_config: PostConfig,
+    metadata: &dyn MetadataSource,
 ) -> Option<Post> {
     // path to series post includes parent
     let post_path = path_slice(path, if is_series { 2 } else { 1 });
-    let log = load_post_log(path, config);
+    let (mut log, files) = load_post_log(path, &config.photo);
+    let privacy = config.track.privacy.as_ref();
 
     if !(log.files_changed || config.force.html) {
+        if let Some(zone) = privacy {
+            hide_logged(&mut log, zone);
+        }
+
         // no files have changed and re-render NOT forced
         assert_index(
             post_config.cover_photo_index,
@@ -134,16 +245,24 @@ fn create_post(
             &post_config.title,
         );
 
-        Some(Post {
+        let mut post = Post {
             path: post_path,
             happened_on: log.happened_on,
             photo_count: log.photo_count,
             photo_locations: log.photo_locations.clone(),
+            photo_points: log.photo_points.clone(),
+            has_track: log.has_track,
+            track_stats: log.track_stats.clone(),
             tags: log.tags.clone(),
+            files,
             ..Post::from_config(post_config, log)
-        })
+        };
+        suggest_where(&mut post, config, false);
+
+        Some(post)
     } else {
-        let photos = load_photos(path, &config.photo);
+        let tracks = load_tracks(path, config);
+        let photos = load_photos(path, config, metadata, &files, &tracks);
 
         if photos.is_empty() {
             None
@@ -155,15 +274,112 @@ fn create_post(
             );
 
             let mut post = Post::from_config(post_config, log);
+            // stats use every cleaned point since simplifying shortens
+            // distances and flattens elevation
+            let cleaned = gps::clean(tracks, &config.track);
 
             post.path = post_path;
+            post.files = files;
+            post.track_stats = gps::track_stats(&cleaned);
+            post.tracks = gps::simplify(cleaned, &config.track);
+            post.has_track = !post.tracks.is_empty();
             post.add_photos(photos);
+            suggest_where(&mut post, config, true);
+
+            if post.has_track {
+                println!(
+                    "   Read {} GPS track(s) with {} points",
+                    post.tracks.len(),
+                    post.tracks.iter().map(|t| t.point_count()).sum::<usize>()
+                );
+            }
 
             Some(post)
         }
     }
 }
 
+/// Load GPX tracks in `path` without points in the privacy zone. Tracks are
+/// left unsimplified so they can be used to geotag photos.
+pub fn load_tracks(path: &Path, config: &BlogConfig) -> Vec<Track> {
+    let tracks = gps::load_tracks(path);
+
+    match &config.track.privacy {
+        Some(zone) => {
+            let (visible, removed) = gps::hide_tracks(tracks, zone);
+
+            if zone.verify && removed > 0 {
+                println!(
+                    "   Removed {} track point(s) in privacy zone",
+                    removed.to_string().yellow()
+                );
+            }
+            visible
+        }
+        _ => tracks,
+    }
+}
+
+/// Find "where" categories for a post that has none from the regions its
+/// photos were taken in. These are assigned to the post if configured,
+/// otherwise they're only reported if `report` is true.
+fn suggest_where(post: &mut Post, config: &BlogConfig, report: bool) {
+    let places = match &config.places.gazetteer {
+        Some(places) => places,
+        _ => return,
+    };
+
+    if post
+        .categories
+        .iter()
+        .any(|c| c.kind == CategoryKind::Where)
+    {
+        return;
+    }
+
+    let regions =
+        places.regions(&post.photo_locations, config.places.max_distance_miles);
+
+    if regions.is_empty() {
+        return;
+    }
+
+    if config.places.assign_where {
+        if report {
+            println!("   Assigned \"where\" categories {}", regions.join(", "));
+        }
+        for name in regions.iter() {
+            post.categories
+                .push(Category::new(name, CategoryKind::Where));
+        }
+    } else if report {
+        println!(
+            "   Suggested \"where\" categories {}",
+            regions.join(", ").yellow()
+        );
+    }
+}
+
+/// Remove locations within the privacy zone from a log written before the
+/// zone was configured or changed
+fn hide_logged(log: &mut PostLog, zone: &GpsPrivacy) {
+    let removed = gps::hide_locations(&mut log.photo_locations, zone);
+
+    log.photo_points
+        .retain(|(_, lon, lat)| !zone.contains((*lat as f64, *lon as f64)));
+
+    if let Some(cover) = log.cover_photo.as_mut() {
+        gps::hide_photos(std::slice::from_mut(cover), zone);
+    }
+
+    if zone.verify && removed > 0 {
+        println!(
+            "   Removed {} logged photo location(s) in privacy zone",
+            removed.to_string().yellow()
+        );
+    }
+}
+
 /// - `index` One-based
 fn assert_index(index: usize, length: usize, label: &str) {
     if index > length {
@@ -176,80 +392,117 @@ fn assert_index(index: usize, length: usize, label: &str) {
     }
 }
 
-/// Load post log. If there is no file then return a log with
-/// `files_have_changed = true`.
-fn load_post_log(path: &Path, config: &BlogConfig) -> PostLog {
-    PostLog::load(path).map_or(PostLog::empty(), |mut log| {
-        match is_modified(
-            path,
-            log.as_of,
-            log.photo_count + 1, // photos plus configuration file
-            config,
-        ) {
-            Ok(modified) => {
-                log.files_changed = modified;
-                log
-            }
-            Err(e) => {
-                println!(
-                    "   Failed to check {} for change {:?}",
-                    folder_name(path),
-                    e
-                );
-                PostLog::empty()
-            }
+/// Load post log and the current stamps of post files, then compare the two.
+/// If there is no log file then return a log with `files_changed = true`. Logs
+/// written before stamps were recorded also count as changed so the post is
+/// re-read and its stamps logged, though its photos aren't resized. Logs
+/// without photo points for the site map are re-read for the same reason.
+fn load_post_log(
+    path: &Path,
+    config: &PhotoConfig,
+) -> (PostLog, BTreeMap<String, FileStamp>) {
+    match PostLog::load(path) {
+        Some(mut log) => {
+            let files = file_stamps(path, config, &log.files);
+
+            log.files_changed = log.files != files || log.unmapped();
+            (log, files)
         }
-    })
+        _ => (
+            PostLog::empty(),
+            file_stamps(path, config, &BTreeMap::new()),
+        ),
+    }
 }
 
-/// Whether `path` contains any pertinent files modified after `threshold`
-/// timestamp.
-///
-/// If `true` then photos will be loaded and a subsequent check will determine
-/// whether particular photos have been modified since the `threshold`.
-fn is_modified(
+/// Size and digest of each source photo, XMP sidecar, GPX and configuration file in
+/// `path`, keyed by file name. Files whose size and modification time match
+/// their `known` stamp aren't hashed again. Resized photos are skipped in case
+/// they share the source extension. Files that can't be read are reported and
+/// skipped.
+pub fn file_stamps(
     path: &Path,
-    threshold: i64,
-    file_count: usize,
-    config: &BlogConfig,
-) -> std::io::Result<bool> {
-    if !path.is_dir() {
-        return Ok(true);
-    }
-    let mut count: usize = 0;
+    config: &PhotoConfig,
+    known: &BTreeMap<String, FileStamp>,
+) -> BTreeMap<String, FileStamp> {
+    let mut stamps = BTreeMap::new();
+
+    let entries = match fs::read_dir(path) {
+        Ok(entries) => entries,
+        _ => {
+            println!(
+                "   {} {}",
+                "Failed to read files in".red(),
+                folder_name(path).magenta()
+            );
+            return stamps;
+        }
+    };
 
     let allow_name = |name: &str| {
-        name.ends_with(&config.photo.source_ext) || name == CONFIG_FILE
+        (name.ends_with(&config.source_ext) && !resize::is_resized(name))
+            || name.ends_with(xmp::SIDECAR_EXT)
+            || name.ends_with(GPX_EXT)
+            || name == CONFIG_FILE
     };
 
-    for entry in fs::read_dir(path)? {
-        let entry: DirEntry = entry?;
-        let os_name = entry.file_name();
-        let name = os_name.to_str();
+    for entry in entries.filter_map(|e| e.ok()) {
+        let name = entry.file_name().to_string_lossy().to_string();
 
-        if name.is_none() || !allow_name(name.unwrap()) {
+        if !allow_name(&name) {
             continue;
         }
-        count += 1;
 
-        if count > file_count {
-            // more than expected files
-            return Ok(true);
+        match FileStamp::read_known(&entry.path(), known.get(&name)) {
+            Ok(stamp) => {
+                stamps.insert(name, stamp);
+            }
+            Err(e) => {
+                println!("   {} {} {:?}", "failed to read".red(), name.red(), e)
+            }
         }
+    }
 
-        let modified: i64 = entry
-            .metadata()?
-            .modified()?
-            .duration_since(UNIX_EPOCH)
-            .unwrap()
-            .as_secs() as i64;
+    stamps
+}
 
-        if threshold < modified {
-            // file modified more recently than threshold
-            return Ok(true);
-        }
+#[cfg(test)]
+mod tests {
+    use super::file_stamps;
+    use crate::config::PhotoConfig;
+    use std::{collections::BTreeMap, env, fs, path::Path};
+
+    #[test]
+    fn file_stamps_test() {
+        let stamps = file_stamps(
+            Path::new("./src/fixtures"),
+            &PhotoConfig::fixture(),
+            &BTreeMap::new(),
+        );
+
+        assert!(stamps.contains_key("img_006-of-021.jpg"));
+        assert!(stamps.contains_key("img_003-of-016.jpg"));
+        assert!(stamps.contains_key("out.xmp"));
+        assert!(stamps.contains_key("track.gpx"));
+        assert!(!stamps.contains_key("out.json"));
+        assert_ne!(stamps["img_006-of-021.jpg"], stamps["img_003-of-016.jpg"]);
     }
 
-    // consider path to be modified if it has a different file count
-    Ok(file_count != count)
+    #[test]
+    fn file_stamps_skip_resized_test() {
+        let folder = env::temp_dir().join("exifweb-stamps-test");
+        let _ = fs::remove_dir_all(&folder);
+        fs::create_dir_all(&folder).unwrap();
+
+        for name in ["img_001-of-002.jpg", "001_l.jpg", "001_t.jpg"].iter() {
+            fs::write(folder.join(name), name).unwrap();
+        }
+        let stamps =
+            file_stamps(&folder, &PhotoConfig::fixture(), &BTreeMap::new());
+
+        assert_eq!(stamps.len(), 1);
+        assert!(stamps.contains_key("img_001-of-002.jpg"));
+
+        let _ = fs::remove_dir_all(&folder);
+    }
 }
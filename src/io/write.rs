@@ -2,22 +2,34 @@
 
 use crate::{
     config::{BlogConfig, CategoryIcon, FacebookConfig, FeaturedPost, PostLog},
+    gps::{self, DOWNLOAD_FOLDER, DOWNLOAD_GPX, DOWNLOAD_KML, TRACK_FILE},
     html,
-    image::MapBox,
-    models::{Blog, Category, CategoryKind, PhotoPath, Post},
+    image::{self, static_map_provider},
+    json_ld,
+    models::{geo_json, Blog, Category, CategoryKind, PhotoPath, Post},
     tools::{config_regex, path_slice, rot13, write_result},
 };
 use chrono::{DateTime, FixedOffset};
 use hashbrown::HashMap;
 use regex::Regex;
-use std::{collections::BTreeMap, fmt::Display, fs, path::Path};
+use std::{
+    collections::BTreeMap, convert::Infallible, fmt::Display, fs, path::Path,
+};
 use yarte::Template;
 
-// TODO: render map page
 // TODO: render post redirects
 
 const ALPHABET: &str = "ABCDEFGHIJKLMNOPQRSTUVWXYZ";
 
+/// Post sub-folder for the full-screen map page
+const MAP_FOLDER: &str = "map";
+
+/// GeoJSON file of post photo locations for the map photo layer
+const PHOTO_FILE: &str = "photos.json";
+
+/// GeoJSON file of every photo location written to the root folder
+const BLOG_PHOTO_FILE: &str = "geo.json";
+
 /// Render template and write content to `path` file
 fn write_page(path: &Path, template: impl Template) {
     write_result(path, || template.call(), true);
@@ -66,6 +78,7 @@ impl<'a> Writer<'a> {
                 content_width: config.style.content_width,
                 map_image_height: config.style.inline_map_height,
                 thumb_size: config.photo.size.display.thumb,
+                allow_download: config.track.allow_download,
                 contact_link: config
                     .owner
                     .email
@@ -101,23 +114,170 @@ impl<'a> Writer<'a> {
                 || self.config.force.html
             {
                 self.post(&p);
+                self.post_track(p);
+                self.post_map(p);
+                self.post_downloads(p);
                 // GOAL: spawn thread to write log
                 PostLog::write(self.root, &p);
             }
         }
     }
 
-    /// Download and save map images for each post
+    /// Save map images for each post with changed locations, from the cache
+    /// where possible, then remove cached images no post uses
     pub fn post_maps(&self) {
+        let provider = static_map_provider(&self.config.mapbox);
+
         for p in self.context.blog.posts.values() {
             if p.locations_changed()
                 || p.cover_photo_aspect_ratio_changed()
                 || self.config.force.maps
             {
-                println!("Downloading {} map images", p.title);
-                MapBox::save_static(&p, self.root, self.config);
+                println!("Saving {} map images", p.title);
+                image::save_static(
+                    p,
+                    self.root,
+                    self.config,
+                    provider.as_ref(),
+                );
             }
         }
+        image::prune_static(
+            self.context.blog.posts.values(),
+            self.root,
+            self.config,
+            provider.as_ref(),
+        );
+    }
+
+    /// Write GeoJSON for post tracks or, if the post no longer has a track,
+    /// remove any previously written
+    fn post_track(&self, post: &Post) {
+        let path = self.root.join(&post.path).join(TRACK_FILE);
+
+        if !post.tracks.is_empty() {
+            write_result(
+                &path,
+                || serde_json::to_string(&geo_json(&post.tracks)),
+                false,
+            );
+        } else if !post.has_track && path.exists() {
+            fs::remove_file(&path).unwrap_or(());
+        }
+    }
+
+    /// Write GPX and KML files of the cleaned post track if downloads are
+    /// allowed or, if they aren't or the post no longer has a track, remove
+    /// any previously written
+    fn post_downloads(&self, post: &Post) {
+        let folder = self.root.join(&post.path).join(DOWNLOAD_FOLDER);
+
+        if self.config.track.allow_download && !post.tracks.is_empty() {
+            let url = json_ld::full_url(self.config, &post.path);
+
+            // ignore error here since it will be caught when writing
+            fs::create_dir_all(&folder).unwrap_or(());
+            write_result(
+                &folder.join(DOWNLOAD_GPX),
+                || Ok::<_, Infallible>(gps::to_gpx(post, &url)),
+                false,
+            );
+            write_result(
+                &folder.join(DOWNLOAD_KML),
+                || Ok::<_, Infallible>(gps::to_kml(post, &url)),
+                false,
+            );
+        } else if (!self.config.track.allow_download || !post.has_track)
+            && folder.exists()
+        {
+            fs::remove_dir_all(&folder).unwrap_or(());
+        }
+    }
+
+    /// Render full-screen map page for posts with photo locations or a track,
+    /// write GeoJSON of photo locations and render a page for each located
+    /// photo that opens with that photo selected
+    fn post_map(&self, post: &Post) {
+        if post.photo_locations.is_empty() && !post.has_track {
+            return;
+        }
+        let map_path = format!("{}/{}", post.path, MAP_FOLDER);
+
+        write_result(
+            &self.root.join(&post.path).join(PHOTO_FILE),
+            || serde_json::to_string(&post.photo_geo_json()),
+            false,
+        );
+
+        self.default_page(&map_path, self.map_context(Some(post), None));
+
+        for p in post.photos.iter().filter(|p| p.location.is_some()) {
+            self.default_page(
+                &format!("{}/{}", map_path, p.index),
+                self.map_context(Some(post), Some(p.index)),
+            );
+        }
+    }
+
+    /// Write GeoJSON of every photo location and render the site-wide map
+    /// page that displays them
+    pub fn blog_map(&self) {
+        write_result(
+            &self.root.join(BLOG_PHOTO_FILE),
+            || {
+                serde_json::to_string(
+                    &self
+                        .context
+                        .blog
+                        .photo_geo_json(&self.config.photo.output_ext),
+                )
+            },
+            false,
+        );
+
+        self.default_page(MAP_FOLDER, self.map_context(None, None));
+    }
+
+    /// Map page context with post details, if any, for the map script
+    fn map_context(
+        &'a self,
+        post: Option<&'a Post>,
+        photo: Option<u8>,
+    ) -> MapContext<'a> {
+        let (title, post_json) = match post {
+            Some(p) => {
+                let bounds = p.bounds().map(|(sw, ne)| {
+                    serde_json::json!({ "sw": [sw.0, sw.1], "ne": [ne.0, ne.1] })
+                });
+
+                (
+                    format!("{} Map", p.title),
+                    serde_json::json!({
+                        "key": p.path,
+                        "title": p.title,
+                        "bounds": bounds,
+                        "photo": photo
+                    })
+                    .to_string()
+                    // keep a title like "</script>" from closing the tag
+                    .replace('<', "\\u003c"),
+                )
+            }
+            _ => (
+                format!("{} Map", self.context.site_title),
+                "null".to_owned(),
+            ),
+        };
+
+        MapContext {
+            ctx: &self.context,
+            post,
+            title,
+            map_style: &self.config.mapbox.style.dynamic,
+            access_token: &self.config.mapbox.access_token,
+            allow_download: self.config.track.allow_download,
+            post_json,
+        }
     }
 
     fn post(&self, post: &Post) {
@@ -136,6 +296,11 @@ impl<'a> Writer<'a> {
                 enable: Enable::all(),
                 ctx: &self.context,
                 json_ld: Some(post.json_ld(&self.config).to_string()),
+                elevation: gps::elevation_svg(
+                    &post.tracks,
+                    &post.photos,
+                    self.config.style.content_width,
+                ),
                 title,
                 sub_title,
             },
@@ -345,6 +510,8 @@ struct CommonContext<'a> {
     pub content_width: u16,
     pub map_image_height: u16,
     pub thumb_size: u16,
+    /// Whether GPX and KML track downloads are written
+    pub allow_download: bool,
     pub contact_link: String,
 
     mode_icons: HashMap<String, Regex>,
@@ -360,6 +527,13 @@ impl<'a> CommonContext<'a> {
     pub fn date(&self, d: DateTime<FixedOffset>) -> String {
         html::date_string(d)
     }
+    pub fn time(
+        &self,
+        d: DateTime<FixedOffset>,
+        at: Option<DateTime<FixedOffset>>,
+    ) -> String {
+        html::time_string(d, at)
+    }
     pub fn travel_icon(&self, categories: &[Category]) -> String {
         match html::travel_mode_icon(categories, &self.mode_icons) {
             Some(icon) => icon,
@@ -382,7 +556,7 @@ impl<'a> CommonContext<'a> {
     //     html::list_label(word, list.iter())
     // }
     pub fn photo_id(&self, index: impl Display) -> String {
-        format!("{:03}", index)
+        html::photo_id(index)
     }
 }
 
@@ -426,10 +600,28 @@ struct PostContext<'c> {
     pub post: &'c Post,
     pub enable: Enable,
     pub json_ld: Option<String>,
+    /// Inline SVG elevation profile for posts with tracks
+    pub elevation: Option<String>,
     pub title: String,
     pub sub_title: String,
 }
 
+#[derive(Template)]
+#[template(path = "map.hbs")]
+struct MapContext<'c> {
+    pub ctx: &'c CommonContext<'c>,
+    /// Post to map or `None` for the site-wide map
+    pub post: Option<&'c Post>,
+    pub title: String,
+    /// Mapbox style identifier for the interactive map
+    pub map_style: &'c str,
+    pub access_token: &'c str,
+    pub allow_download: bool,
+    /// JSON object with post key, title, photo bounds and selected photo or
+    /// `null` for the site-wide map
+    pub post_json: String,
+}
+
 #[derive(Template)]
 #[template(path = "photo_tag.hbs")]
 struct PhotoTagContext<'c> {
@@ -6,9 +6,12 @@
 /// <reference path="./browser.d.ts"/>
 /// <reference path="./util.ts"/>
 
-/** Mapbox style identifier defined in /views/mapbox.hbs */
+/** Mapbox style identifier defined in /templates/map.hbs */
 declare const mapStyle: string
-/** Post key if displaying map for post otherwise undefined */
+/**
+ * Post key, photo bounds and optionally the index of a photo to select if
+ * displaying map for post otherwise undefined
+ */
 declare const post: MapPost
 /** Whether GPX downloads are allowed */
 declare const allowDownload: boolean
@@ -122,7 +125,7 @@ $(function() {
                   .attr('title', tip)
                   .attr('alt', tip)
                   .click(() => {
-                     showPhotoInPost(img.url!)
+                     showPhotoInPost(img)
                   })
             )
             .append(
@@ -423,10 +426,11 @@ $(function() {
    }
 
    map.addControl(nav, 'top-right').on('load', () => {
-      $.getJSON('/geo.json', data => {
+      // post maps only show photos from that post
+      $.getJSON(post ? slug + '/photos.json' : '/geo.json', data => {
          geoJSON = data
          if (geoJSON === null) {
-            console.error('Unable to retrieve blog GeoJSON')
+            console.error('Unable to retrieve photo GeoJSON')
             return
          }
          $count.find('div').html(geoJSON.features.length.toString())
@@ -438,13 +442,16 @@ $(function() {
 
          // can't add post layers until base layers are ready
          if (post) {
-            // Expand bounds so pictures aren't right at the edge. This should
-            // probably do something smarter like a percent of bounding box.
-            post.bounds.sw[0] -= 0.01
-            post.bounds.sw[1] -= 0.01
-            post.bounds.ne[0] += 0.01
-            post.bounds.ne[1] += 0.01
-            $.getJSON('/' + post.key + '/geo.json', addPostLayers)
+            if (post.bounds) {
+               // Expand bounds so pictures aren't right at the edge. This
+               // should probably do something smarter like a percent of
+               // bounding box.
+               post.bounds.sw[0] -= 0.01
+               post.bounds.sw[1] -= 0.01
+               post.bounds.ne[0] += 0.01
+               post.bounds.ne[1] += 0.01
+            }
+            $.getJSON('/' + post.key + '/track.json', addPostLayers)
          } else {
             showPositionInUrl = true
          }
@@ -643,14 +650,56 @@ $(function() {
    }
 
    /**
-    * Retrieve photo ID from preview URL and redirect to post with that photo.
-    *
-    * Example https://farm3.staticflickr.com/2853/33767184811_9eff6deb48_n.jpg
+    * Redirect to post with the photo anchor, such as `/post-path#007`.
+    */
+   function showPhotoInPost(img: MapPhoto) {
+      const id = ('00' + img.index).slice(-3)
+      window.location.href = '/' + img.post + '#' + id
+   }
+
+   /**
+    * Center the map on the photo with the given index and show its preview,
+    * as when the map is opened from a post photo.
+    */
+   function selectPhoto(index: number) {
+      const feature =
+         geoJSON === null
+            ? undefined
+            : geoJSON.features.find(
+                 f => (f.properties as MapPhoto).index == index
+              )
+      const $photo = feature === undefined ? null : html.photo(feature)
+
+      if (feature === undefined || $photo === null) {
+         return false
+      }
+      const center = feature.geometry.coordinates as [number, number]
+
+      map.jumpTo({ center, zoom: 15 })
+      html.photoPreview(
+         {
+            point: map.project(center),
+            lngLat: mapboxgl.LngLat.convert(center)
+         } as mapboxgl.MapMouseEvent,
+         'single',
+         $photo
+      )
+      return true
+   }
+
+   /**
+    * Bounds of track line coordinates for posts without photo locations.
     */
-   function showPhotoInPost(url: string) {
-      const path = url.split('/')
-      const parts = path[path.length - 1].split('_')
-      window.location.href = '/' + parts[0]
+   function trackBounds(
+      track: GeoJSON.FeatureCollection<GeoJSON.LineString>
+   ): mapboxgl.LngLatBounds {
+      const bounds = new mapboxgl.LngLatBounds()
+      track.features.forEach(f => {
+         f.geometry.coordinates.forEach(c => {
+            bounds.extend(c as [number, number])
+         })
+      })
+      return bounds
    }
 
    /**
@@ -693,8 +742,16 @@ $(function() {
          }, 500)
       })
 
+      if (post.photo !== null && selectPhoto(post.photo)) {
+         return
+      }
+
       // https://www.mapbox.com/mapbox-gl-js/api/#map#fitbounds
-      map.fitBounds([post.bounds.sw, post.bounds.ne])
+      if (post.bounds) {
+         map.fitBounds([post.bounds.sw, post.bounds.ne])
+      } else if (track.features.length > 0) {
+         map.fitBounds(trackBounds(track))
+      }
    }
 
    /**
@@ -14,6 +14,16 @@ const enum MediumImage {
    URL = 'src'
 }
 
+/**
+ * Data attribute on `<source>` elements in a photo `<picture>`, listing sizes
+ * in another format to load in place of the small placeholder
+ * @example
+ * `<source type="image/avif" srcset="/small.avif" data-srcset="/medium.avif 512w, /large.avif 1024w"/>`
+ */
+const enum SourceSet {
+   URL = 'srcset'
+}
+
 interface LazyLoadOptions {
    rootMargin?: string
    root?: Element | null
@@ -30,7 +40,7 @@ window.addEventListener('DOMContentLoaded', () => {
    const htmlTag = document.getElementsByTagName('html')[0]! as HTMLHtmlElement
    /** Post photos */
    const photos = Array.from(
-      document.querySelectorAll('figure > img')! as NodeListOf<HTMLImageElement>
+      document.querySelectorAll('figure img')! as NodeListOf<HTMLImageElement>
    )
    /** Light box container */
    const lb = document.getElementById('light-box')! as HTMLDivElement
@@ -363,10 +373,19 @@ window.addEventListener('DOMContentLoaded', () => {
       }
 
       /**
-       * Load larger image per `window.devicePixelRatio`
+       * Load larger image per `window.devicePixelRatio` or, for other formats
+       * in a `<picture>`, let the browser choose from the source set
        */
       private loadImage(el: HTMLImageElement) {
          el.src = el.dataset[this.largeImageKey]!
+
+         const picture = el.parentElement
+
+         if (picture && picture.tagName == 'PICTURE') {
+            picture
+               .querySelectorAll('source')
+               .forEach(s => { s.srcset = s.dataset[SourceSet.URL]! })
+         }
       }
    }
 
@@ -1,6 +1,6 @@
 //! JSON-LD helper methods
 
-use crate::config::BlogConfig;
+use crate::{config::BlogConfig, models::TrackStats};
 use serde_json::{json, Value};
 
 pub static CONTEXT: &str = "http://schema.org";
@@ -46,6 +46,25 @@ pub fn image(width: u16, height: u16, url: String) -> Value {
     })
 }
 
+/// Trip recorded by GPS tracks, described as the post subject
+///
+/// http://schema.org/ExerciseAction
+pub fn trip(stats: &TrackStats) -> Value {
+    let mut description = format!("{} feet of elevation gain", stats.gain());
+
+    if let Some(time) = stats.moving_time() {
+        description.push_str(&format!(" over {} of moving time", time));
+    }
+
+    json!({
+        "@type": "ExerciseAction",
+        "distance": format!("{} mi", stats.miles()),
+        "startTime": stats.start.map(|d| d.to_rfc3339()),
+        "endTime": stats.end.map(|d| d.to_rfc3339()),
+        "description": description
+    })
+}
+
 /// http://schema.org/WebPage
 pub fn web_page(config: &BlogConfig, path: &str) -> Value {
     json!({
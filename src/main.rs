@@ -3,134 +3,238 @@
 extern crate enum_primitive_derive;
 extern crate num_traits;
 
+mod cli;
 mod config;
 mod deserialize;
+mod gps;
 mod html;
 mod image;
 mod io;
 mod json_ld;
+mod maintain;
 mod minify;
 mod models;
+mod plan;
+mod serve;
 mod tools;
+mod watch;
 
+use cli::{Command, Options, Override};
 use colored::*;
-use config::{BlogConfig, BlogLog, FeaturedPost};
-use image::cwebp;
+use config::{BlogConfig, BlogLog, FeaturedPost, CONFIG_FILE};
+use image::{resize, MetadataSource};
 use io::{read, Writer};
 use models::{Blog, Photo};
+use plan::Plan;
+use serve::Server;
 use std::{
-    self, env, fs,
+    self, fs,
     path::{Path, PathBuf},
     process,
 };
+use structopt::StructOpt;
 use tools::folder_name;
+use watch::Watcher;
 
-// TODO: read and process GPX files
-
-static RUN_HELP: &str = "
-    -h      Show help
-
-    -d      Set working directory (current directory assumed if not given)
-            Example: exifweb -d=./some/path
+fn main() {
+    let options = Options::from_args();
+    let root = options.dir.as_path();
+
+    match options.command {
+        Command::Render {
+            auto: true, force, ..
+        } => watch(root, force.overrides(), None),
+        Command::Render {
+            dry_run: true,
+            force,
+            ..
+        } => plan(root, force.overrides()),
+        Command::Render { force, .. } => render(root, force.overrides()),
+        Command::Init => initialize(root),
+        Command::Check => maintain::check(root),
+        Command::Clean { photos } => maintain::clean(root, photos),
+        Command::Serve { port, force } => serve(root, port, force.overrides()),
+        Command::Stats => maintain::stats(root),
+    }
+}
 
-    -auto   Detect changes and render differences
-    -init   Create post configuration
-    -force  Comma-delimited list of posts, maps, photos or tags
+fn initialize(root: &Path) {
+    let path = root.join(CONFIG_FILE);
 
-          =posts    Re-render all post HTML and basic root pages
-          =maps     Re-download map images for post and category pages
-          =photos   Recreate all resized photos
-          =tags     Re-render all photo tag pages
+    match fs::write(&path, config::post::EMPTY_CONFIG) {
+        Ok(_) => {
+            println!("Created {}", path.display());
 
-            Example: exifweb -force=posts,tags
+            process::Command::new("notepad")
+                .current_dir(root)
+                .arg(CONFIG_FILE);
+        }
+        Err(e) => {
+            println!("Error writing {} {:?}", path.display(), e);
+            process::exit(1)
+        }
+    };
+}
 
-";
+fn render(root: &Path, overrides: Override) {
+    let mut config = load_config(root, overrides);
+    let metadata = image::metadata_source(&config.photo);
+    let mut blog = load_blog(root, &config, metadata.as_ref());
 
-/// Override normal rendering behavior which is to only reprocess when changes
-/// are detected
-#[derive(Default)]
-struct Override {
-    posts: bool,
-    maps: bool,
-    photos: bool,
-    tags: bool,
+    render_blog(root, &mut config, &mut blog, metadata.as_ref());
 }
 
-fn main() {
-    // Default path is current directory
-    let mut path: String = String::from(".");
-    let mut overrides: Override = Override::default();
-    let mut rendering: bool = true;
-    let mut args = env::args();
-
-    while let Some(a) = args.next() {
-        match a.as_ref() {
-            "-h" => {
-                // show help regardless of other arguments
-                print!("{}", RUN_HELP);
-                process::exit(0);
-            }
-            "-init" => {
-                rendering = false;
-            }
-            _ => {
-                if a.starts_with("-d=") {
-                    path = args.next().expect("Invalid directory parameter");
-                } else if a.starts_with("-force=") {
-                    let list = args.next().expect("Invalid force parameter");
-
-                    for f in list.split(',') {
-                        match f {
-                            "posts" => overrides.posts = true,
-                            "maps" => overrides.maps = true,
-                            "photos" => overrides.photos = true,
-                            "tags" => overrides.tags = true,
-                            _ => (),
-                        }
-                    }
-                }
-            }
+/// Serve rendered files while watching for changes
+fn serve(root: &Path, port: u16, overrides: Override) {
+    match Server::start(root, port) {
+        Ok(server) => {
+            println!(
+                "{} {}",
+                "Serving at".bold(),
+                format!("http://localhost:{}", port).cyan().bold()
+            );
+            watch(root, overrides, Some(&server));
+        }
+        Err(e) => {
+            println!("{} {:?}", "Failed to start server".red(), e);
+            process::exit(1)
         }
-    }
-
-    if rendering {
-        render(&path, overrides);
-    } else {
-        initialize(&path);
     }
 }
 
-fn initialize(path: &str) {
-    let name = format!("{}/{}", path, config::CONFIG_FILE);
+/// Render then keep the blog in memory and re-render whatever is affected by
+/// each batch of file changes until the process is stopped. Pages open in
+/// browsers are reloaded after each render if `server` is given.
+fn watch(root: &Path, overrides: Override, server: Option<&Server>) {
+    let mut config = load_config(root, overrides);
+    let mut metadata = image::metadata_source(&config.photo);
+    let mut blog = load_blog(root, &config, metadata.as_ref());
 
-    match fs::write(
-        Path::new(path).join(config::CONFIG_FILE),
-        config::post::EMPTY_CONFIG,
-    ) {
-        Ok(_) => {
-            println!("Created {}", name);
+    render_blog(root, &mut config, &mut blog, metadata.as_ref());
 
-            process::Command::new("notepad")
-                .current_dir(path)
-                .arg(config::CONFIG_FILE);
+    let mut watcher = Watcher::new(root, &config);
+
+    loop {
+        // later renders only consider later changes
+        blog.update_history();
+        config.force = Default::default();
+
+        println!("\n{}", "Watching for changes (Ctrl+C to stop)".bold());
+
+        let changes = watcher.wait();
+
+        if changes.config {
+            // root configuration may affect every page
+            println!("\n{}", "Root configuration changed".bold());
+            config = load_config(
+                root,
+                Override {
+                    posts: true,
+                    ..Override::default()
+                },
+            );
+            metadata = image::metadata_source(&config.photo);
+            blog = load_blog(root, &config, metadata.as_ref());
+            watcher = Watcher::new(root, &config);
+        } else {
+            for name in changes.folders.iter() {
+                blog.remove_posts(name);
+                post_from_entry(
+                    &mut blog,
+                    &root.join(name),
+                    &config,
+                    metadata.as_ref(),
+                );
+            }
         }
-        Err(e) => println!("Error writing {} {:?}", name, e),
-    };
+
+        if changes.config || !changes.folders.is_empty() {
+            render_blog(root, &mut config, &mut blog, metadata.as_ref());
+        }
+
+        if let Some(server) = server {
+            // asset files are served as they are so also need a reload
+            server.reload();
+        }
+    }
 }
 
-fn render(path: &str, overrides: Override) {
-    let root = Path::new(path);
-    let entries = load_root_directory(&root);
-    let mut config = load_config(&root, overrides);
+/// Load posts from every directory within root
+fn load_blog(
+    root: &Path,
+    config: &BlogConfig,
+    metadata: &dyn MetadataSource,
+) -> Blog {
+    let entries = load_root_directory(root);
     let mut blog = Blog::default();
 
-    blog.history = BlogLog::load(&root).unwrap_or_else(BlogLog::empty);
+    blog.history = BlogLog::load(root).unwrap_or_else(BlogLog::empty);
 
     // iterate over every file or directory within root
     for entry in entries {
-        post_from_entry(&mut blog, entry.unwrap(), &config);
+        post_from_entry(&mut blog, &entry.unwrap().path(), config, metadata);
     }
 
+    blog
+}
+
+/// Order posts then load photos for those that need them to be re-rendered or
+/// are missing photo sizes
+fn prepare_blog(
+    root: &Path,
+    config: &BlogConfig,
+    blog: &mut Blog,
+    metadata: &dyn MetadataSource,
+) {
+    blog.correlate_posts();
+
+    for (_, p) in blog.posts.iter_mut().filter(|(_, p)| {
+        p.photos.is_empty()
+            && (p.sequence_changed()
+                || !indexed_sizes_saved(
+                    &root.join(&p.path),
+                    p.photo_count,
+                    config,
+                ))
+    }) {
+        // posts that changed order need to be re-rendered and posts missing
+        // sizes need them created, both of which require all their photo data
+        // to be loaded
+        let path = root.join(&p.path);
+        let tracks = read::load_tracks(&path, config);
+        let photos =
+            read::load_photos(&path, config, metadata, &p.files, &tracks);
+        p.add_photos(photos);
+        // tracks are needed to draw the elevation profile
+        p.tracks =
+            gps::simplify(gps::clean(tracks, &config.track), &config.track);
+    }
+    blog.prepare_maps(config);
+}
+
+/// Report which posts, maps, photos and tag pages a render would process
+/// without writing any files, logs or images
+fn plan(root: &Path, overrides: Override) {
+    let mut config = load_config(root, overrides);
+    config.dry_run = true;
+
+    let metadata = image::metadata_source(&config.photo);
+    let mut blog = load_blog(root, &config, metadata.as_ref());
+
+    prepare_blog(root, &config, &mut blog, metadata.as_ref());
+    blog.collate_tags();
+
+    Plan::new(root, &blog, &config.force).print();
+}
+
+/// Render pages and resize photos for posts that have changed or for all posts
+/// if forced
+fn render_blog(
+    root: &Path,
+    config: &mut BlogConfig,
+    blog: &mut Blog,
+    metadata: &dyn MetadataSource,
+) {
     print!("\n");
     success_metric(blog.post_count(), "total posts");
 
@@ -138,19 +242,7 @@ fn render(path: &str, overrides: Override) {
         return;
     }
 
-    blog.correlate_posts();
-
-    for (_, p) in blog
-        .posts
-        .iter_mut()
-        .filter(|(_, p)| p.photos.is_empty() && p.sequence_changed())
-    {
-        // posts that changed order need to be re-rendered which requires all
-        // their photo data to be loaded
-        p.add_photos(read::load_photos(&root.join(&p.path), &config.photo));
-    }
-
-    blog.prepare_maps(&config);
+    prepare_blog(root, config, blog, metadata);
 
     let render_count = blog.needs_render_count();
     let render_html =
@@ -164,6 +256,7 @@ fn render(path: &str, overrides: Override) {
 
         if let Some(p) = config
             .featured_post
+            .as_ref()
             .and_then(|f| blog.get_featured(&f.path))
         {
             config.featured_post = Some(FeaturedPost {
@@ -178,14 +271,15 @@ fn render(path: &str, overrides: Override) {
 
         success_metric(blog.category_count(), "post categories");
         success_metric(blog.tag_count(), "unique photo tags");
-        BlogLog::write(root, &blog);
+        BlogLog::write(root, blog);
 
-        let write = Writer::new(root, &config, &blog);
+        let write = Writer::new(root, config, blog);
 
         if render_html {
             write.posts();
             write.home_page();
             write.sitemap();
+            write.blog_map();
             write.category_menu();
             write.mobile_menu();
             write.photo_tags();
@@ -196,30 +290,66 @@ fn render(path: &str, overrides: Override) {
 
         write.post_maps();
 
-        for (path, post) in blog.posts {
-            let last_render = post.history.as_of;
-            let full_path = root.join(&path).to_string_lossy().to_string();
-            let mut count: usize = 0;
+        resize_photos(root, config, blog);
+    }
+}
 
-            println!(
-                "\nExamining {} photos in {}",
-                post.title.yellow(),
-                full_path.cyan()
-            );
+/// Create sizes of photos that have changed or are missing sizes, or of every
+/// photo if forced, across the configured number of workers
+fn resize_photos(root: &Path, config: &BlogConfig, blog: &Blog) {
+    let mut photos: Vec<(PathBuf, &Photo)> = Vec::new();
 
-            for p in post.photos {
-                if p.file.created > last_render {
-                    count += 1;
-                    cwebp::create_sizes(&full_path, &p, &config.photo);
-                }
-            }
-            if count > 0 {
-                println!("   Resized {} photo(s)", count);
-            } else {
-                println!("   All photos are current");
+    for (path, post) in blog.posts.iter() {
+        let full_path = root.join(path);
+        let before = photos.len();
+
+        for p in post.photos.iter() {
+            if resize::needs_resize(&full_path, post, p, config.force.photos) {
+                photos.push((full_path.clone(), p));
             }
         }
+
+        if photos.len() > before {
+            println!(
+                "\n{} photo(s) to resize in {}",
+                photos.len() - before,
+                post.title.yellow()
+            );
+        }
     }
+
+    if photos.is_empty() {
+        println!("\nAll photos are current");
+        return;
+    }
+
+    println!();
+
+    let failures = resize::create_sizes(&photos, &config.photo);
+
+    success_metric(photos.len() - failures.len(), "photos resized");
+    resize::report_failures(&failures, root);
+}
+
+/// Whether every size of photos numbered one through `count` has been saved in
+/// every output format, judging by file names alone for posts whose photos
+/// weren't loaded
+fn indexed_sizes_saved(
+    folder: &Path,
+    count: usize,
+    config: &BlogConfig,
+) -> bool {
+    let sizes = resize::render_sizes(&config.photo);
+
+    (1..=count).all(|index| {
+        sizes.iter().all(|(_, suffix, _)| {
+            resize::output_formats(&config.photo).all(|ext| {
+                folder
+                    .join(format!("{:03}_{}{}", index, suffix, ext))
+                    .is_file()
+            })
+        })
+    })
 }
 
 /// Load all entries (files and directories) from the root directory
@@ -240,13 +370,7 @@ fn load_root_directory(root: &Path) -> fs::ReadDir {
 /// Load configuration file and apply command line arguments and environment
 /// variables
 fn load_config(root: &Path, overrides: Override) -> BlogConfig {
-    let mut config: BlogConfig = match BlogConfig::load(root) {
-        Some(config) => config,
-        _ => {
-            println!("{}", "Missing root configuration file".red());
-            process::exit(1)
-        }
-    };
+    let mut config = load_blog_config(root);
     let notify = |label: &str, force: bool| {
         println!("{}", format!("Force {}: {}", label, force).cyan().bold())
     };
@@ -265,22 +389,40 @@ fn load_config(root: &Path, overrides: Override) -> BlogConfig {
     config
 }
 
-/// Create post(s) from a directory entry. The number of posts created for the
+/// Load root configuration file or exit if it's missing or invalid
+fn load_blog_config(root: &Path) -> BlogConfig {
+    match BlogConfig::load(root) {
+        Some(config) => config,
+        _ => {
+            println!("{}", "Missing or invalid root configuration file".red());
+            process::exit(1)
+        }
+    }
+}
+
+/// Create post(s) from a root directory path. The number of posts created for the
 /// blog may be one, several or none depending on whether the entry is a
 /// post-containing directory, a series-containing directory or neither,
 /// respectively.
-fn post_from_entry(blog: &mut Blog, entry: fs::DirEntry, config: &BlogConfig) {
-    let path: PathBuf = entry.path();
-    let dir_name: &str = folder_name(&path);
-
-    if !path.is_dir() || config.ignore_folders.contains(&dir_name.to_string()) {
-        // ignore root files and specified folders
+fn post_from_entry(
+    blog: &mut Blog,
+    path: &Path,
+    config: &BlogConfig,
+    metadata: &dyn MetadataSource,
+) {
+    let dir_name: &str = folder_name(path);
+
+    if !path.is_dir()
+        || dir_name.starts_with('.')
+        || config.ignore_folders.contains(&dir_name.to_string())
+    {
+        // ignore root files, hidden folders and specified folders
         return;
     }
 
     println!("\n{} └ {}", "Found root directory".bold(), dir_name.bold());
 
-    if let Some(posts) = read::series(&path, &config) {
+    if let Some(posts) = read::series(path, &config, metadata) {
         println!("   Series of {} posts:", posts.len());
         for p in posts {
             println!("{:6}{} ({} photos)", "", p.title.yellow(), p.photo_count);
@@ -290,7 +432,7 @@ fn post_from_entry(blog: &mut Blog, entry: fs::DirEntry, config: &BlogConfig) {
         return;
     }
 
-    if let Some(post) = read::post(path.as_path(), &config) {
+    if let Some(post) = read::post(path, &config, metadata) {
         println!("   {} ({} photos)", post.title.yellow(), post.photo_count);
         blog.add_post(post);
     }
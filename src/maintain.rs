@@ -0,0 +1,270 @@
+//! Commands that validate, clean or summarize a blog without rendering it
+
+use crate::{
+    config::{
+        BlogConfig, PostConfig, SeriesConfig, CACHE_FILE, CONFIG_FILE, LOG_FILE,
+    },
+    image::{self, resize},
+    load_blog, load_blog_config,
+    models::CategoryKind,
+    success_metric,
+    tools::{self, folder_name},
+};
+use colored::*;
+use std::{
+    collections::BTreeSet,
+    fs,
+    path::{Path, PathBuf},
+    process,
+};
+
+/// Validate blog, series and post configuration and report problems without
+/// rendering. Exits with a non-zero code if any are found.
+pub fn check(root: &Path) {
+    let config = load_blog_config(root);
+    let mut problems: Vec<String> = Vec::new();
+    let mut post_count: usize = 0;
+
+    for path in root_folders(root, &config) {
+        let parts: Vec<PathBuf> = sub_folders(&path)
+            .into_iter()
+            .filter(|p| p.join(CONFIG_FILE).is_file())
+            .collect();
+
+        if parts.is_empty() {
+            if path.join(CONFIG_FILE).is_file() {
+                post_count += 1;
+                check_post(&path, &config, None, &mut problems);
+            }
+            continue;
+        }
+
+        match SeriesConfig::load(&path) {
+            Some(series) => {
+                let mut seen: BTreeSet<u8> = BTreeSet::new();
+
+                for p in parts.iter() {
+                    post_count += 1;
+
+                    if let Some(part) =
+                        check_post(p, &config, Some(&series), &mut problems)
+                    {
+                        if !seen.insert(part) {
+                            problems.push(format!(
+                                "{} has more than one part {}",
+                                series.path, part
+                            ));
+                        }
+                    }
+                }
+            }
+            _ => problems.push(format!(
+                "{} has posts but no valid series configuration",
+                folder_name(&path)
+            )),
+        }
+    }
+
+    println!();
+    success_metric(post_count, "posts checked");
+
+    if problems.is_empty() {
+        success_metric(0, "problems");
+    } else {
+        println!("{}", format!("{:>5} problems", problems.len()).bold().red());
+
+        for p in problems.iter() {
+            println!("{:6}{}", "", p.red());
+        }
+        process::exit(1);
+    }
+}
+
+/// Check configuration and photo count of the post in `path`, adding any
+/// problems to the list. Returns the series part if the post is in a series.
+fn check_post(
+    path: &Path,
+    config: &BlogConfig,
+    series: Option<&SeriesConfig>,
+    problems: &mut Vec<String>,
+) -> Option<u8> {
+    let name = tools::path_slice(path, if series.is_some() { 2 } else { 1 });
+    let post = match PostConfig::load(path) {
+        Some(post) => post,
+        _ => {
+            problems.push(format!("{} has invalid configuration", name));
+            return None;
+        }
+    };
+    let photo_count = fs::read_dir(path)
+        .map(|entries| {
+            entries
+                .filter_map(|e| e.ok())
+                .filter(|e| {
+                    e.file_name()
+                        .to_string_lossy()
+                        .ends_with(&config.photo.source_ext)
+                })
+                .count()
+        })
+        .unwrap_or(0);
+
+    if photo_count == 0 {
+        problems.push(format!("{} has no photos", name));
+    } else if post.cover_photo_index > photo_count {
+        problems.push(format!(
+            "{} cover photo index {} exceeds its {} photos",
+            name, post.cover_photo_index, photo_count
+        ));
+    }
+
+    let series = series?;
+
+    if post.part == 0 || post.part > series.parts {
+        problems.push(format!(
+            "{} part {} is not between 1 and {}",
+            name, post.part, series.parts
+        ));
+        None
+    } else {
+        Some(post.part)
+    }
+}
+
+/// Remove render logs and photo caches so the next render is complete. Resized
+/// photos are also removed if `photos` is true.
+pub fn clean(root: &Path, photos: bool) {
+    let config = load_blog_config(root);
+    let mut removed: usize = 0;
+    let resized = |name: &str| {
+        resize::output_formats(&config.photo).any(|ext| name.ends_with(ext))
+            && resize::is_resized(name)
+    };
+    let mut remove = |path: PathBuf| {
+        if let Err(e) = fs::remove_file(&path) {
+            println!(
+                "   {} {} {:?}",
+                "Failed to remove".red(),
+                path.display(),
+                e
+            );
+        } else {
+            removed += 1;
+        }
+    };
+
+    if root.join(LOG_FILE).is_file() {
+        remove(root.join(LOG_FILE));
+    }
+
+    for folder in root_folders(root, &config) {
+        let mut folders = sub_folders(&folder);
+        folders.push(folder);
+
+        for path in folders {
+            let entries = match fs::read_dir(&path) {
+                Ok(entries) => entries,
+                _ => continue,
+            };
+
+            for entry in entries.filter_map(|e| e.ok()) {
+                let name = entry.file_name().to_string_lossy().to_string();
+
+                if name == LOG_FILE
+                    || name == CACHE_FILE
+                    || (photos && resized(&name))
+                {
+                    remove(entry.path());
+                }
+            }
+        }
+    }
+
+    println!();
+    success_metric(removed, "files removed");
+}
+
+/// Summarize posts, photos, categories and tags. Unchanged posts are summarized
+/// from their logs and, as with a dry run, no caches are written.
+pub fn stats(root: &Path) {
+    let mut config = load_blog_config(root);
+    config.dry_run = true;
+
+    let metadata = image::metadata_source(&config.photo);
+    let mut blog = load_blog(root, &config, metadata.as_ref());
+
+    blog.correlate_posts();
+    blog.collate_tags();
+
+    let posts = blog.posts.values();
+    let series: BTreeSet<&str> = posts
+        .clone()
+        .filter_map(|p| p.series.as_ref().map(|s| s.path.as_str()))
+        .collect();
+    let dates: Vec<_> = posts.clone().filter_map(|p| p.happened_on).collect();
+
+    println!();
+    success_metric(blog.post_count(), "posts");
+    success_metric(series.len(), "series");
+    success_metric(posts.clone().map(|p| p.photo_count).sum(), "photos");
+    success_metric(
+        posts.map(|p| p.photo_locations.len()).sum(),
+        "photos with locations",
+    );
+
+    for kind in &[
+        CategoryKind::Who,
+        CategoryKind::What,
+        CategoryKind::When,
+        CategoryKind::Where,
+    ] {
+        success_metric(
+            blog.categories.get(kind).map_or(0, |list| list.len()),
+            &format!("{} categories", kind),
+        );
+    }
+
+    success_metric(blog.tag_count(), "unique photo tags");
+    success_metric(blog.needs_render_count(), "posts need rendered");
+
+    if let (Some(first), Some(last)) = (dates.iter().min(), dates.iter().max())
+    {
+        println!(
+            "{}",
+            format!(
+                "{:>5} {} to {}",
+                "",
+                first.format("%B %e, %Y"),
+                last.format("%B %e, %Y")
+            )
+            .bold()
+            .green()
+        );
+    }
+}
+
+/// Root folders that may contain posts or series
+fn root_folders(root: &Path, config: &BlogConfig) -> Vec<PathBuf> {
+    sub_folders(root)
+        .into_iter()
+        .filter(|p| {
+            !config.ignore_folders.contains(&folder_name(p).to_string())
+        })
+        .collect()
+}
+
+/// Sorted folders within `path`, excluding hidden folders like the map cache
+fn sub_folders(path: &Path) -> Vec<PathBuf> {
+    let mut folders: Vec<PathBuf> = fs::read_dir(path)
+        .map(|entries| {
+            entries
+                .filter_map(|e| e.ok())
+                .map(|e| e.path())
+                .filter(|p| p.is_dir() && !folder_name(p).starts_with('.'))
+                .collect()
+        })
+        .unwrap_or_default();
+
+    folders.sort();
+    folders
+}
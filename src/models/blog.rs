@@ -1,4 +1,4 @@
-use crate::config::{BlogConfig, BlogLog, ExifConfig};
+use crate::config::{BlogConfig, BlogLog, ExifConfig, PostLog};
 use crate::models::{Category, CategoryKind, PhotoPath, Post, TagPhotos};
 use chrono::{DateTime, FixedOffset};
 use hashbrown::HashMap;
@@ -33,6 +33,76 @@ impl Blog {
         self.posts.insert(p.path.clone(), p);
     }
 
+    /// Remove posts in the root `folder`, which may be a single post or a
+    /// series, along with their category references. Returns the number of
+    /// posts removed.
+    pub fn remove_posts(&mut self, folder: &str) -> usize {
+        let prefix = format!("{}/", folder);
+        let paths: Vec<String> = self
+            .posts
+            .keys()
+            .filter(|p| *p == folder || p.starts_with(&prefix))
+            .cloned()
+            .collect();
+
+        for path in paths.iter() {
+            self.posts.remove(path);
+        }
+
+        for categories in self.categories.values_mut() {
+            for c in categories.iter_mut() {
+                c.post_paths.retain(|p| !paths.contains(p));
+            }
+            categories.retain(|c| !c.post_paths.is_empty());
+        }
+        self.categories
+            .retain(|_, categories| !categories.is_empty());
+
+        paths.len()
+    }
+
+    /// Replace post and tag history with current values so that a later
+    /// render only considers subsequent changes
+    pub fn update_history(&mut self) {
+        for p in self.posts.values_mut() {
+            let log = PostLog::from_post(p);
+            p.history = log;
+        }
+        self.history.tags = self.tags.clone();
+    }
+
+    /// GeoJSON feature collection of every located photo, grouped by post,
+    /// with thumbnails of `image_ext` type
+    pub fn photo_geo_json(&self, image_ext: &str) -> serde_json::Value {
+        let features: Vec<serde_json::Value> = self
+            .posts
+            .values()
+            .flat_map(|p| {
+                p.photo_points.iter().map(move |(index, lon, lat)| {
+                    let path = PhotoPath {
+                        post_path: p.path.clone(),
+                        photo_index: *index,
+                    };
+
+                    serde_json::json!({
+                        "type": "Feature",
+                        "properties": {
+                            "post": p.path,
+                            "index": index,
+                            "url": format!("/{}", path.thumb_url(image_ext))
+                        },
+                        "geometry": {
+                            "type": "Point",
+                            "coordinates": [lon, lat]
+                        }
+                    })
+                })
+            })
+            .collect();
+
+        serde_json::json!({ "type": "FeatureCollection", "features": features })
+    }
+
     /// Calculate map image sizes based on cover image dimensions
     pub fn prepare_maps(&mut self, config: &BlogConfig) {
         for (_, p) in self.posts.iter_mut() {
@@ -153,6 +223,10 @@ impl Blog {
 
         for (k, p) in self.posts.iter_mut() {
             // sorted position of post
+            // clear sequence from any previous correlation
+            p.prev_path = None;
+            p.next_path = None;
+
             if let Some(i) = ordered.iter().position(|kt| kt.path == *k) {
                 if i > 0 {
                     p.prev_path =
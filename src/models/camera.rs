@@ -1,7 +1,8 @@
 use super::ExposureMode;
+use serde::{Deserialize, Serialize};
 
 /// Information about the camera used to make the photo.
-#[derive(Debug, Default, Clone)]
+#[derive(Serialize, Deserialize, Debug, Default, Clone)]
 pub struct Camera {
     /// Make and model of the camera
     pub name: String,
@@ -1,6 +1,6 @@
 use crate::num_traits::FromPrimitive;
 use fmt::Display;
-use serde::{de, Deserialize, Deserializer};
+use serde::{de, Deserialize, Deserializer, Serialize, Serializer};
 use std::{fmt, marker::Copy};
 
 #[derive(Debug, Primitive, Copy, PartialEq, Eq)]
@@ -39,6 +39,15 @@ impl<'de> Deserialize<'de> for ExposureMode {
     }
 }
 
+impl Serialize for ExposureMode {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        serializer.serialize_u8(*self as u8)
+    }
+}
+
 impl Display for ExposureMode {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match *self {
@@ -1,10 +1,15 @@
-use serde::Deserialize;
+use serde::{Deserialize, Serialize};
 
 /// Latitude and longitude in degrees
-#[derive(Debug, Default, Deserialize, Clone)]
+#[derive(Debug, Default, Serialize, Deserialize, Clone)]
 pub struct Location {
     pub longitude: f32,
     pub latitude: f32,
+
+    /// Whether the location was interpolated from a GPS track rather than
+    /// recorded by the camera
+    #[serde(default)]
+    pub inferred: bool,
 }
 
 impl Location {
@@ -7,6 +7,7 @@ mod photo;
 mod post;
 mod size;
 mod tag;
+mod track;
 
 pub use blog::Blog;
 pub use camera::Camera;
@@ -17,3 +18,4 @@ pub use photo::{Photo, PhotoFile, PhotoPath};
 pub use post::{Post, PostSeries};
 pub use size::{suffix, Size, SizeCollection};
 pub use tag::{collate_tags, TagPhotos};
+pub use track::{geo_json, Track, TrackPoint, TrackStats};
@@ -31,8 +31,6 @@ impl PhotoPath {
 pub struct PhotoFile {
     /// File name of source image including extension
     pub name: String,
-    /// Timestamp when the file was created
-    pub created: i64,
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone)]
@@ -67,6 +65,22 @@ pub struct Photo {
     #[serde(skip)]
     pub tags: Vec<String>,
 
+    /// City where photo was taken
+    #[serde(skip)]
+    pub city: Option<String>,
+
+    /// State or province where photo was taken
+    #[serde(skip)]
+    pub state: Option<String>,
+
+    /// Terms under which the photo may be used, like "All Rights Reserved"
+    #[serde(skip)]
+    pub usage_terms: Option<String>,
+
+    /// Star rating from `1` to `5`
+    #[serde(skip)]
+    pub rating: Option<u8>,
+
     /// When the photograph was taken per camera EXIF
     #[serde(skip)]
     pub date_taken: Option<DateTime<FixedOffset>>,
@@ -111,14 +125,55 @@ impl Photo {
         let size = &self.size.medium;
 
         // TODO: needs full image path?
-        serde_json::json!({
+        let mut json = serde_json::json!({
             "@type": "ImageObject",
             "url": size.name,
             "width": size.width,
             "height": size.height
+        });
+
+        if let Some(place) = self.place_name() {
+            json["contentLocation"] = serde_json::json!({
+                "@type": "Place",
+                "name": place
+            });
+        }
+        if let Some(terms) = &self.usage_terms {
+            json["copyrightNotice"] = serde_json::json!(terms);
+        }
+        json
+    }
+
+    /// GeoJSON point with the photo thumbnail for the map photo layer or `None`
+    /// if the photo has no location
+    pub fn geo_json(&self, post_path: &str) -> Option<serde_json::Value> {
+        self.location.as_ref().map(|l| {
+            serde_json::json!({
+                "type": "Feature",
+                "properties": {
+                    "index": self.index,
+                    "title": self.title,
+                    "post": post_path,
+                    "url": format!("/{}/{}", post_path, self.size.thumb.name),
+                    "inferred": l.inferred
+                },
+                "geometry": {
+                    "type": "Point",
+                    "coordinates": [l.longitude, l.latitude]
+                }
+            })
         })
     }
 
+    /// City and state where photo was taken, such as "Boise, Idaho"
+    pub fn place_name(&self) -> Option<String> {
+        match (&self.city, &self.state) {
+            (Some(city), Some(state)) => Some(format!("{}, {}", city, state)),
+            (Some(place), None) | (None, Some(place)) => Some(place.clone()),
+            _ => None,
+        }
+    }
+
     /// Whether photo is in portrait orientation (taller than wide)
     pub fn is_portrait(&self) -> bool {
         self.size.is_portrait()
@@ -155,7 +210,6 @@ impl Default for Photo {
         Photo {
             file: PhotoFile {
                 name: String::new(),
-                created: 0,
             },
             artist: None,
             software: String::new(),
@@ -165,6 +219,10 @@ impl Default for Photo {
             location: None,
             index: 0,
             tags: Vec::new(),
+            city: None,
+            state: None,
+            usage_terms: None,
+            rating: None,
             date_taken: None,
             outlier_date: false,
             size: SizeCollection::default(),
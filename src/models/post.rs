@@ -1,11 +1,11 @@
 use crate::{
     config::{BlogConfig, PostConfig, PostLog},
     json_ld,
-    models::{collate_tags, Category, Photo, TagPhotos},
-    tools::earliest_photo_date,
+    models::{collate_tags, Category, Photo, TagPhotos, Track, TrackStats},
+    tools::{earliest_photo_date, FileStamp},
 };
 use chrono::{DateTime, FixedOffset, Utc};
-use core::cmp::Ordering;
+use core::cmp::{Ordering, Reverse};
 use serde_json;
 use std::{collections::BTreeMap, time::SystemTime};
 
@@ -90,6 +90,15 @@ pub struct Post {
 
     /// Whether GPX track was found for the post
     pub has_track: bool,
+
+    /// Tracks from post GPX files. Like `photos`, these are only loaded when
+    /// post files have changed.
+    pub tracks: Vec<Track>,
+
+    /// Distance, elevation and timing computed from `tracks` and logged so
+    /// they're available when tracks aren't loaded
+    pub track_stats: Option<TrackStats>,
+
     /// Categories to which this post belongs
     pub categories: Vec<Category>,
 
@@ -99,14 +108,25 @@ pub struct Post {
     /// https://docs.mapbox.com/api/maps/#retrieve-a-static-map-from-a-style
     pub photo_locations: Vec<(f32, f32)>,
 
+    /// Index, longitude and latitude of each located photo for the site map,
+    /// logged so posts don't need their photos reloaded to be included
+    pub photo_points: Vec<(u8, f32, f32)>,
+
     /// Zero-based index of cover photo within vector of photos
     pub cover_photo_index: usize,
 
+    /// Whether no cover photo is configured so the top rated photo is used
+    pub rated_cover: bool,
+
     pub tags: BTreeMap<String, TagPhotos<u8>>,
 
     /// Record of previous post photos and configuration
     pub history: PostLog,
 
+    /// Size and digest of each photo and the configuration file, keyed by
+    /// file name
+    pub files: BTreeMap<String, FileStamp>,
+
     /// Width/height of cover map
     pub cover_map_size: (u16, u16),
 }
@@ -132,12 +152,16 @@ impl Post {
         photo
     }
 
+    /// Set photos and the values derived from them, including the top rated
+    /// cover photo if none is configured
     pub fn add_photos(&mut self, photos: Vec<Photo>) {
         let mut locations: Vec<(f32, f32)> = Vec::new();
+        let mut points: Vec<(u8, f32, f32)> = Vec::new();
 
         for p in &photos {
             if let Some(l) = &p.location {
                 locations.push(l.as_tuple());
+                points.push((p.index, l.longitude, l.latitude));
             }
         }
 
@@ -161,8 +185,24 @@ impl Post {
 
         self.tags = collate_tags(&photos);
         self.photo_locations = locations;
+        self.photo_points = points;
         self.photo_count = photos.len();
         self.photos = photos;
+
+        if self.rated_cover {
+            self.cover_photo_index = self.top_rated_photo().unwrap_or(0);
+        }
+    }
+
+    /// Index of the highest rated photo, the first if several share the top
+    /// rating, or `None` if no photo is rated
+    pub fn top_rated_photo(&self) -> Option<usize> {
+        self.photos
+            .iter()
+            .enumerate()
+            .filter_map(|(i, p)| p.rating.map(|r| (i, r)))
+            .max_by_key(|(i, r)| (*r, Reverse(*i)))
+            .map(|(i, _)| i)
     }
 
     pub fn has_video(&self) -> bool {
@@ -212,17 +252,22 @@ impl Default for Post {
             prev_path: None,
 
             has_track: false,
+            tracks: Vec::new(),
+            track_stats: None,
             categories: Vec::new(),
 
             photo_count: 0,
             cover_photo_index: 0,
+            rated_cover: false,
             cover_map_size: (0, 0),
 
             tags: BTreeMap::new(),
             history: PostLog::empty(),
+            files: BTreeMap::new(),
             series: None,
 
             photo_locations: Vec::new(),
+            photo_points: Vec::new(),
         }
     }
 }
@@ -238,6 +283,7 @@ impl Post {
             summary: config.summary,
             // convert to zero-based index
             cover_photo_index: if i > 0 { i - 1 } else { 0 },
+            rated_cover: i == 0,
             chronological: config.chronological,
             history: log,
             ..Self::default()
@@ -264,10 +310,35 @@ impl Post {
             "mainEntityOfPage": json_ld::web_page(config, "about"),
             "datePublished": &self.happened_on.map(|d| d.to_rfc3339()),
             "dateModified": &self.updated_on.to_rfc3339(),
-            "articleSection": categories.join(",")
+            "articleSection": categories.join(","),
+            "about": self.track_stats.as_ref().map(json_ld::trip)
         })
     }
 
+    /// GeoJSON feature collection of post photos that have locations
+    pub fn photo_geo_json(&self) -> serde_json::Value {
+        let features: Vec<serde_json::Value> = self
+            .photos
+            .iter()
+            .filter_map(|p| p.geo_json(&self.path))
+            .collect();
+
+        serde_json::json!({ "type": "FeatureCollection", "features": features })
+    }
+
+    /// Southwest and northeast longitude/latitude corners around photo
+    /// locations or `None` if there are none
+    pub fn bounds(&self) -> Option<((f32, f32), (f32, f32))> {
+        let (first, rest) = self.photo_locations.split_first()?;
+
+        Some(rest.iter().fold((*first, *first), |(sw, ne), (lon, lat)| {
+            (
+                (sw.0.min(*lon), sw.1.min(*lat)),
+                (ne.0.max(*lon), ne.1.max(*lat)),
+            )
+        }))
+    }
+
     /// Build root-relative URLs for all post photo sizes and compute cover map
     /// dimensions to fit next to small image within content width
     pub fn prepare_maps(&mut self, config: &BlogConfig) {
@@ -311,6 +382,12 @@ impl Post {
         self.history.cover_aspect_ratio_changed(self)
     }
 
+    /// Whether the photo file has been added or changed since the last render
+    /// so its sizes need to be regenerated
+    pub fn photo_changed(&self, photo: &Photo) -> bool {
+        self.history.photo_changed(self, photo)
+    }
+
     /// Whether photos or configuration have changed
     pub fn files_changed(&self) -> bool {
         self.history.files_changed
@@ -336,3 +413,89 @@ impl PartialEq for Post {
 }
 
 impl Eq for Post {}
+
+#[cfg(test)]
+mod tests {
+    use super::Post;
+    use crate::models::{Location, Photo};
+
+    #[test]
+    fn photo_geo_json_test() {
+        let located = |index: u8, longitude: f32, latitude: f32| Photo {
+            index,
+            location: Some(Location {
+                longitude,
+                latitude,
+                inferred: false,
+            }),
+            ..Photo::default()
+        };
+        let mut post = Post {
+            path: "brother-ride/2.trying-to-survive".to_owned(),
+            ..Post::default()
+        };
+
+        assert_eq!(post.bounds(), None);
+
+        post.add_photos(vec![
+            located(1, -116.0, 46.0),
+            Photo {
+                index: 2,
+                ..Photo::default()
+            },
+            located(3, -115.5, 45.5),
+        ]);
+
+        let json = post.photo_geo_json();
+        let features = json["features"].as_array().unwrap();
+
+        assert_eq!(features.len(), 2);
+        assert_eq!(features[1]["properties"]["index"], 3);
+        assert_eq!(
+            features[0]["properties"]["post"],
+            "brother-ride/2.trying-to-survive"
+        );
+        assert_eq!(
+            features[0]["geometry"]["coordinates"],
+            serde_json::json!([-116.0, 46.0])
+        );
+        assert_eq!(post.bounds(), Some(((-116.0, 45.5), (-115.5, 46.0))));
+        assert_eq!(
+            post.photo_points,
+            vec![(1, -116.0, 46.0), (3, -115.5, 45.5)]
+        );
+    }
+
+    #[test]
+    fn top_rated_photo_test() {
+        let rated = |rating: Option<u8>| Photo {
+            rating,
+            ..Photo::default()
+        };
+        let mut post = Post::default();
+
+        post.add_photos(vec![rated(None), rated(None)]);
+        assert_eq!(post.top_rated_photo(), None);
+
+        post.add_photos(vec![
+            rated(Some(3)),
+            rated(None),
+            rated(Some(5)),
+            rated(Some(5)),
+        ]);
+        assert_eq!(post.top_rated_photo(), Some(2));
+        // configured cover is kept
+        assert_eq!(post.cover_photo_index, 0);
+
+        let mut post = Post {
+            rated_cover: true,
+            ..Post::default()
+        };
+
+        post.add_photos(vec![rated(Some(3)), rated(Some(4))]);
+        assert_eq!(post.cover_photo_index, 1);
+
+        post.add_photos(vec![rated(None), rated(None)]);
+        assert_eq!(post.cover_photo_index, 0);
+    }
+}
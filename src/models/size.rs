@@ -38,22 +38,55 @@ impl SizeCollection {
         let name = |end: &'static str| {
             format!("{:03}_{}{}", index, end, config.output_ext)
         };
+        let alternates = |size: Size, end: &'static str| Size {
+            alternates: config
+                .output_formats
+                .iter()
+                .map(|ext| format!("{:03}_{}{}", index, end, ext))
+                .collect(),
+            ..size
+        };
         let original = Size::new(width, height, name(suffix::ORIGINAL));
         let size = &config.size.display;
 
         SizeCollection {
-            large: original.limit_to(size.large, name(suffix::LARGE)),
-            medium: original.limit_to(size.medium, name(suffix::MEDIUM)),
-            small: original.limit_to(size.small, name(suffix::SMALL)),
-            thumb: Size {
-                width: size.thumb,
-                height: size.thumb,
-                name: name(suffix::THUMB),
-            },
+            large: alternates(
+                original.limit_to(size.large, name(suffix::LARGE)),
+                suffix::LARGE,
+            ),
+            medium: alternates(
+                original.limit_to(size.medium, name(suffix::MEDIUM)),
+                suffix::MEDIUM,
+            ),
+            small: alternates(
+                original.limit_to(size.small, name(suffix::SMALL)),
+                suffix::SMALL,
+            ),
+            thumb: alternates(
+                Size::new(size.thumb, size.thumb, name(suffix::THUMB)),
+                suffix::THUMB,
+            ),
             original,
         }
     }
 
+    /// Sizes in each additional format, most preferred first, for `<source>`
+    /// elements ahead of the `output_ext` image
+    pub fn sources(&self) -> Vec<Source<'_>> {
+        self.medium
+            .alternates
+            .iter()
+            .enumerate()
+            .map(|(i, medium)| Source {
+                mime_type: mime_type(medium),
+                large: &self.large.alternates[i],
+                medium,
+                small: &self.small.alternates[i],
+                thumb: &self.thumb.alternates[i],
+            })
+            .collect()
+    }
+
     /// Whether photo is in portrait orientation (taller than wide)
     pub fn is_portrait(&self) -> bool {
         self.original.width < self.original.height
@@ -70,11 +103,41 @@ impl SizeCollection {
     }
 }
 
+/// File names of each post size in one additional format
+#[derive(Debug, PartialEq, Eq)]
+pub struct Source<'a> {
+    /// Media type of the format, like `image/avif`
+    pub mime_type: &'static str,
+    pub large: &'a str,
+    pub medium: &'a str,
+    pub small: &'a str,
+    pub thumb: &'a str,
+}
+
+/// Media type for the extension of a file name
+fn mime_type(name: &str) -> &'static str {
+    let ext = name.rsplit('.').next().unwrap_or_default().to_lowercase();
+
+    match ext.as_str() {
+        "avif" => "image/avif",
+        "webp" => "image/webp",
+        "jpg" | "jpeg" => "image/jpeg",
+        "png" => "image/png",
+        "gif" => "image/gif",
+        "tif" | "tiff" => "image/tiff",
+        _ => "application/octet-stream",
+    }
+}
+
 #[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq, Eq)]
 pub struct Size {
     pub width: u16,
     pub height: u16,
     pub name: String,
+    /// File names of the same size in each of the configured
+    /// `output_formats`
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub alternates: Vec<String>,
 }
 
 impl Size {
@@ -83,6 +146,7 @@ impl Size {
             width,
             height,
             name,
+            alternates: Vec::new(),
         }
     }
 
@@ -137,4 +201,31 @@ mod tests {
 
         assert_eq!(source.limit_to(800, name(suffix::MEDIUM)), target);
     }
+
+    #[test]
+    fn sources_test() {
+        let mut config = PhotoConfig::fixture();
+        config.output_formats = vec![".avif".to_owned(), ".png".to_owned()];
+
+        let sizes = SizeCollection::from(1024, 768, 6, &config);
+        let sources = sizes.sources();
+
+        assert_eq!(sizes.medium.name, "006_m.webp");
+        assert_eq!(sizes.medium.alternates, vec!["006_m.avif", "006_m.png"]);
+        assert!(sizes.original.alternates.is_empty());
+        assert_eq!(
+            sources[0],
+            Source {
+                mime_type: "image/avif",
+                large: "006_l.avif",
+                medium: "006_m.avif",
+                small: "006_s.avif",
+                thumb: "006_t.avif",
+            }
+        );
+        assert_eq!(sources[1].mime_type, "image/png");
+        assert!(SizeCollection::from(1024, 768, 6, &PhotoConfig::fixture())
+            .sources()
+            .is_empty());
+    }
 }
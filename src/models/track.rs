@@ -0,0 +1,200 @@
+use chrono::{DateTime, FixedOffset};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+
+/// Point recorded by a GPS device
+#[derive(Debug, Clone, PartialEq)]
+pub struct TrackPoint {
+    pub latitude: f64,
+    pub longitude: f64,
+    /// Meters above sea level
+    pub elevation: Option<f32>,
+    pub time: Option<DateTime<FixedOffset>>,
+}
+
+impl TrackPoint {
+    /// Latitude and longitude pair
+    pub fn lat_lon(&self) -> (f64, f64) {
+        (self.latitude, self.longitude)
+    }
+
+    /// GeoJSON longitude, latitude position rounded to about a meter
+    fn position(&self) -> Value {
+        let round = |n: f64| (n * 100_000.0).round() / 100_000.0;
+        json!([round(self.longitude), round(self.latitude)])
+    }
+}
+
+#[cfg(test)]
+impl TrackPoint {
+    pub fn new(latitude: f64, longitude: f64) -> Self {
+        TrackPoint {
+            latitude,
+            longitude,
+            elevation: None,
+            time: None,
+        }
+    }
+}
+
+/// Named track or route from a GPX file. Each segment is a continuous series
+/// of points so there may be gaps between segments where a device was paused.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Track {
+    pub name: Option<String>,
+    pub segments: Vec<Vec<TrackPoint>>,
+}
+
+impl Track {
+    pub fn point_count(&self) -> usize {
+        self.segments.iter().map(|s| s.len()).sum()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.segments.iter().all(|s| s.is_empty())
+    }
+}
+
+/// Trip statistics computed from post tracks
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+pub struct TrackStats {
+    pub distance_miles: f32,
+    pub elevation_gain_feet: f32,
+    pub elevation_loss_feet: f32,
+    /// Highest point or `None` if tracks have no elevations
+    pub max_elevation_feet: Option<f32>,
+    /// Time of first timed point
+    pub start: Option<DateTime<FixedOffset>>,
+    /// Time of last timed point
+    pub end: Option<DateTime<FixedOffset>>,
+    /// Seconds spent moving between timed points, excluding stops
+    pub moving_seconds: i64,
+}
+
+impl TrackStats {
+    /// Distance to a tenth of a mile, such as "23.4"
+    pub fn miles(&self) -> String {
+        format!("{:.1}", self.distance_miles)
+    }
+
+    /// Elevation gain to the nearest foot with thousands separated, such as
+    /// "2,350"
+    pub fn gain(&self) -> String {
+        thousands(self.elevation_gain_feet)
+    }
+
+    pub fn loss(&self) -> String {
+        thousands(self.elevation_loss_feet)
+    }
+
+    pub fn max_elevation(&self) -> Option<String> {
+        self.max_elevation_feet.map(thousands)
+    }
+
+    /// Hours and minutes spent moving, such as "3h 05m", or `None` if the
+    /// tracks were untimed
+    pub fn moving_time(&self) -> Option<String> {
+        if self.moving_seconds > 0 {
+            let minutes = (self.moving_seconds + 30) / 60;
+            Some(format!("{}h {:02}m", minutes / 60, minutes % 60))
+        } else {
+            None
+        }
+    }
+}
+
+/// Whole number with comma thousands separators
+fn thousands(n: f32) -> String {
+    let digits = format!("{:.0}", n.abs());
+    let mut text = String::new();
+
+    for (i, c) in digits.chars().enumerate() {
+        if i > 0 && (digits.len() - i) % 3 == 0 {
+            text.push(',');
+        }
+        text.push(c);
+    }
+    if n <= -0.5 {
+        text.insert(0, '-');
+    }
+    text
+}
+
+/// GeoJSON feature collection with a `LineString` for every track segment
+///
+/// https://tools.ietf.org/html/rfc7946
+pub fn geo_json(tracks: &[Track]) -> Value {
+    let features: Vec<Value> = tracks
+        .iter()
+        .flat_map(|t| {
+            t.segments.iter().filter(|s| s.len() > 1).map(move |s| {
+                json!({
+                    "type": "Feature",
+                    "properties": { "name": t.name },
+                    "geometry": {
+                        "type": "LineString",
+                        "coordinates": s
+                            .iter()
+                            .map(|p| p.position())
+                            .collect::<Vec<Value>>()
+                    }
+                })
+            })
+        })
+        .collect();
+
+    json!({ "type": "FeatureCollection", "features": features })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{geo_json, Track, TrackPoint, TrackStats};
+    use serde_json::json;
+
+    #[test]
+    fn geo_json_test() {
+        let track = Track {
+            name: Some("Ride".to_owned()),
+            segments: vec![
+                vec![
+                    TrackPoint::new(46.123_456_7, -116.1),
+                    TrackPoint::new(46.2, -116.2),
+                ],
+                // single points can't form a line
+                vec![TrackPoint::new(46.3, -116.3)],
+            ],
+        };
+
+        assert_eq!(
+            geo_json(&[track]),
+            json!({
+                "type": "FeatureCollection",
+                "features": [{
+                    "type": "Feature",
+                    "properties": { "name": "Ride" },
+                    "geometry": {
+                        "type": "LineString",
+                        "coordinates": [[-116.1, 46.12346], [-116.2, 46.2]]
+                    }
+                }]
+            })
+        );
+    }
+
+    #[test]
+    fn stats_format_test() {
+        let stats = TrackStats {
+            distance_miles: 23.44,
+            elevation_gain_feet: 12_345.4,
+            max_elevation_feet: Some(980.0),
+            moving_seconds: 3 * 3600 + 5 * 60 + 10,
+            ..TrackStats::default()
+        };
+
+        assert_eq!(stats.miles(), "23.4");
+        assert_eq!(stats.gain(), "12,345");
+        assert_eq!(stats.max_elevation(), Some("980".to_owned()));
+        assert_eq!(stats.moving_time(), Some("3h 05m".to_owned()));
+        assert_eq!(TrackStats::default().moving_time(), None);
+    }
+}
@@ -0,0 +1,291 @@
+//! Report what a render would do without doing it
+
+use crate::{config::Overrides, image::resize, models::Blog};
+use colored::*;
+use std::path::Path;
+
+/// Work a render would do for one post
+#[derive(Debug, PartialEq)]
+pub struct PostPlan<'a> {
+    pub path: &'a str,
+    pub title: &'a str,
+    /// Why the post page would be rendered
+    pub render: Vec<&'static str>,
+    /// Why the post map images would be downloaded
+    pub maps: Vec<&'static str>,
+    /// Names of photos that would be resized
+    pub resize: Vec<&'a str>,
+}
+
+impl<'a> PostPlan<'a> {
+    fn is_empty(&self) -> bool {
+        self.render.is_empty() && self.maps.is_empty() && self.resize.is_empty()
+    }
+}
+
+/// Work a render would do for a blog that has been loaded, correlated and had
+/// its tags collated. This follows the same conditions as `render_blog()` and
+/// the `Writer`.
+#[derive(Debug, PartialEq)]
+pub struct Plan<'a> {
+    /// Whether root, category and tag pages would be rendered
+    pub render_html: bool,
+    /// Posts with work to do
+    pub posts: Vec<PostPlan<'a>>,
+    /// Slugs of tag pages that would be rendered
+    pub tags: Vec<&'a str>,
+}
+
+impl<'a> Plan<'a> {
+    pub fn new(root: &Path, blog: &'a Blog, force: &Overrides) -> Self {
+        let render_html =
+            blog.needs_render_count() > 0 || force.html || force.tags;
+        // maps and photos are only processed when something is rendered
+        let render_any = render_html || force.maps || force.photos;
+        let mut posts: Vec<PostPlan> = Vec::new();
+
+        for p in blog.posts.values() {
+            let mut plan = PostPlan {
+                path: &p.path,
+                title: &p.title,
+                render: Vec::new(),
+                maps: Vec::new(),
+                resize: Vec::new(),
+            };
+
+            if p.files_changed() {
+                plan.render.push("files changed");
+            }
+            if p.sequence_changed() {
+                plan.render.push("sequence changed");
+            }
+            if force.html {
+                plan.render.push("forced");
+            }
+
+            if render_any {
+                if p.locations_changed() {
+                    plan.maps.push("locations changed");
+                }
+                if p.cover_photo_aspect_ratio_changed() {
+                    plan.maps.push("cover aspect ratio changed");
+                }
+                if force.maps {
+                    plan.maps.push("forced");
+                }
+
+                let folder = root.join(&p.path);
+
+                plan.resize = p
+                    .photos
+                    .iter()
+                    .filter(|photo| {
+                        resize::needs_resize(&folder, p, photo, force.photos)
+                    })
+                    .map(|photo| photo.file.name.as_str())
+                    .collect();
+            }
+
+            if !plan.is_empty() {
+                posts.push(plan);
+            }
+        }
+
+        let tags = if render_html {
+            blog.tags
+                .iter()
+                .filter(|(_, t)| t.changed || force.tags)
+                .map(|(slug, _)| slug.as_str())
+                .collect()
+        } else {
+            Vec::new()
+        };
+
+        Plan {
+            render_html,
+            posts,
+            tags,
+        }
+    }
+
+    /// Print plan beginning with totals followed by work for each post
+    pub fn print(&self) {
+        let count = |f: fn(&PostPlan) -> bool| {
+            self.posts.iter().filter(|p| f(p)).count()
+        };
+        let metric = |count: usize, label: &str| {
+            println!("{}", format!("{:>5} {}", count, label).bold().green())
+        };
+
+        println!("\n{}", "Render plan (nothing will be written)".bold());
+
+        metric(count(|p| !p.render.is_empty()), "posts would be rendered");
+        metric(
+            count(|p| !p.maps.is_empty()),
+            "post maps would be downloaded",
+        );
+        metric(
+            self.posts.iter().map(|p| p.resize.len()).sum(),
+            "photos would be resized",
+        );
+        metric(self.tags.len(), "tag pages would be rendered");
+
+        if !self.render_html {
+            println!(
+                "{:6}{}",
+                "",
+                "Root and category pages are current".cyan()
+            );
+        }
+
+        for p in self.posts.iter() {
+            println!("\n{} └ {}", p.title.yellow(), p.path.cyan());
+
+            if !p.render.is_empty() {
+                println!("{:6}render: {}", "", p.render.join(", "));
+            }
+            if !p.maps.is_empty() {
+                println!("{:6}maps: {}", "", p.maps.join(", "));
+            }
+            if !p.resize.is_empty() {
+                println!("{:6}resize: {}", "", p.resize.join(", "));
+            }
+        }
+
+        if !self.tags.is_empty() {
+            println!("\n{}", "Tag pages".bold());
+            println!("{:6}{}", "", self.tags.join(", "));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Plan, PostPlan};
+    use crate::{
+        config::{Overrides, PhotoConfig, PostLog},
+        models::{Blog, Photo, PhotoFile, Post, SizeCollection},
+        tools::FileStamp,
+    };
+    use std::{env, fs, path::PathBuf};
+
+    fn stamp(digest: &str) -> FileStamp {
+        FileStamp {
+            size: 1,
+            digest: digest.to_owned(),
+            modified: None,
+        }
+    }
+
+    fn photo(name: &str, index: u8) -> Photo {
+        Photo {
+            file: PhotoFile {
+                name: name.to_owned(),
+            },
+            index,
+            size: SizeCollection::from(
+                200,
+                100,
+                index,
+                &PhotoConfig::fixture(),
+            ),
+            ..Photo::default()
+        }
+    }
+
+    /// Blog root with every size of every photo saved
+    fn root(name: &str, blog: &Blog) -> PathBuf {
+        let root = env::temp_dir().join(name);
+        let _ = fs::remove_dir_all(&root);
+
+        for p in blog.posts.values() {
+            let folder = root.join(&p.path);
+            fs::create_dir_all(&folder).unwrap();
+
+            for photo in p.photos.iter() {
+                let s = &photo.size;
+
+                for size in [&s.large, &s.medium, &s.small, &s.thumb].iter() {
+                    fs::write(folder.join(&size.name), "").unwrap();
+                }
+            }
+        }
+        root
+    }
+
+    /// Blog with one unchanged post and one with an edited photo
+    fn blog() -> Blog {
+        let mut blog = Blog::default();
+        let mut unchanged = Post {
+            path: "unchanged".to_owned(),
+            title: "Unchanged".to_owned(),
+            photos: vec![photo("001.jpg", 1)],
+            ..Post::default()
+        };
+        unchanged.files.insert("001.jpg".to_owned(), stamp("a"));
+        unchanged.history = PostLog::from_post(&unchanged);
+
+        let mut edited = Post {
+            path: "edited".to_owned(),
+            title: "Edited".to_owned(),
+            photos: vec![photo("001.jpg", 1), photo("002.jpg", 2)],
+            ..Post::default()
+        };
+        edited.files.insert("001.jpg".to_owned(), stamp("a"));
+        edited.files.insert("002.jpg".to_owned(), stamp("b"));
+        edited.history = PostLog::from_post(&edited);
+        edited.history.files_changed = true;
+        edited.files.insert("002.jpg".to_owned(), stamp("c"));
+
+        blog.add_post(unchanged);
+        blog.add_post(edited);
+        blog
+    }
+
+    #[test]
+    fn changed_post_test() {
+        let blog = blog();
+        let root = root("exifweb-plan-changed-test", &blog);
+        let plan = Plan::new(&root, &blog, &Overrides::default());
+
+        assert!(plan.render_html);
+        assert_eq!(
+            plan.posts,
+            vec![PostPlan {
+                path: "edited",
+                title: "Edited",
+                render: vec!["files changed"],
+                maps: Vec::new(),
+                resize: vec!["002.jpg"],
+            }]
+        );
+
+        // missing sizes are resized even if the photo hasn't changed
+        fs::remove_file(root.join("unchanged/001_t.webp")).unwrap();
+
+        let plan = Plan::new(&root, &blog, &Overrides::default());
+
+        assert_eq!(plan.posts.len(), 2);
+        assert_eq!(plan.posts[1].resize, vec!["001.jpg"]);
+
+        let _ = fs::remove_dir_all(&root);
+    }
+
+    #[test]
+    fn forced_photos_test() {
+        let blog = blog();
+        let force = Overrides {
+            photos: true,
+            ..Overrides::default()
+        };
+        let root = root("exifweb-plan-forced-test", &blog);
+        let plan = Plan::new(&root, &blog, &force);
+
+        assert_eq!(plan.posts.len(), 2);
+        assert_eq!(plan.posts[1].path, "unchanged");
+        assert_eq!(plan.posts[1].resize, vec!["001.jpg"]);
+        assert!(plan.posts[1].render.is_empty());
+
+        let _ = fs::remove_dir_all(&root);
+    }
+}
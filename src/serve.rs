@@ -0,0 +1,373 @@
+//! Serve rendered files over localhost and reload open pages after each render
+
+use colored::*;
+use std::{
+    fs,
+    io::{self, BufRead, BufReader, Write},
+    net::{TcpListener, TcpStream},
+    path::{Component, Path, PathBuf},
+    sync::{Arc, Mutex},
+    thread,
+};
+
+/// Path of the event stream that pages listen to for reload events
+static RELOAD_PATH: &str = "/_reload";
+
+/// Script added to served HTML pages to reload them when an event is received
+static RELOAD_SCRIPT: &str = "<script>new EventSource(\"/_reload\").onmessage=\
+                              function(){location.reload()}</script>";
+
+/// Page served for missing files, rendered by `Writer::error_pages()`
+static NOT_FOUND_PAGE: &str = "404.html";
+
+/// Open event streams to browser pages
+type Clients = Arc<Mutex<Vec<TcpStream>>>;
+
+pub struct Server {
+    clients: Clients,
+}
+
+impl Server {
+    /// Listen on `port` and serve files from `root` on a background thread,
+    /// each connection being handled on its own thread
+    pub fn start(root: &Path, port: u16) -> io::Result<Self> {
+        let listener = TcpListener::bind(("127.0.0.1", port))?;
+        let clients: Clients = Arc::new(Mutex::new(Vec::new()));
+        let root = root.to_path_buf();
+        let server = Server {
+            clients: clients.clone(),
+        };
+
+        thread::spawn(move || {
+            for stream in listener.incoming().filter_map(|s| s.ok()) {
+                let root = root.clone();
+                let clients = clients.clone();
+
+                thread::spawn(move || {
+                    if let Err(e) = respond(&root, stream, &clients) {
+                        println!("   {} {:?}", "Failed to respond".red(), e);
+                    }
+                });
+            }
+        });
+
+        Ok(server)
+    }
+
+    /// Tell open pages to reload, dropping those that have been closed
+    pub fn reload(&self) {
+        let mut clients = self.clients.lock().unwrap();
+
+        clients.retain(|c| {
+            let mut c: &TcpStream = c;
+            c.write_all(b"data: reload\n\n")
+                .and_then(|_| c.flush())
+                .is_ok()
+        });
+    }
+}
+
+/// Read request line and headers from `stream` then write the requested file,
+/// a redirect to add a folder's trailing slash, the not-found page or, for the
+/// reload path, keep the stream open for later events
+fn respond(
+    root: &Path,
+    stream: TcpStream,
+    clients: &Clients,
+) -> io::Result<()> {
+    let mut reader = BufReader::new(stream.try_clone()?);
+    let mut request = String::new();
+    let mut header = String::new();
+
+    reader.read_line(&mut request)?;
+
+    // ignore headers
+    while reader.read_line(&mut header)? > 2 {
+        header.clear();
+    }
+
+    let mut parts = request.split_whitespace();
+    let method = parts.next().unwrap_or_default();
+    let url = parts.next().unwrap_or_default();
+    let mut stream = stream;
+
+    if method != "GET" && method != "HEAD" {
+        return write_response(
+            &mut stream,
+            "405 Method Not Allowed",
+            "",
+            &[],
+            true,
+        );
+    }
+
+    if url == RELOAD_PATH {
+        stream.write_all(
+            b"HTTP/1.1 200 OK\r\nContent-Type: text/event-stream\r\n\
+              Cache-Control: no-cache\r\n\r\n",
+        )?;
+        clients.lock().unwrap().push(stream);
+        return Ok(());
+    }
+
+    if let Some(location) = with_slash(root, url) {
+        return write_redirect(&mut stream, &location);
+    }
+
+    let (status, path) = match resolve(root, url) {
+        Some(path) => ("200 OK", path),
+        _ => ("404 Not Found", root.join(NOT_FOUND_PAGE)),
+    };
+    let mut body = fs::read(&path).unwrap_or_default();
+    let content_type = content_type(&path);
+
+    if content_type.starts_with("text/html") {
+        body = with_reload(body);
+    }
+
+    write_response(&mut stream, status, content_type, &body, method != "HEAD")
+}
+
+/// Write headers describing `body`, followed by the body itself unless only
+/// headers were requested
+fn write_response<W: Write>(
+    stream: &mut W,
+    status: &str,
+    content_type: &str,
+    body: &[u8],
+    include_body: bool,
+) -> io::Result<()> {
+    write!(
+        stream,
+        "HTTP/1.1 {}\r\nContent-Type: {}\r\nContent-Length: {}\r\n\
+         Cache-Control: no-cache\r\nConnection: close\r\n\r\n",
+        status,
+        content_type,
+        body.len()
+    )?;
+    if include_body {
+        stream.write_all(body)?;
+    }
+    stream.flush()
+}
+
+/// Permanently redirect to `location`
+fn write_redirect<W: Write>(stream: &mut W, location: &str) -> io::Result<()> {
+    write!(
+        stream,
+        "HTTP/1.1 301 Moved Permanently\r\nLocation: {}\r\n\
+         Content-Length: 0\r\nConnection: close\r\n\r\n",
+        location
+    )?;
+    stream.flush()
+}
+
+/// URL with a trailing slash added if it's for a folder page without one, so
+/// relative links within the page resolve to the folder rather than its parent
+fn with_slash(root: &Path, url: &str) -> Option<String> {
+    let end = url.find(&['?', '#'][..]).unwrap_or(url.len());
+    let (path, rest) = url.split_at(end);
+
+    if path.ends_with('/') {
+        return None;
+    }
+    resolve(root, path)
+        .filter(|p| p.ends_with("index.html") && !path.ends_with("index.html"))
+        .map(|_| format!("{}/{}", path, rest))
+}
+
+/// File within `root` for a URL path. Folders resolve to their `index.html`
+/// to match the pages written by `Writer::default_page()`. Paths that would
+/// leave `root` aren't resolved.
+fn resolve(root: &Path, url: &str) -> Option<PathBuf> {
+    let url = url.split(&['?', '#'][..]).next().unwrap_or_default();
+    let relative = PathBuf::from(decode(url).trim_start_matches('/'));
+
+    if relative
+        .components()
+        .any(|c| !matches!(c, Component::Normal(_)))
+    {
+        return None;
+    }
+
+    let path = root.join(relative);
+
+    if path.is_dir() {
+        Some(path.join("index.html")).filter(|p| p.is_file())
+    } else if path.is_file() {
+        Some(path)
+    } else {
+        None
+    }
+}
+
+/// Decode percent-encoded URL characters
+fn decode(url: &str) -> String {
+    let bytes = url.as_bytes();
+    let mut decoded: Vec<u8> = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+
+    while i < bytes.len() {
+        let hex = bytes
+            .get(i + 1..i + 3)
+            .and_then(|h| std::str::from_utf8(h).ok())
+            .and_then(|h| u8::from_str_radix(h, 16).ok());
+
+        match (bytes[i], hex) {
+            (b'%', Some(b)) => {
+                decoded.push(b);
+                i += 3;
+            }
+            (b, _) => {
+                decoded.push(b);
+                i += 1;
+            }
+        }
+    }
+
+    String::from_utf8_lossy(&decoded).to_string()
+}
+
+fn content_type(path: &Path) -> &'static str {
+    match path
+        .extension()
+        .and_then(|e| e.to_str())
+        .unwrap_or_default()
+    {
+        "html" => "text/html; charset=utf-8",
+        "css" => "text/css",
+        "js" => "application/javascript",
+        "json" | "geojson" => "application/json",
+        "xml" => "application/xml",
+        "txt" => "text/plain; charset=utf-8",
+        "svg" => "image/svg+xml",
+        "jpg" | "jpeg" => "image/jpeg",
+        "png" => "image/png",
+        "gif" => "image/gif",
+        "webp" => "image/webp",
+        "avif" => "image/avif",
+        "ico" => "image/x-icon",
+        "woff" => "font/woff",
+        "woff2" => "font/woff2",
+        _ => "application/octet-stream",
+    }
+}
+
+/// Add reload script before the closing body tag or, if there isn't one, at
+/// the end of the page
+fn with_reload(html: Vec<u8>) -> Vec<u8> {
+    let text = String::from_utf8_lossy(&html);
+
+    match text.rfind("</body>") {
+        Some(i) => format!("{}{}{}", &text[..i], RELOAD_SCRIPT, &text[i..])
+            .into_bytes(),
+        _ => format!("{}{}", text, RELOAD_SCRIPT).into_bytes(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        content_type, decode, resolve, with_reload, with_slash, write_redirect,
+        write_response, RELOAD_SCRIPT,
+    };
+    use std::{env, fs, path::Path};
+
+    #[test]
+    fn resolve_test() {
+        let root = env::temp_dir().join("exifweb-serve-test");
+        let _ = fs::remove_dir_all(&root);
+
+        fs::create_dir_all(root.join("photo-tag/bird")).unwrap();
+        fs::write(root.join("index.html"), "").unwrap();
+        fs::write(root.join("photo-tag/bird/index.html"), "").unwrap();
+        fs::write(root.join("sitemap.xml"), "").unwrap();
+
+        assert_eq!(resolve(&root, "/"), Some(root.join("index.html")));
+        assert_eq!(
+            resolve(&root, "/photo-tag/bird?from=home"),
+            Some(root.join("photo-tag/bird/index.html"))
+        );
+        assert_eq!(
+            resolve(&root, "/sitemap.xml"),
+            Some(root.join("sitemap.xml"))
+        );
+        assert_eq!(resolve(&root, "/photo-tag"), None);
+        assert_eq!(resolve(&root, "/missing"), None);
+        assert_eq!(resolve(&root, "/photo-tag/../index.html"), None);
+
+        let _ = fs::remove_dir_all(&root);
+    }
+
+    #[test]
+    fn redirect_test() {
+        let root = env::temp_dir().join("exifweb-redirect-test");
+        let _ = fs::remove_dir_all(&root);
+
+        fs::create_dir_all(root.join("post")).unwrap();
+        fs::write(root.join("index.html"), "").unwrap();
+        fs::write(root.join("post/index.html"), "").unwrap();
+        fs::write(root.join("post/001_s.webp"), "").unwrap();
+
+        assert_eq!(with_slash(&root, "/post"), Some("/post/".to_owned()));
+        assert_eq!(
+            with_slash(&root, "/post?from=map"),
+            Some("/post/?from=map".to_owned())
+        );
+        assert_eq!(with_slash(&root, "/post/"), None);
+        assert_eq!(with_slash(&root, "/"), None);
+        assert_eq!(with_slash(&root, "/post/index.html"), None);
+        assert_eq!(with_slash(&root, "/post/001_s.webp"), None);
+        assert_eq!(with_slash(&root, "/missing"), None);
+
+        let mut response: Vec<u8> = Vec::new();
+        write_redirect(&mut response, "/post/").unwrap();
+        let response = String::from_utf8(response).unwrap();
+
+        assert!(response.starts_with("HTTP/1.1 301 Moved Permanently\r\n"));
+        assert!(response.contains("Location: /post/\r\n"));
+
+        let _ = fs::remove_dir_all(&root);
+    }
+
+    #[test]
+    fn content_type_test() {
+        assert_eq!(content_type(Path::new("001_l.avif")), "image/avif");
+        assert_eq!(content_type(Path::new("001_l.webp")), "image/webp");
+    }
+
+    #[test]
+    fn decode_test() {
+        assert_eq!(decode("/my%20post/caf%C3%A9"), "/my post/café");
+        assert_eq!(decode("/100%"), "/100%");
+    }
+
+    #[test]
+    fn reload_script_test() {
+        let html = with_reload(b"<html><body><p/></body></html>".to_vec());
+
+        assert_eq!(
+            String::from_utf8(html).unwrap(),
+            format!("<html><body><p/>{}</body></html>", RELOAD_SCRIPT)
+        );
+    }
+
+    #[test]
+    fn head_response_test() {
+        let mut get: Vec<u8> = Vec::new();
+        let mut head: Vec<u8> = Vec::new();
+
+        write_response(&mut get, "200 OK", "text/plain", b"hello", true)
+            .unwrap();
+        write_response(&mut head, "200 OK", "text/plain", b"hello", false)
+            .unwrap();
+
+        let get = String::from_utf8(get).unwrap();
+        let head = String::from_utf8(head).unwrap();
+
+        // same headers, including the length, but no body
+        assert!(head.contains("Content-Length: 5\r\n"));
+        assert!(head.ends_with("\r\n\r\n"));
+        assert_eq!(get, format!("{}hello", head));
+    }
+}
@@ -4,7 +4,9 @@ use chrono::{DateTime, FixedOffset};
 use hashbrown::HashMap;
 use lazy_static::*;
 use regex::Regex;
-use std::{error, fs, path::Path};
+use serde::{Deserialize, Serialize};
+use sha1::{Digest, Sha1};
+use std::{error, fs, io, path::Path, time::UNIX_EPOCH};
 
 /// Hash represented as vector of string tuples
 pub type Pairs = Vec<(String, String)>;
@@ -60,6 +62,59 @@ pub fn pos_from_name(re: &Regex, name: &str) -> Option<u8> {
     re.captures(name).and_then(|caps| caps[1].parse().ok())
 }
 
+/// Numerator and denominator of rational text like `240/10`. Text without a
+/// slash is treated as a whole number.
+pub fn rational_parts(text: &str) -> Option<(i64, i64)> {
+    let mut parts = text.trim().splitn(2, '/');
+    let num = parts.next()?.trim().parse().ok()?;
+    let denom = match parts.next() {
+        Some(d) => d.trim().parse().ok()?,
+        _ => 1,
+    };
+
+    if denom == 0 {
+        None
+    } else {
+        Some((num, denom))
+    }
+}
+
+/// Decimal value of rational text like `240/10`
+pub fn parse_rational(text: &str) -> Option<f64> {
+    rational_parts(text).map(|(num, denom)| num as f64 / denom as f64)
+}
+
+/// Fraction reduced to lowest terms (`1/500`) or, if it divides evenly, a
+/// whole number
+pub fn reduce_fraction(num: i64, denom: i64) -> Option<String> {
+    if denom == 0 {
+        return None;
+    }
+
+    let divisor = gcd(num.abs(), denom.abs()).max(1);
+    let (num, denom) = (num / divisor, denom / divisor);
+    // keep any negative sign on the numerator
+    let (num, denom) = if denom < 0 {
+        (-num, -denom)
+    } else {
+        (num, denom)
+    };
+
+    if denom == 1 {
+        Some(num.to_string())
+    } else {
+        Some(format!("{}/{}", num, denom))
+    }
+}
+
+fn gcd(a: i64, b: i64) -> i64 {
+    if b == 0 {
+        a
+    } else {
+        gcd(b, a % b)
+    }
+}
+
 /// Convert text to slug (snake-case) format
 pub fn slugify(s: &str) -> String {
     lazy_static! {
@@ -189,6 +244,65 @@ pub fn earliest_photo_date(photos: &[Photo]) -> Option<DateTime<FixedOffset>> {
     }
 }
 
+/// Size and content digest of a file. Unlike modification times, these only
+/// change if the content does, so stamps are equal if they match. The
+/// modification time is kept only to skip hashing unchanged files.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct FileStamp {
+    /// Length in bytes
+    pub size: u64,
+    /// Hex encoded SHA-1 of file content
+    pub digest: String,
+    /// Milliseconds since the Unix epoch that the file was last modified
+    #[serde(default)]
+    pub modified: Option<u64>,
+}
+
+impl PartialEq for FileStamp {
+    fn eq(&self, other: &Self) -> bool {
+        self.size == other.size && self.digest == other.digest
+    }
+}
+
+impl Eq for FileStamp {}
+
+impl FileStamp {
+    /// Read stamp for file at `path` or, if its size and modification time
+    /// match the `known` stamp, reuse that stamp rather than hashing the file
+    pub fn read_known(path: &Path, known: Option<&Self>) -> io::Result<Self> {
+        let metadata = fs::metadata(path)?;
+        let modified = metadata
+            .modified()
+            .ok()
+            .and_then(|t| t.duration_since(UNIX_EPOCH).ok())
+            .map(|d| d.as_millis() as u64);
+
+        if let Some(k) = known {
+            if modified.is_some()
+                && k.modified == modified
+                && k.size == metadata.len()
+            {
+                return Ok(k.clone());
+            }
+        }
+
+        let mut file = fs::File::open(path)?;
+        let mut hasher = Sha1::new();
+        let size = io::copy(&mut file, &mut hasher)?;
+        let digest = hasher
+            .result()
+            .iter()
+            .map(|b| format!("{:02x}", b))
+            .collect();
+
+        Ok(FileStamp {
+            size,
+            digest,
+            modified,
+        })
+    }
+}
+
 /// Write result of `to_string()` closure, printing but otherwise swallowing
 /// any errors that occur
 pub fn write_result<E: error::Error, F: FnOnce() -> Result<String, E>>(
@@ -252,6 +366,67 @@ mod tests {
         }
     }
 
+    #[test]
+    fn rational_test() {
+        assert_eq!(rational_parts("240/10"), Some((240, 10)));
+        assert_eq!(rational_parts(" 200 "), Some((200, 1)));
+        assert_eq!(rational_parts("1/0"), None);
+        assert_eq!(rational_parts("f/2.8"), None);
+
+        assert_eq!(parse_rational("240/10"), Some(24.0));
+        assert_eq!(parse_rational("-1/2"), Some(-0.5));
+
+        assert_eq!(reduce_fraction(10, 100), Some("1/10".to_owned()));
+        assert_eq!(reduce_fraction(0, 6), Some("0".to_owned()));
+        assert_eq!(reduce_fraction(-2, 6), Some("-1/3".to_owned()));
+        assert_eq!(reduce_fraction(2, -6), Some("-1/3".to_owned()));
+        assert_eq!(reduce_fraction(8, 1), Some("8".to_owned()));
+        assert_eq!(reduce_fraction(1, 0), None);
+    }
+
+    #[test]
+    fn file_stamp_test() {
+        let stamp =
+            FileStamp::read_known(Path::new("./src/fixtures/out.xmp"), None)
+                .unwrap();
+
+        assert_eq!(
+            stamp.size,
+            fs::metadata("./src/fixtures/out.xmp").unwrap().len()
+        );
+        assert_eq!(stamp.digest.len(), 40);
+        assert_eq!(
+            stamp,
+            FileStamp::read_known(Path::new("./src/fixtures/out.xmp"), None)
+                .unwrap()
+        );
+        assert_ne!(
+            stamp,
+            FileStamp::read_known(Path::new("./src/fixtures/out.json"), None)
+                .unwrap()
+        );
+        assert!(stamp.modified.is_some());
+
+        // a known stamp with the same size and time is reused without hashing
+        let known = FileStamp {
+            digest: "not hashed".to_owned(),
+            ..stamp.clone()
+        };
+        let path = Path::new("./src/fixtures/out.xmp");
+
+        assert_eq!(
+            FileStamp::read_known(path, Some(&known)).unwrap().digest,
+            "not hashed"
+        );
+
+        let touched = FileStamp {
+            modified: known.modified.map(|m| m - 1),
+            ..known
+        };
+
+        assert_eq!(FileStamp::read_known(path, Some(&touched)).unwrap(), stamp);
+    }
+
     #[test]
     fn path_end_name_test() {
         let path = Path::new("./docs/something/else");
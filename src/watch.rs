@@ -0,0 +1,263 @@
+//! Poll the blog root for changed source files
+
+use crate::{
+    config::{BlogConfig, CONFIG_FILE},
+    gps::{DOWNLOAD_FOLDER, GPX_EXT},
+    image::{resize, xmp::SIDECAR_EXT},
+};
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    fs,
+    path::{Component, Path, PathBuf},
+    thread,
+    time::{Duration, Instant, SystemTime},
+};
+
+/// How often to check for changes
+const POLL: Duration = Duration::from_secs(1);
+
+/// How long files must be unchanged before changes are reported. Lightroom
+/// writes exported photos one at a time so changes come in bursts.
+const QUIET: Duration = Duration::from_secs(3);
+
+/// Size and modified time of a file
+type FileState = (u64, Option<SystemTime>);
+
+/// Changes found within the blog root
+#[derive(Debug, Default, PartialEq)]
+pub struct Changes {
+    /// Names of root folders (posts or series) with changed files
+    pub folders: BTreeSet<String>,
+    /// Whether the root configuration file changed
+    pub config: bool,
+    /// Whether files changed in ignored folders, such as those holding scripts
+    /// or styles
+    pub assets: bool,
+}
+
+impl Changes {
+    pub fn is_empty(&self) -> bool {
+        self.folders.is_empty() && !self.config && !self.assets
+    }
+}
+
+/// Watch photos, XMP sidecars, GPX and configuration files in post and series
+/// folders, the root configuration file and everything within ignored folders.
+/// Templates are compiled into `exifweb` so changing them requires a rebuild.
+pub struct Watcher {
+    root: PathBuf,
+    source_ext: String,
+    /// Root folders that aren't posts
+    asset_folders: Vec<String>,
+    files: BTreeMap<PathBuf, FileState>,
+}
+
+impl Watcher {
+    pub fn new(root: &Path, config: &BlogConfig) -> Self {
+        Watcher::with(
+            root,
+            &config.photo.source_ext,
+            config.ignore_folders.clone(),
+        )
+    }
+
+    fn with(root: &Path, source_ext: &str, asset_folders: Vec<String>) -> Self {
+        let mut watcher = Watcher {
+            root: root.to_path_buf(),
+            source_ext: source_ext.to_string(),
+            asset_folders,
+            files: BTreeMap::new(),
+        };
+        watcher.files = watcher.scan();
+        watcher
+    }
+
+    /// Block until files change then return those changes once files have
+    /// stopped changing for the quiet period
+    pub fn wait(&mut self) -> Changes {
+        let mut changes = Changes::default();
+        let mut last_change = Instant::now();
+
+        loop {
+            thread::sleep(POLL);
+
+            if self.poll(&mut changes) {
+                last_change = Instant::now();
+            } else if !changes.is_empty() && last_change.elapsed() >= QUIET {
+                return changes;
+            }
+        }
+    }
+
+    /// Scan files and add any differences from the previous scan to `changes`.
+    /// Returns whether anything changed.
+    fn poll(&mut self, changes: &mut Changes) -> bool {
+        let files = self.scan();
+
+        if files == self.files {
+            return false;
+        }
+
+        for (path, state) in files.iter() {
+            if self.files.get(path) != Some(state) {
+                // added or modified
+                self.classify(path, changes);
+            }
+        }
+        for path in self.files.keys() {
+            if !files.contains_key(path) {
+                // removed
+                self.classify(path, changes);
+            }
+        }
+
+        self.files = files;
+        true
+    }
+
+    /// Record which part of the blog `path` belongs to
+    fn classify(&self, path: &Path, changes: &mut Changes) {
+        let relative = path.strip_prefix(&self.root).unwrap_or(path);
+        let mut components = relative.components();
+        let first = match components.next() {
+            Some(Component::Normal(name)) => name.to_string_lossy().to_string(),
+            _ => return,
+        };
+
+        if components.next().is_none() {
+            // only configuration is watched in the root
+            changes.config = true;
+        } else if self.asset_folders.contains(&first) {
+            changes.assets = true;
+        } else {
+            changes.folders.insert(first);
+        }
+    }
+
+    /// Size and modified time of each watched file
+    fn scan(&self) -> BTreeMap<PathBuf, FileState> {
+        let mut files = BTreeMap::new();
+        self.scan_dir(&self.root, 0, &mut files);
+        files
+    }
+
+    /// Add watched files in `dir` to `files`. Posts may be in root folders or
+    /// in series folders so only two levels beneath the root are scanned
+    /// except within asset folders.
+    fn scan_dir(
+        &self,
+        dir: &Path,
+        depth: usize,
+        files: &mut BTreeMap<PathBuf, FileState>,
+    ) {
+        let entries = match fs::read_dir(dir) {
+            Ok(entries) => entries,
+            _ => return,
+        };
+
+        for entry in entries.filter_map(|e| e.ok()) {
+            let path = entry.path();
+            let name = entry.file_name().to_string_lossy().to_string();
+            let metadata = match entry.metadata() {
+                Ok(m) => m,
+                _ => continue,
+            };
+            let is_asset = depth == 0 && self.asset_folders.contains(&name);
+
+            if metadata.is_dir() {
+                if name == DOWNLOAD_FOLDER {
+                    // written by render
+                    continue;
+                } else if is_asset {
+                    self.scan_all(&path, files);
+                } else if depth < 2 {
+                    self.scan_dir(&path, depth + 1, files);
+                }
+            } else if name == CONFIG_FILE
+                || (depth > 0
+                    && ((name.ends_with(&self.source_ext)
+                        && !resize::is_resized(&name))
+                        || name.ends_with(SIDECAR_EXT)
+                        || name.ends_with(GPX_EXT)))
+            {
+                files.insert(path, (metadata.len(), metadata.modified().ok()));
+            }
+        }
+    }
+
+    /// Add every file within `dir` to `files`
+    fn scan_all(&self, dir: &Path, files: &mut BTreeMap<PathBuf, FileState>) {
+        let entries = match fs::read_dir(dir) {
+            Ok(entries) => entries,
+            _ => return,
+        };
+
+        for entry in entries.filter_map(|e| e.ok()) {
+            let path = entry.path();
+
+            match entry.metadata() {
+                Ok(m) if m.is_dir() => self.scan_all(&path, files),
+                Ok(m) => {
+                    files.insert(path, (m.len(), m.modified().ok()));
+                }
+                _ => (),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Changes, Watcher};
+    use std::{env, fs, path::PathBuf};
+
+    fn temp_root() -> PathBuf {
+        let root = env::temp_dir().join("exifweb-watch-test");
+        let _ = fs::remove_dir_all(&root);
+
+        for dir in &["post", "series/part-1", "js"] {
+            fs::create_dir_all(root.join(dir)).unwrap();
+        }
+        fs::write(root.join("exifweb.toml"), "").unwrap();
+        fs::write(root.join("post/001.jpg"), "photo").unwrap();
+        fs::write(root.join("series/part-1/001.jpg"), "photo").unwrap();
+        fs::write(root.join("js/post.js"), "").unwrap();
+
+        root
+    }
+
+    #[test]
+    fn poll_test() {
+        let root = temp_root();
+        let mut watcher = Watcher::with(&root, ".jpg", vec!["js".to_owned()]);
+        let mut changes = Changes::default();
+
+        assert!(!watcher.poll(&mut changes));
+
+        // rendered files aren't watched
+        fs::write(root.join("post/index.html"), "").unwrap();
+        fs::write(root.join("index.html"), "").unwrap();
+
+        assert!(!watcher.poll(&mut changes));
+        assert!(changes.is_empty());
+
+        fs::write(root.join("post/001.jpg"), "edited photo").unwrap();
+        fs::write(root.join("series/part-1/002.xmp"), "").unwrap();
+        fs::write(root.join("js/post.js"), "script").unwrap();
+
+        assert!(watcher.poll(&mut changes));
+        assert_eq!(
+            changes.folders.iter().collect::<Vec<_>>(),
+            vec!["post", "series"]
+        );
+        assert!(changes.assets);
+        assert!(!changes.config);
+
+        fs::remove_file(root.join("exifweb.toml")).unwrap();
+
+        assert!(watcher.poll(&mut changes));
+        assert!(changes.config);
+
+        let _ = fs::remove_dir_all(&root);
+    }
+}